@@ -0,0 +1,482 @@
+use crate::auth::{Session, SessionStore};
+use crate::db::Database;
+use crate::models::{FieldChange, LogChainStatus};
+use crate::permissions;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+// Audit trail: commands that change data record the row before and after the
+// change as JSON in `activity_logs.before_data` / `after_data`, so the log no
+// longer depends on the frontend remembering to call `log_activity`.
+//
+// The log is also hash-chained: every row stores the previous row's hash and
+// a SHA-256 over that plus its own content. Rows are never deleted; old rows are
+// moved to `activity_logs_archive` under a seal, keeping the chain intact.
+
+/// Appends a row to `activity_logs`; used by commands that record events themselves.
+pub fn write_activity_log(
+    conn: &Connection,
+    user_id: Option<i64>,
+    username: &str,
+    action: &str,
+    entity_type: &str,
+    entity_id: Option<i64>,
+    description: &str,
+) -> Result<(), String> {
+    insert_log(conn, user_id, username, action, entity_type, entity_id, description, None, None)
+}
+
+/// Records a data change by `session` with before/after snapshots (either may be None
+/// for creates and deletes). The description is derived from the snapshots.
+pub fn record_change(
+    conn: &Connection,
+    session: &Session,
+    action: &str,
+    entity_type: &str,
+    entity_id: Option<i64>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), String> {
+    let description = describe(action, entity_type, entity_id, after.as_ref().or(before.as_ref()));
+    insert_log(
+        conn,
+        Some(session.user_id),
+        &session.username,
+        action,
+        entity_type,
+        entity_id,
+        &description,
+        before.map(|v| v.to_string()),
+        after.map(|v| v.to_string()),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_log(
+    conn: &Connection,
+    user_id: Option<i64>,
+    username: &str,
+    action: &str,
+    entity_type: &str,
+    entity_id: Option<i64>,
+    description: &str,
+    before_data: Option<String>,
+    after_data: Option<String>,
+) -> Result<(), String> {
+    let prev_hash = last_hash(conn)?;
+    conn.execute(
+        "INSERT INTO activity_logs (user_id, username, action, entity_type, entity_id, description, before_data, after_data, prev_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![user_id, username, action, entity_type, entity_id, description, before_data, after_data, prev_hash],
+    ).map_err(|e| e.to_string())?;
+
+    // created_at is filled in by SQLite, so hash the row as stored
+    let id = conn.last_insert_rowid();
+    let row = conn.query_row(&format!("SELECT {} FROM activity_logs WHERE id = ?1", LOG_COLUMNS), params![id], LogRow::from_row)
+        .map_err(|e| e.to_string())?;
+    conn.execute("UPDATE activity_logs SET hash = ?1 WHERE id = ?2", params![row.compute_hash(), id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// --- Hash chain ---
+
+const LOG_COLUMNS: &str =
+    "id, user_id, username, action, entity_type, entity_id, description, created_at, before_data, after_data, prev_hash, hash";
+
+struct LogRow {
+    id: i64,
+    user_id: Option<i64>,
+    username: String,
+    action: String,
+    entity_type: String,
+    entity_id: Option<i64>,
+    description: String,
+    created_at: Option<String>,
+    before_data: Option<String>,
+    after_data: Option<String>,
+    prev_hash: Option<String>,
+    hash: Option<String>,
+}
+
+impl LogRow {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(LogRow {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            username: row.get(2)?,
+            action: row.get(3)?,
+            entity_type: row.get(4)?,
+            entity_id: row.get(5)?,
+            description: row.get(6)?,
+            created_at: row.get(7)?,
+            before_data: row.get(8)?,
+            after_data: row.get(9)?,
+            prev_hash: row.get(10)?,
+            hash: row.get(11)?,
+        })
+    }
+
+    fn compute_hash(&self) -> String {
+        // Serialised as a JSON array so field boundaries are unambiguous
+        let content = json!([
+            self.id, self.prev_hash.as_deref().unwrap_or(""), self.user_id, self.username, self.action,
+            self.entity_type, self.entity_id, self.description, self.created_at, self.before_data, self.after_data,
+        ]);
+        Sha256::digest(content.to_string().as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+fn load_rows(conn: &Connection, table: &str) -> rusqlite::Result<Vec<LogRow>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM {} ORDER BY id", LOG_COLUMNS, table))?;
+    let rows = stmt.query_map([], LogRow::from_row)?;
+    rows.collect()
+}
+
+/// Hash of the newest row, live or archived ("" for an empty log).
+fn last_hash(conn: &Connection) -> Result<String, String> {
+    for table in ["activity_logs", "activity_logs_archive"] {
+        let hash: Option<Option<String>> = conn.query_row(
+            &format!("SELECT hash FROM {} ORDER BY id DESC LIMIT 1", table),
+            [],
+            |row| row.get(0),
+        ).optional().map_err(|e| e.to_string())?;
+        if let Some(hash) = hash {
+            return Ok(hash.unwrap_or_default());
+        }
+    }
+    Ok(String::new())
+}
+
+/// Chains rows written before hashing existed (called from `init_db`).
+pub fn seal_unhashed_rows(conn: &Connection) -> rusqlite::Result<()> {
+    let unhashed: i64 = conn.query_row("SELECT COUNT(*) FROM activity_logs WHERE hash IS NULL", [], |row| row.get(0))?;
+    if unhashed == 0 {
+        return Ok(());
+    }
+
+    let mut prev_hash: Option<String> = None;
+    for mut row in load_rows(conn, "activity_logs")? {
+        if row.hash.is_none() {
+            row.prev_hash = Some(prev_hash.clone().unwrap_or_default());
+            let hash = row.compute_hash();
+            conn.execute(
+                "UPDATE activity_logs SET prev_hash = ?1, hash = ?2 WHERE id = ?3",
+                params![row.prev_hash, hash, row.id],
+            )?;
+            row.hash = Some(hash);
+        }
+        prev_hash = row.hash;
+    }
+    Ok(())
+}
+
+/// Walks the archived rows and then the live rows in id order and reports the first
+/// row whose link or content does not match.
+pub fn verify_chain(conn: &Connection) -> Result<LogChainStatus, String> {
+    let mut expected_prev = String::new();
+    let mut checked_rows = 0;
+
+    for table in ["activity_logs_archive", "activity_logs"] {
+        for row in load_rows(conn, table).map_err(|e| e.to_string())? {
+            let broken = if row.prev_hash.as_deref().unwrap_or("") != expected_prev {
+                Some("Link to the previous entry is broken (an entry was removed or reordered)")
+            } else if row.hash.as_deref() != Some(row.compute_hash().as_str()) {
+                Some("Entry content does not match its hash (the entry was modified)")
+            } else {
+                None
+            };
+            if let Some(reason) = broken {
+                return Ok(LogChainStatus {
+                    valid: false,
+                    checked_rows,
+                    first_broken_id: Some(row.id),
+                    reason: Some(reason.to_string()),
+                });
+            }
+            expected_prev = row.hash.unwrap_or_default();
+            checked_rows += 1;
+        }
+    }
+
+    // Every seal must still account for the rows it archived
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.first_log_id, s.row_count, s.last_hash,
+                (SELECT COUNT(*) FROM activity_logs_archive a WHERE a.seal_id = s.id),
+                (SELECT hash FROM activity_logs_archive a WHERE a.id = s.last_log_id)
+         FROM activity_log_seals s ORDER BY s.id"
+    ).map_err(|e| e.to_string())?;
+    let seals = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i64>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    }).map_err(|e| e.to_string())?;
+    for seal in seals {
+        let (seal_id, first_log_id, row_count, last_hash, archived, archived_last_hash) = seal.map_err(|e| e.to_string())?;
+        if archived != row_count || archived_last_hash.as_deref() != Some(last_hash.as_str()) {
+            return Ok(LogChainStatus {
+                valid: false,
+                checked_rows,
+                first_broken_id: Some(first_log_id),
+                reason: Some(format!("Archive seal #{} no longer matches its archived entries", seal_id)),
+            });
+        }
+    }
+
+    Ok(LogChainStatus { valid: true, checked_rows, first_broken_id: None, reason: None })
+}
+
+/// Moves every live entry up to `up_to_id` into the archive under a new seal and
+/// records the archive itself as a new chained entry. Returns the number of rows moved.
+pub fn archive_logs(conn: &Connection, session: &Session, up_to_id: i64) -> Result<i64, String> {
+    let status = verify_chain(conn)?;
+    if !status.valid {
+        return Err(format!(
+            "Activity log chain is broken at entry #{}; archiving refused",
+            status.first_broken_id.unwrap_or_default()
+        ));
+    }
+
+    let (row_count, first_id, last_id): (i64, Option<i64>, Option<i64>) = conn.query_row(
+        "SELECT COUNT(*), MIN(id), MAX(id) FROM activity_logs WHERE id <= ?1",
+        params![up_to_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).map_err(|e| e.to_string())?;
+    let (Some(first_id), Some(last_id)) = (first_id, last_id) else {
+        return Ok(0);
+    };
+    let last_hash: String = conn.query_row("SELECT hash FROM activity_logs WHERE id = ?1", params![last_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO activity_log_seals (first_log_id, last_log_id, row_count, last_hash, sealed_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![first_id, last_id, row_count, last_hash, session.username],
+    ).map_err(|e| e.to_string())?;
+    let seal_id = conn.last_insert_rowid();
+
+    conn.execute(
+        &format!(
+            "INSERT INTO activity_logs_archive ({cols}, seal_id) SELECT {cols}, ?1 FROM activity_logs WHERE id <= ?2",
+            cols = LOG_COLUMNS
+        ),
+        params![seal_id, last_id],
+    ).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM activity_logs WHERE id <= ?1", params![last_id]).map_err(|e| e.to_string())?;
+
+    write_activity_log(
+        conn,
+        Some(session.user_id),
+        &session.username,
+        "ARCHIVE",
+        "System",
+        Some(seal_id),
+        &format!("Archived {} log entries (#{}-#{}) under seal #{}", row_count, first_id, last_id, seal_id),
+    )?;
+    Ok(row_count)
+}
+
+#[tauri::command]
+pub fn verify_activity_log_chain(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<LogChainStatus, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ACTIVITY_LOG)?;
+    verify_chain(&conn)
+}
+
+/// Archives entries older than `older_than_days` (0 archives everything).
+#[tauri::command]
+pub fn archive_activity_logs(token: String, older_than_days: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::ARCHIVE_ACTIVITY_LOG)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let up_to_id: Option<i64> = tx.query_row(
+        "SELECT MAX(id) FROM activity_logs WHERE created_at <= datetime('now', ?1)",
+        params![format!("-{} days", older_than_days.max(0))],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    let archived = match up_to_id {
+        Some(id) => archive_logs(&tx, &session, id)?,
+        None => 0,
+    };
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(archived)
+}
+
+fn describe(action: &str, entity_type: &str, entity_id: Option<i64>, snapshot: Option<&Value>) -> String {
+    let verb = match action {
+        "CREATE" => "Created",
+        "UPDATE" => "Updated",
+        "DELETE" => "Deleted",
+        "ROLE_CHANGE" => "Changed role of",
+        "SETTINGS" => "Updated",
+        other => other,
+    };
+    let label = snapshot.and_then(|s| {
+        ["product_name", "customer_name", "supplier_name", "username", "category", "name"]
+            .iter()
+            .find_map(|key| s.get(*key).and_then(Value::as_str))
+    });
+    match (entity_id, label) {
+        (Some(id), Some(label)) => format!("{} {} #{} ({})", verb, entity_type, id, label),
+        (Some(id), None) => format!("{} {} #{}", verb, entity_type, id),
+        (None, Some(label)) => format!("{} {} ({})", verb, entity_type, label),
+        (None, None) => format!("{} {}", verb, entity_type),
+    }
+}
+
+// --- Snapshots ---
+
+// Credentials never go into the log: hashed columns are dropped and secret
+// settings (API keys) are masked.
+const OMITTED_COLUMNS: &[&str] = &["password", "pin"];
+const MASKED_SETTING_SUFFIX: &str = "_key";
+
+fn value_to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Value::from(format!("<{} bytes>", b.len())),
+    }
+}
+
+fn query_objects(conn: &Connection, sql: &str, id: i64) -> Result<Vec<Value>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query(params![id]).map_err(|e| e.to_string())?;
+
+    let mut objects = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut object = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            if OMITTED_COLUMNS.contains(&column.as_str()) {
+                continue;
+            }
+            let value = row.get_ref(i).map_err(|e| e.to_string())?;
+            object.insert(column.clone(), value_to_json(value));
+        }
+        objects.push(Value::Object(object));
+    }
+    Ok(objects)
+}
+
+/// Captures a single row as a JSON object, keyed by column name.
+pub fn snapshot(conn: &Connection, table: &str, key_column: &str, id: i64) -> Result<Option<Value>, String> {
+    let sql = format!("SELECT * FROM {} WHERE {} = ?1", table, key_column);
+    Ok(query_objects(conn, &sql, id)?.into_iter().next())
+}
+
+/// Captures a row together with its child rows (e.g. an order and its `order_items`),
+/// which are stored under the child table's name.
+pub fn snapshot_with_items(
+    conn: &Connection,
+    table: &str,
+    key_column: &str,
+    id: i64,
+    item_table: &str,
+    item_key_column: &str,
+) -> Result<Option<Value>, String> {
+    let Some(mut parent) = snapshot(conn, table, key_column, id)? else {
+        return Ok(None);
+    };
+    let sql = format!("SELECT * FROM {} WHERE {} = ?1 ORDER BY rowid", item_table, item_key_column);
+    let items = query_objects(conn, &sql, id)?;
+    if let Value::Object(ref mut object) = parent {
+        object.insert(item_table.to_string(), Value::Array(items));
+    }
+    Ok(Some(parent))
+}
+
+/// Captures the whole key/value settings table as one object.
+pub fn snapshot_settings(conn: &Connection) -> Result<Value, String> {
+    let mut stmt = conn.prepare("SELECT key, value FROM settings ORDER BY key").map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?;
+
+    let mut object = Map::new();
+    for row in rows {
+        let (key, value) = row.map_err(|e| e.to_string())?;
+        let value = if key.ends_with(MASKED_SETTING_SUFFIX) && !value.is_empty() { "********".to_string() } else { value };
+        object.insert(key, Value::from(value));
+    }
+    Ok(Value::Object(object))
+}
+
+// --- Diff ---
+
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&path, child, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, child) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, i), child, out);
+            }
+        }
+        _ => out.push((prefix.to_string(), value.clone())),
+    }
+}
+
+/// Field-level differences between two snapshots. Nested item rows are addressed
+/// as `order_items[0].quantity`.
+pub fn diff(before: Option<&Value>, after: Option<&Value>) -> Vec<FieldChange> {
+    let mut before_fields = Vec::new();
+    let mut after_fields = Vec::new();
+    if let Some(value) = before {
+        flatten("", value, &mut before_fields);
+    }
+    if let Some(value) = after {
+        flatten("", value, &mut after_fields);
+    }
+
+    let mut changes = Vec::new();
+    for (field, old) in &before_fields {
+        let new = after_fields.iter().find(|(f, _)| f == field).map(|(_, v)| v.clone());
+        if new.as_ref() != Some(old) {
+            changes.push(FieldChange { field: field.clone(), before: Some(old.clone()), after: new });
+        }
+    }
+    for (field, new) in &after_fields {
+        if !before_fields.iter().any(|(f, _)| f == field) {
+            changes.push(FieldChange { field: field.clone(), before: None, after: Some(new.clone()) });
+        }
+    }
+    changes
+}
+
+#[tauri::command]
+pub fn get_activity_log_diff(token: String, log_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<FieldChange>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ACTIVITY_LOG)?;
+
+    // Archived entries keep their id, so look there once the live row is gone
+    let (before_data, after_data): (Option<String>, Option<String>) = conn.query_row(
+        "SELECT before_data, after_data FROM activity_logs WHERE id = ?1
+         UNION ALL
+         SELECT before_data, after_data FROM activity_logs_archive WHERE id = ?1
+         LIMIT 1",
+        params![log_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional().map_err(|e| e.to_string())?.ok_or_else(|| "Log entry not found".to_string())?;
+
+    let parse = |data: Option<String>| -> Result<Option<Value>, String> {
+        data.map(|s| serde_json::from_str(&s).map_err(|e| e.to_string())).transpose()
+    };
+    let before = parse(before_data)?;
+    let after = parse(after_data)?;
+    Ok(diff(before.as_ref(), after.as_ref()))
+}
@@ -0,0 +1,326 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use crate::permissions;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Passwords are stored as Argon2id PHC strings ("$argon2id$v=19$...").
+// Rows created before hashing was introduced still hold the raw password;
+// those are accepted once and upgraded by `login`.
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+pub fn is_password_hash(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    if is_password_hash(stored) {
+        match PasswordHash::new(stored) {
+            Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+            Err(_) => false,
+        }
+    } else {
+        // Legacy plaintext row
+        constant_time_eq(password.as_bytes(), stored.as_bytes())
+    }
+}
+
+/// Checks a login password and, on success, rewrites a legacy plaintext row
+/// as an Argon2id hash so it is only ever accepted in plaintext once.
+pub fn verify_and_upgrade(conn: &Connection, user_id: i64, password: &str, stored: &str) -> Result<bool, String> {
+    if !verify_password(password, stored) {
+        return Ok(false);
+    }
+    if !is_password_hash(stored) {
+        let hashed = hash_password(password)?;
+        conn.execute(
+            "UPDATE users SET password = ?1 WHERE id = ?2",
+            params![hashed, user_id],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(true)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub const MIN_PASSWORD_LENGTH: usize = 6;
+
+/// The one password policy, applied wherever a password is set.
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+    Ok(())
+}
+
+/// PINs are 4-8 digits and hashed exactly like passwords.
+pub fn validate_pin(pin: &str) -> Result<(), String> {
+    if pin.len() < 4 || pin.len() > 8 || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err("PIN must be 4 to 8 digits".to_string());
+    }
+    Ok(())
+}
+
+// --- Recovery codes ---
+// One-time codes shown once at setup so an offline shop can regain access if the
+// owner forgets their password. Only Argon2 hashes of the normalised code are kept.
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Generates a code such as `7KQ2M-XH4TP` (ambiguous characters left out).
+pub fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    let chars: String = bytes.iter()
+        .map(|b| RECOVERY_ALPHABET[*b as usize % RECOVERY_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+/// Upper-cases and strips separators so codes can be typed loosely.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+// --- Sessions ---
+// `login` issues an opaque random token that the frontend passes back with every
+// command. Tokens only live in memory, so restarting the app signs everyone out.
+// When `auto_lock_minutes` is set, a session idle for longer than that is locked
+// until someone enters a PIN through `switch_user`.
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub last_active: Instant,
+    pub locked: bool,
+}
+
+pub const SESSION_LOCKED: &str = "Session locked. Enter your PIN to continue";
+
+#[derive(Default)]
+pub struct SessionStore {
+    pub sessions: Mutex<HashMap<String, Session>>,
+    /// Idle minutes before a session locks (0 = never). Mirrors the `auto_lock_minutes` setting.
+    pub auto_lock_minutes: AtomicI64,
+}
+
+impl SessionStore {
+    pub fn issue(&self, user_id: i64, username: &str, role: &str) -> Result<String, String> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        sessions.insert(token.clone(), Session {
+            user_id,
+            username: username.to_string(),
+            role: role.to_string(),
+            last_active: Instant::now(),
+            locked: false,
+        });
+        Ok(token)
+    }
+
+    pub fn revoke(&self, token: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        sessions.remove(token);
+        Ok(())
+    }
+
+    /// Drops every session belonging to a user (e.g. after the account is deleted).
+    pub fn revoke_user(&self, user_id: i64) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        sessions.retain(|_, s| s.user_id != user_id);
+        Ok(())
+    }
+
+    /// Applies a role change to the user's live sessions so it takes effect immediately.
+    pub fn update_role(&self, user_id: i64, role: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        for session in sessions.values_mut().filter(|s| s.user_id == user_id) {
+            session.role = role.to_string();
+        }
+        Ok(())
+    }
+
+    pub fn set_auto_lock_minutes(&self, minutes: i64) {
+        self.auto_lock_minutes.store(minutes.max(0), Ordering::Relaxed);
+    }
+
+    pub fn require_session(&self, token: &str) -> Result<Session, String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        let session = sessions.get_mut(token).ok_or_else(|| "Session expired. Please log in again".to_string())?;
+
+        let minutes = self.auto_lock_minutes.load(Ordering::Relaxed);
+        if minutes > 0 && session.last_active.elapsed() > Duration::from_secs(minutes as u64 * 60) {
+            session.locked = true;
+        }
+        if session.locked {
+            return Err(SESSION_LOCKED.to_string());
+        }
+        session.last_active = Instant::now();
+        Ok(session.clone())
+    }
+
+    /// Locks the session right away (e.g. the operator steps away from the till).
+    pub fn lock(&self, token: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        let session = sessions.get_mut(token).ok_or_else(|| "Session expired. Please log in again".to_string())?;
+        session.locked = true;
+        Ok(())
+    }
+
+    /// Hands an existing (possibly locked) session over to another user, keeping the token.
+    pub fn switch_user(&self, token: &str, user_id: i64, username: &str, role: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|e| e.to_string())?;
+        let session = sessions.get_mut(token).ok_or_else(|| "Session expired. Please log in again".to_string())?;
+        session.user_id = user_id;
+        session.username = username.to_string();
+        session.role = role.to_string();
+        session.last_active = Instant::now();
+        session.locked = false;
+        Ok(())
+    }
+
+    /// Validates the token and checks the user's role against the permission matrix.
+    pub fn require_permission(&self, conn: &Connection, token: &str, permission: &str) -> Result<Session, String> {
+        let session = self.require_session(token)?;
+        if !permissions::has_permission(conn, &session.role, permission)? {
+            return Err("Permission denied".to_string());
+        }
+        Ok(session)
+    }
+}
+
+// --- Login throttling ---
+// Failed attempts are counted per username (known or not). From the second failure
+// on, each attempt pushes `locked_until` out exponentially; after MAX_FAILED_ATTEMPTS
+// the account is locked for LOCKOUT_MINUTES, doubling with every further failure.
+// A successful login or `unlock_user` clears the counter.
+
+const BACKOFF_START: i64 = 2;
+const MAX_BACKOFF_SECONDS: i64 = 60;
+const MAX_FAILED_ATTEMPTS: i64 = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+const MAX_LOCKOUT_MINUTES: i64 = 24 * 60;
+
+/// Returns an error while the username is in backoff or locked out.
+pub fn check_login_allowed(conn: &Connection, username: &str) -> Result<(), String> {
+    let state: Option<(i64, i64)> = conn.query_row(
+        "SELECT failed_count, CAST((julianday(locked_until) - julianday('now')) * 86400 AS INTEGER)
+         FROM login_attempts WHERE username = ?1 AND locked_until > datetime('now')",
+        params![username],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional().map_err(|e| e.to_string())?;
+
+    match state {
+        None => Ok(()),
+        Some((failed, seconds)) if failed >= MAX_FAILED_ATTEMPTS => {
+            let minutes = (seconds + 59) / 60;
+            Err(format!("Account locked after too many failed attempts. Try again in {} minute(s)", minutes.max(1)))
+        }
+        Some((_, seconds)) => Err(format!("Too many failed attempts. Try again in {} second(s)", seconds.max(1))),
+    }
+}
+
+/// Counts a failed attempt and extends the backoff / lockout window.
+pub fn record_login_failure(conn: &Connection, username: &str) -> Result<i64, String> {
+    let failed: i64 = conn.query_row(
+        "SELECT failed_count FROM login_attempts WHERE username = ?1",
+        params![username],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?.unwrap_or(0) + 1;
+
+    let delay_seconds = if failed >= MAX_FAILED_ATTEMPTS {
+        let doublings = (failed - MAX_FAILED_ATTEMPTS).min(10) as u32;
+        (LOCKOUT_MINUTES * 2_i64.pow(doublings)).min(MAX_LOCKOUT_MINUTES) * 60
+    } else if failed >= BACKOFF_START {
+        2_i64.pow((failed - 1) as u32).min(MAX_BACKOFF_SECONDS)
+    } else {
+        0
+    };
+
+    conn.execute(
+        "INSERT INTO login_attempts (username, failed_count, last_failed_at, locked_until)
+         VALUES (?1, ?2, datetime('now'), datetime('now', ?3))
+         ON CONFLICT(username) DO UPDATE SET
+            failed_count = excluded.failed_count,
+            last_failed_at = excluded.last_failed_at,
+            locked_until = excluded.locked_until",
+        params![username, failed, format!("+{} seconds", delay_seconds)],
+    ).map_err(|e| e.to_string())?;
+    Ok(failed)
+}
+
+/// PINs carry no username, so wrong entries are counted against the account that
+/// holds the session they were typed into. Logging in again does not reset the count.
+pub fn pin_throttle_key(username: &str) -> String {
+    format!("{} (pin)", username)
+}
+
+pub fn clear_login_failures(conn: &Connection, username: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM login_attempts WHERE username = ?1", params![username])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users_with(password: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, username TEXT, password TEXT NOT NULL)").unwrap();
+        conn.execute("INSERT INTO users (id, username, password) VALUES (1, 'owner', ?1)", params![password]).unwrap();
+        conn
+    }
+
+    fn stored_password(conn: &Connection) -> String {
+        conn.query_row("SELECT password FROM users WHERE id = 1", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn argon2id_hash_round_trips() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+    }
+
+    #[test]
+    fn plaintext_row_is_upgraded_on_login() {
+        let conn = users_with("secret1");
+        assert!(verify_and_upgrade(&conn, 1, "secret1", "secret1").unwrap());
+
+        let stored = stored_password(&conn);
+        assert!(is_password_hash(&stored));
+        assert!(verify_password("secret1", &stored));
+    }
+
+    #[test]
+    fn plaintext_mismatch_fails() {
+        let conn = users_with("secret1");
+        assert!(!verify_password("secret2", "secret1"));
+        assert!(!verify_and_upgrade(&conn, 1, "secret2", "secret1").unwrap());
+        assert_eq!(stored_password(&conn), "secret1");
+    }
+}
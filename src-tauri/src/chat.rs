@@ -1,3 +1,5 @@
+use crate::auth::SessionStore;
+use crate::permissions;
 use crate::db::Database;
 use tauri::{State, command};
 use serde::{Deserialize, Serialize};
@@ -55,8 +57,9 @@ struct GeminiPart {
 }
 
 #[command]
-pub fn create_conversation(title: String, db: State<Database>) -> Result<i64, String> {
+pub fn create_conversation(token: String, title: String, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::USE_CHAT)?;
     conn.execute(
         "INSERT INTO conversations (title) VALUES (?1)",
         rusqlite::params![title],
@@ -65,8 +68,9 @@ pub fn create_conversation(title: String, db: State<Database>) -> Result<i64, St
 }
 
 #[command]
-pub fn get_conversations(db: State<Database>) -> Result<Vec<Conversation>, String> {
+pub fn get_conversations(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Conversation>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::USE_CHAT)?;
     let mut stmt = conn.prepare("SELECT id, title, created_at FROM conversations ORDER BY updated_at DESC").map_err(|e| e.to_string())?;
     let rows = stmt.query_map([], |row| {
         Ok(Conversation {
@@ -84,8 +88,9 @@ pub fn get_conversations(db: State<Database>) -> Result<Vec<Conversation>, Strin
 }
 
 #[command]
-pub fn get_messages(conversation_id: i64, db: State<Database>) -> Result<Vec<Message>, String> {
+pub fn get_messages(token: String, conversation_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Message>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::USE_CHAT)?;
     let mut stmt = conn.prepare("SELECT id, conversation_id, sender, content, created_at FROM messages WHERE conversation_id = ?1 ORDER BY created_at ASC").map_err(|e| e.to_string())?;
     let rows = stmt.query_map(rusqlite::params![conversation_id], |row| {
         Ok(Message {
@@ -105,18 +110,20 @@ pub fn get_messages(conversation_id: i64, db: State<Database>) -> Result<Vec<Mes
 }
 
 #[command]
-pub fn delete_conversation(conversation_id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_conversation(token: String, conversation_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::USE_CHAT)?;
     conn.execute("DELETE FROM conversations WHERE id = ?1", rusqlite::params![conversation_id]).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[command]
-pub async fn send_chat_message(conversation_id: i64, user_message: String, api_key: String, db: State<'_, Database>) -> Result<Message, String> {
+pub async fn send_chat_message(token: String, conversation_id: i64, user_message: String, api_key: String, db: State<'_, Database>, sessions: State<'_, SessionStore>) -> Result<Message, String> {
     println!("DEBUG: send_chat_message called. conversation_id: {}, user_message: {}, api_key_len: {}", conversation_id, user_message, api_key.len());
     // 1. Save User Message
     {
         let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        sessions.require_permission(&conn, &token, permissions::USE_CHAT)?;
         conn.execute(
             "INSERT INTO messages (conversation_id, sender, content) VALUES (?1, 'user', ?2)",
             rusqlite::params![conversation_id, user_message],
@@ -128,7 +135,7 @@ pub async fn send_chat_message(conversation_id: i64, user_message: String, api_k
 
     // 2. Prepare API Call
     // Fetch full history to send context
-    let history = get_messages(conversation_id, db.clone())?;
+    let history = get_messages(token, conversation_id, db.clone(), sessions.clone())?;
     let mut contents = Vec::new();
     for msg in history {
          contents.push(ChatContent {
@@ -1,12 +1,29 @@
-use crate::models::{Product, Purchase, PurchaseItem, Order, OrderItem, DashboardStats, SalesReportItem, InventoryReportItem, User, Expense};
+use crate::models::{Product, Purchase, PurchaseItem, Order, OrderItem, DashboardStats, SalesReportItem, SalesReturnReportItem, CashierSalesItem, InventoryReportItem, User, AuthSession, AdminSetup, LoginLockout, Expense};
 use crate::db::Database;
+use crate::audit;
+use crate::auth::{self, SessionStore};
+use crate::customers;
+use crate::held_orders;
+use crate::loyalty;
+use crate::order_status;
+use crate::payments;
+use crate::permissions;
+use crate::promotions;
+use crate::purchase_orders;
+use crate::purchase_returns;
+use crate::sales_returns;
+use crate::shifts;
+use crate::stock_policy;
+use crate::suppliers;
+use crate::totals;
 use tauri::{State, AppHandle, Manager};
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use std::collections::HashMap;
 
 #[tauri::command]
-pub fn get_products(db: State<Database>) -> Result<Vec<Product>, String> {
+pub fn get_products(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Product>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PRODUCTS)?;
     
     // Fetch products with the first image if available
     let mut stmt = conn.prepare("
@@ -53,8 +70,9 @@ pub fn get_products(db: State<Database>) -> Result<Vec<Product>, String> {
 }
 
 #[tauri::command]
-pub fn get_product_images(product_id: i64, db: State<Database>) -> Result<Vec<String>, String> {
+pub fn get_product_images(token: String, product_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<String>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PRODUCTS)?;
     
     let mut stmt = conn.prepare("SELECT image_path FROM product_images WHERE product_id = ?1").map_err(|e| e.to_string())?;
     let images_iter = stmt.query_map(params![product_id], |row| {
@@ -71,7 +89,11 @@ pub fn get_product_images(product_id: i64, db: State<Database>) -> Result<Vec<St
 }
 
 #[tauri::command]
-pub fn read_image_base64(path: String, app_handle: tauri::AppHandle) -> Result<String, String> {
+pub fn read_image_base64(token: String, path: String, app_handle: tauri::AppHandle, db: State<Database>, sessions: State<SessionStore>) -> Result<String, String> {
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        sessions.require_permission(&conn, &token, permissions::VIEW_PRODUCTS)?;
+    }
     let app_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
     
     // Determine the actual path to try
@@ -178,8 +200,9 @@ fn save_images(app: &AppHandle, images: Vec<String>) -> Result<Vec<String>, Stri
 }
 
 #[tauri::command]
-pub fn create_product(product: Product, images: Vec<String>, db: State<Database>, app: AppHandle) -> Result<i64, String> {
+pub fn create_product(token: String, product: Product, images: Vec<String>, db: State<Database>, sessions: State<SessionStore>, app: AppHandle) -> Result<i64, String> {
    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+   sessions.require_permission(&conn, &token, permissions::MANAGE_PRODUCTS)?;
    let tx = conn.transaction().map_err(|e| e.to_string())?;
    
    tx.execute(
@@ -222,9 +245,12 @@ pub fn create_product(product: Product, images: Vec<String>, db: State<Database>
 }
 
 #[tauri::command]
-pub fn update_product(product: Product, images: Vec<String>, db: State<Database>, app: AppHandle) -> Result<(), String> {
+pub fn update_product(token: String, product: Product, images: Vec<String>, db: State<Database>, sessions: State<SessionStore>, app: AppHandle) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_PRODUCTS)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let product_id = product.id.ok_or("Product id is required")?;
+    let before = audit::snapshot_with_items(&tx, "products", "id", product_id, "product_images", "product_id")?;
     
     // Update Product Details
     tx.execute(
@@ -266,6 +292,9 @@ pub fn update_product(product: Product, images: Vec<String>, db: State<Database>
             ).map_err(|e| e.to_string())?;
         }
     }
+
+    let after = audit::snapshot_with_items(&tx, "products", "id", product_id, "product_images", "product_id")?;
+    audit::record_change(&tx, &session, "UPDATE", "Product", Some(product_id), before, after)?;
     
     tx.commit().map_err(|e| e.to_string())?;
 
@@ -273,33 +302,54 @@ pub fn update_product(product: Product, images: Vec<String>, db: State<Database>
 }
 
 #[tauri::command]
-pub fn delete_product(id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_product(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_PRODUCTS)?;
+    let before = audit::snapshot(&conn, "products", "id", id)?;
     
     // Soft delete
     conn.execute(
         "UPDATE products SET is_deleted = 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
         params![id],
     ).map_err(|e| e.to_string())?;
+    audit::record_change(&conn, &session, "DELETE", "Product", Some(id), before, None)?;
     
     Ok(())
 }
 
 #[tauri::command]
-pub fn create_purchase(purchase: Purchase, items: Vec<PurchaseItem>, db: State<Database>) -> Result<i64, String> {
+pub fn create_purchase(token: String, mut purchase: Purchase, mut items: Vec<PurchaseItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_PURCHASE)?;
+    totals::purchase(&mut purchase, &mut items)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let purchase_id = post_purchase(&tx, &session, &purchase, &items, None)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(purchase_id)
+}
+
+/// Records a purchase and adds its items to stock at weighted-average cost. Shared by
+/// direct buying and purchase order receipts (`purchase_order_id` links the receipt).
+pub fn post_purchase(tx: &rusqlite::Connection, session: &auth::Session, purchase: &Purchase, items: &[PurchaseItem], purchase_order_id: Option<i64>) -> Result<i64, String> {
+    let supplier_id = suppliers::match_or_create(tx, purchase)?;
+    let (paid_amount, due_amount) = suppliers::payable_amounts(tx, purchase, None, supplier_id)?;
     
     // 1. Insert Purchase
     tx.execute(
-        "INSERT INTO purchases (supplier_name, supplier_phone, invoice_number, purchase_date, total_amount, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO purchases (supplier_name, supplier_phone, invoice_number, purchase_date, total_amount, notes, created_by, created_by_name, supplier_id, paid_amount, due_amount, purchase_order_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
         params![
             purchase.supplier_name,
             purchase.supplier_phone,
             purchase.invoice_number,
             purchase.purchase_date,
             purchase.total_amount,
-            purchase.notes
+            purchase.notes,
+            session.user_id,
+            session.username,
+            supplier_id,
+            paid_amount,
+            due_amount,
+            purchase_order_id
         ],
     ).map_err(|e| e.to_string())?;
     
@@ -351,19 +401,32 @@ pub fn create_purchase(purchase: Purchase, items: Vec<PurchaseItem>, db: State<D
             params![updated_total_quantity, new_average_buying_price, item.product_id],
         ).map_err(|e| e.to_string())?;
     }
+
+    // 4. Settle the due from any return credit the supplier still owes us
+    purchase_returns::apply_open_credit(tx, purchase_id)?;
     
-    tx.commit().map_err(|e| e.to_string())?;
     Ok(purchase_id)
 }
 
 #[tauri::command]
-pub fn create_order(order: Order, items: Vec<OrderItem>, db: State<Database>) -> Result<i64, String> {
+pub fn create_order(token: String, mut order: Order, mut items: Vec<OrderItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_ORDER)?;
+    let approver = order.override_pin.as_deref().map(|pin| approve_by_pin(&conn, &session, pin, permissions::OVERRIDE_STOCK)).transpose()?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let shift_id = shifts::require_open_shift(&tx)?;
+    let promotions = promotions::apply(&tx, &mut order, &mut items, None)?;
+    let tax = totals::order(&tx, &mut order, &mut items)?;
+    let short_stock = stock_policy::check(&tx, &session, approver.as_ref(), &order, &items)?;
+    let customer_id = customers::match_or_create(&tx, &order)?;
+    let points = loyalty::checkout(&tx, &order, customer_id)?;
+    let (paid_amount, due_amount) = customers::checkout_amounts(&tx, &order, None, customer_id, points.value)?;
+    let settlement = payments::settle(&order, paid_amount)?;
+    let status = order_status::initial_status(&order.order_type);
     
     // 1. Insert Order
     tx.execute(
-        "INSERT INTO orders (order_date, order_type, customer_name, customer_phone, customer_address, subtotal, extra_charge, delivery_charge, discount, grand_total, payment_method, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        "INSERT INTO orders (order_date, order_type, customer_name, customer_phone, customer_address, subtotal, extra_charge, delivery_charge, discount, grand_total, payment_method, notes, shift_id, created_by, created_by_name, customer_id, paid_amount, due_amount, points_redeemed, points_value, points_earned, status, change_amount, tax_total, tax_mode, coupon_code, promotion_discount) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27)",
         params![
             order.order_date,
             order.order_type,
@@ -375,15 +438,38 @@ pub fn create_order(order: Order, items: Vec<OrderItem>, db: State<Database>) ->
             order.delivery_charge,
             order.discount,
             order.grand_total,
-            order.payment_method,
-            order.notes
+            settlement.payment_method,
+            order.notes,
+            shift_id,
+            session.user_id,
+            session.username,
+            customer_id,
+            paid_amount,
+            due_amount,
+            points.redeemed,
+            points.value,
+            points.earned,
+            status,
+            settlement.change,
+            tax.total,
+            tax.mode,
+            order.coupon_code,
+            order.promotion_discount
         ],
     ).map_err(|e| e.to_string())?;
     
     let order_id = tx.last_insert_rowid();
+    payments::record(&tx, order_id, &settlement)?;
+    promotions::record(&tx, order_id, &promotions)?;
+    if let Some(approval) = &short_stock {
+        stock_policy::log_approval(&tx, &session, approval, order_id)?;
+    }
+    order_status::record(&tx, &session, order_id, None, status, None)?;
+    held_orders::release(&tx, order.held_order_id)?;
+    loyalty::post_order(&tx, &session, order_id, customer_id, &points)?;
     
     // 2. Insert Items and Update Product
-    for item in items {
+    for (item, line) in items.iter().zip(&tax.lines) {
         // Fetch current buying price for snapshot
         let buying_price: f64 = tx.query_row(
             "SELECT buying_price FROM products WHERE id = ?1",
@@ -392,14 +478,19 @@ pub fn create_order(order: Order, items: Vec<OrderItem>, db: State<Database>) ->
         ).map_err(|e| e.to_string())?;
         
         tx.execute(
-            "INSERT INTO order_items (order_id, product_id, quantity, selling_price, subtotal, buying_price_snapshot) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO order_items (order_id, product_id, quantity, selling_price, subtotal, buying_price_snapshot, tax_rate, taxable_amount, tax_amount, promotion_id, promotion_discount) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             params![
                 order_id,
                 item.product_id,
                 item.quantity,
                 item.selling_price,
                 item.subtotal,
-                buying_price
+                buying_price,
+                line.rate,
+                line.taxable,
+                line.tax,
+                item.promotion_id,
+                item.promotion_discount
             ],
         ).map_err(|e| e.to_string())?;
         
@@ -416,10 +507,11 @@ pub fn create_order(order: Order, items: Vec<OrderItem>, db: State<Database>) ->
 }
 
 #[tauri::command]
-pub fn get_purchases(db: State<Database>) -> Result<Vec<Purchase>, String> {
+pub fn get_purchases(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Purchase>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
     
-    let mut stmt = conn.prepare("SELECT purchase_id, supplier_name, supplier_phone, invoice_number, purchase_date, total_amount, notes, created_at FROM purchases ORDER BY purchase_date DESC").map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare("SELECT purchase_id, supplier_name, supplier_phone, invoice_number, purchase_date, total_amount, notes, created_at, created_by, created_by_name, supplier_id, paid_amount, due_amount, purchase_order_id FROM purchases ORDER BY purchase_date DESC").map_err(|e| e.to_string())?;
     
     let purchases_iter = stmt.query_map([], |row| {
         Ok(Purchase {
@@ -431,6 +523,12 @@ pub fn get_purchases(db: State<Database>) -> Result<Vec<Purchase>, String> {
             total_amount: row.get(5)?,
             notes: row.get(6)?,
             created_at: row.get(7)?,
+            supplier_id: row.get(10)?,
+            paid_amount: row.get(11)?,
+            due_amount: row.get(12)?,
+            purchase_order_id: row.get(13)?,
+            created_by: row.get(8)?,
+            created_by_name: row.get(9)?,
         })
     }).map_err(|e| e.to_string())?;
     
@@ -443,12 +541,13 @@ pub fn get_purchases(db: State<Database>) -> Result<Vec<Purchase>, String> {
 }
 
 #[tauri::command]
-pub fn get_orders(db: State<Database>) -> Result<Vec<Order>, String> {
+pub fn get_orders(token: String, status: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Order>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ORDERS)?;
     
-    let mut stmt = conn.prepare("SELECT order_id, order_date, order_type, customer_name, customer_phone, customer_address, subtotal, extra_charge, delivery_charge, discount, grand_total, payment_method, notes FROM orders ORDER BY order_date DESC").map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare("SELECT order_id, order_date, order_type, customer_name, customer_phone, customer_address, subtotal, extra_charge, delivery_charge, discount, grand_total, payment_method, notes, shift_id, created_by, created_by_name, customer_id, paid_amount, due_amount, points_redeemed, points_value, points_earned, status, change_amount, tax_total, tax_mode, coupon_code, promotion_discount FROM orders WHERE ?1 IS NULL OR status = ?1 ORDER BY order_date DESC").map_err(|e| e.to_string())?;
     
-    let orders_iter = stmt.query_map([], |row| {
+    let orders_iter = stmt.query_map(params![status], |row| {
         Ok(Order {
             order_id: Some(row.get(0)?),
             order_date: row.get(1)?,
@@ -463,6 +562,25 @@ pub fn get_orders(db: State<Database>) -> Result<Vec<Order>, String> {
             grand_total: row.get(10)?,
             payment_method: row.get(11)?,
             notes: row.get(12)?,
+            shift_id: row.get(13)?,
+            created_by: row.get(14)?,
+            created_by_name: row.get(15)?,
+            customer_id: row.get(16)?,
+            paid_amount: row.get(17)?,
+            due_amount: row.get(18)?,
+            points_redeemed: row.get(19)?,
+            points_value: row.get(20)?,
+            points_earned: row.get(21)?,
+            status: row.get(22)?,
+            held_order_id: None,
+            payments: None,
+            change_amount: row.get(23)?,
+            allow_short_stock: None,
+            override_pin: None,
+            tax_total: row.get(24)?,
+            tax_mode: row.get(25)?,
+            coupon_code: row.get(26)?,
+            promotion_discount: row.get(27)?,
         })
     }).map_err(|e| e.to_string())?;
     
@@ -475,14 +593,34 @@ pub fn get_orders(db: State<Database>) -> Result<Vec<Order>, String> {
 }
 
 #[tauri::command]
-pub fn get_dashboard_stats(db: State<Database>) -> Result<DashboardStats, String> {
+pub fn get_dashboard_stats(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<DashboardStats, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    
-    // --- Sales Calculations ---
-    let total_sales: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders", [], |row| row.get(0)).unwrap_or(0.0);
-    let sales_today: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE date(order_date) = date('now', 'localtime')", [], |row| row.get(0)).unwrap_or(0.0);
-    let sales_month: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE strftime('%Y-%m', order_date) = strftime('%Y-%m', 'now', 'localtime')", [], |row| row.get(0)).unwrap_or(0.0);
-    let sales_year: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE strftime('%Y', order_date) = strftime('%Y', 'now', 'localtime')", [], |row| row.get(0)).unwrap_or(0.0);
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_DASHBOARD)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
+    
+    // --- Sales Calculations (cancelled and returned orders are void) ---
+    let total_sales: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE status NOT IN ('cancelled', 'returned')", [], |row| row.get(0)).unwrap_or(0.0);
+    let sales_today: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE date(order_date) = date('now', 'localtime') AND status NOT IN ('cancelled', 'returned')", [], |row| row.get(0)).unwrap_or(0.0);
+    let sales_month: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE strftime('%Y-%m', order_date) = strftime('%Y-%m', 'now', 'localtime') AND status NOT IN ('cancelled', 'returned')", [], |row| row.get(0)).unwrap_or(0.0);
+    let sales_year: f64 = conn.query_row("SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE strftime('%Y', order_date) = strftime('%Y', 'now', 'localtime') AND status NOT IN ('cancelled', 'returned')", [], |row| row.get(0)).unwrap_or(0.0);
+
+    // --- Sales Returns (counted when the goods come back, not against the original sale) ---
+    // Returns the refunded value, the tax refunded with it and the cost of restocked goods for a SQL condition
+    let get_returns = |condition: &str| -> (f64, f64, f64) {
+        let sql = format!("
+            SELECT COALESCE(SUM(ri.subtotal), 0),
+                   COALESCE(SUM(CASE WHEN oi.quantity > 0 THEN ri.quantity * COALESCE(oi.tax_amount, 0) / oi.quantity ELSE 0 END), 0),
+                   COALESCE(SUM(CASE WHEN ri.restock = 1 THEN ri.quantity * ri.cost_snapshot ELSE 0 END), 0)
+            FROM sales_return_items ri
+            JOIN sales_returns r ON ri.return_id = r.id
+            LEFT JOIN order_items oi ON oi.id = ri.order_item_id
+            WHERE {}", condition);
+        conn.query_row(&sql, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap_or((0.0, 0.0, 0.0))
+    };
+    let (total_returns, total_returns_tax, total_restocked) = get_returns("1=1");
+    let (returns_today, returns_tax_today, restocked_today) = get_returns("date(r.return_date) = date('now', 'localtime')");
+    let (returns_month, returns_tax_month, restocked_month) = get_returns("strftime('%Y-%m', r.return_date) = strftime('%Y-%m', 'now', 'localtime')");
+    let (returns_year, returns_tax_year, restocked_year) = get_returns("strftime('%Y', r.return_date) = strftime('%Y', 'now', 'localtime')");
 
     // --- Purchases Calculations ---
     let total_purchases: f64 = conn.query_row("SELECT COALESCE(SUM(total_amount), 0) FROM purchases", [], |row| row.get(0)).unwrap_or(0.0);
@@ -490,14 +628,22 @@ pub fn get_dashboard_stats(db: State<Database>) -> Result<DashboardStats, String
     let purchases_month: f64 = conn.query_row("SELECT COALESCE(SUM(total_amount), 0) FROM purchases WHERE strftime('%Y-%m', purchase_date) = strftime('%Y-%m', 'now', 'localtime')", [], |row| row.get(0)).unwrap_or(0.0);
     let purchases_year: f64 = conn.query_row("SELECT COALESCE(SUM(total_amount), 0) FROM purchases WHERE strftime('%Y', purchase_date) = strftime('%Y', 'now', 'localtime')", [], |row| row.get(0)).unwrap_or(0.0);
 
-    // --- Profit Calculations (Sales - COGS) ---
+    // --- Profit Calculations (Net Sales - Tax - Net COGS) ---
     // Helper to get COGS for a SQL condition
     let get_cogs = |condition: &str| -> f64 {
         let sql = format!("
             SELECT COALESCE(SUM(oi.quantity * oi.buying_price_snapshot), 0) 
             FROM order_items oi 
             JOIN orders o ON oi.order_id = o.order_id 
-            WHERE {}", condition);
+            WHERE o.status NOT IN ('cancelled', 'returned') AND {}", condition);
+        conn.query_row(&sql, [], |row| row.get(0)).unwrap_or(0.0)
+    };
+    // Tax collected is owed to the tax office, so it is not profit
+    let get_tax = |condition: &str| -> f64 {
+        let sql = format!("
+            SELECT COALESCE(SUM(o.tax_total), 0)
+            FROM orders o
+            WHERE o.status NOT IN ('cancelled', 'returned') AND {}", condition);
         conn.query_row(&sql, [], |row| row.get(0)).unwrap_or(0.0)
     };
 
@@ -505,23 +651,38 @@ pub fn get_dashboard_stats(db: State<Database>) -> Result<DashboardStats, String
     let cogs_today = get_cogs("date(o.order_date) = date('now', 'localtime')");
     let cogs_month = get_cogs("strftime('%Y-%m', o.order_date) = strftime('%Y-%m', 'now', 'localtime')");
     let cogs_year  = get_cogs("strftime('%Y', o.order_date) = strftime('%Y', 'now', 'localtime')");
-
-    let total_profit = total_sales - total_cogs;
-    let profit_today = sales_today - cogs_today;
-    let profit_month = sales_month - cogs_month;
-    let profit_year  = sales_year - cogs_year;
+    let total_tax = get_tax("1=1") - total_returns_tax;
+    let tax_today = get_tax("date(o.order_date) = date('now', 'localtime')") - returns_tax_today;
+    let tax_month = get_tax("strftime('%Y-%m', o.order_date) = strftime('%Y-%m', 'now', 'localtime')") - returns_tax_month;
+    let tax_year  = get_tax("strftime('%Y', o.order_date) = strftime('%Y', 'now', 'localtime')") - returns_tax_year;
+
+    // Profit figures are withheld from roles without view_profit (e.g. cashiers)
+    // Restocked goods come back out of COGS; written-off goods stay in it as a loss
+    let profit = |sales: f64, tax: f64, cogs: f64, returns: f64, restocked: f64| {
+        if can_view_profit { Some((sales - returns) - tax - (cogs - restocked)) } else { None }
+    };
+    let total_profit = profit(total_sales, total_tax, total_cogs, total_returns, total_restocked);
+    let profit_today = profit(sales_today, tax_today, cogs_today, returns_today, restocked_today);
+    let profit_month = profit(sales_month, tax_month, cogs_month, returns_month, restocked_month);
+    let profit_year  = profit(sales_year, tax_year, cogs_year, returns_year, restocked_year);
 
     // --- Inventory & Meta ---
     let inventory_value: f64 = conn.query_row("SELECT COALESCE(SUM(stock_quantity * buying_price), 0) FROM products WHERE is_deleted = 0", [], |row| row.get(0)).unwrap_or(0.0);
     let low_stock_count: i64 = conn.query_row("SELECT COUNT(*) FROM products WHERE stock_quantity <= 5 AND is_deleted = 0", [], |row| row.get(0)).unwrap_or(0);
-    let order_count: i64 = conn.query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0)).unwrap_or(0);
+    let order_count: i64 = conn.query_row("SELECT COUNT(*) FROM orders WHERE status NOT IN ('cancelled', 'returned')", [], |row| row.get(0)).unwrap_or(0);
     let product_count: i64 = conn.query_row("SELECT COUNT(*) FROM products WHERE is_deleted = 0", [], |row| row.get(0)).unwrap_or(0);
+    let total_receivables: f64 = conn.query_row("SELECT COALESCE(SUM(due_amount), 0) FROM orders WHERE due_amount > 0", [], |row| row.get(0)).unwrap_or(0.0);
+    let total_payables: f64 = conn.query_row("SELECT COALESCE(SUM(due_amount), 0) FROM purchases WHERE due_amount > 0", [], |row| row.get(0)).unwrap_or(0.0);
     
     Ok(DashboardStats {
         sales_today,
         sales_month,
         sales_year,
         total_sales,
+        returns_today,
+        returns_month,
+        returns_year,
+        total_returns,
         purchases_today,
         purchases_month,
         purchases_year,
@@ -531,6 +692,8 @@ pub fn get_dashboard_stats(db: State<Database>) -> Result<DashboardStats, String
         profit_year,
         total_profit,
         inventory_value,
+        total_receivables,
+        total_payables,
         low_stock_count,
         order_count,
         product_count,
@@ -538,8 +701,10 @@ pub fn get_dashboard_stats(db: State<Database>) -> Result<DashboardStats, String
 }
 
 #[tauri::command]
-pub fn get_sales_report(start_date: String, end_date: String, db: State<Database>) -> Result<Vec<SalesReportItem>, String> {
+pub fn get_sales_report(token: String, start_date: String, end_date: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<SalesReportItem>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
     
     let mut stmt = conn.prepare(
         "SELECT 
@@ -549,9 +714,10 @@ pub fn get_sales_report(start_date: String, end_date: String, db: State<Database
             o.grand_total,
             COALESCE(o.discount, 0),
             COALESCE((SELECT COUNT(*) FROM order_items WHERE order_items.order_id = o.order_id), 0),
-            (o.grand_total - COALESCE((SELECT SUM(quantity * buying_price_snapshot) FROM order_items WHERE order_items.order_id = o.order_id), 0)) as profit 
+            (o.grand_total - COALESCE(o.tax_total, 0) - COALESCE((SELECT SUM(quantity * buying_price_snapshot) FROM order_items WHERE order_items.order_id = o.order_id), 0)) as profit,
+            o.created_by_name
          FROM orders o 
-         WHERE date(o.order_date) BETWEEN date(?1) AND date(?2)
+         WHERE date(o.order_date) BETWEEN date(?1) AND date(?2) AND o.status NOT IN ('cancelled', 'returned')
          ORDER BY o.order_date DESC"
     ).map_err(|e| e.to_string())?;
     
@@ -563,7 +729,8 @@ pub fn get_sales_report(start_date: String, end_date: String, db: State<Database
             total: row.get(3)?,
             discount: row.get(4)?,
             items_count: row.get(5)?,
-            profit: row.get(6)?,
+            profit: if can_view_profit { Some(row.get(6)?) } else { None },
+            cashier: row.get(7)?,
         })
     }).map_err(|e| e.to_string())?;
     
@@ -575,9 +742,100 @@ pub fn get_sales_report(start_date: String, end_date: String, db: State<Database
     Ok(items)
 }
 
+/// Sales returns dated within the range, shown beside `get_sales_report` so gross
+/// sales, returns and net sales can be read separately.
 #[tauri::command]
-pub fn get_inventory_report(db: State<Database>) -> Result<Vec<InventoryReportItem>, String> {
+pub fn get_sales_returns_report(token: String, start_date: String, end_date: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<SalesReturnReportItem>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT
+            r.id,
+            r.order_id,
+            r.return_date,
+            o.customer_name,
+            r.total_amount,
+            r.refund_method,
+            r.reason,
+            (SELECT COUNT(*) FROM sales_return_items WHERE sales_return_items.return_id = r.id),
+            (r.total_amount
+                - COALESCE((SELECT SUM(ri.quantity * oi.tax_amount / oi.quantity) FROM sales_return_items ri
+                            JOIN order_items oi ON oi.id = ri.order_item_id WHERE ri.return_id = r.id AND oi.quantity > 0), 0)
+                - COALESCE((SELECT SUM(quantity * cost_snapshot) FROM sales_return_items WHERE sales_return_items.return_id = r.id AND restock = 1), 0)),
+            r.created_by_name
+         FROM sales_returns r
+         LEFT JOIN orders o ON o.order_id = r.order_id
+         WHERE date(r.return_date) BETWEEN date(?1) AND date(?2)
+         ORDER BY r.return_date DESC"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map(params![start_date, end_date], |row| {
+        Ok(SalesReturnReportItem {
+            return_id: row.get(0)?,
+            order_id: row.get(1)?,
+            date: row.get(2)?,
+            customer: row.get(3)?,
+            total: row.get(4)?,
+            refund_method: row.get(5)?,
+            reason: row.get(6)?,
+            items_count: row.get(7)?,
+            profit: if can_view_profit { Some(row.get(8)?) } else { None },
+            cashier: row.get(9)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+/// Sales totals per user who created the orders, for the same date range as `get_sales_report`.
+#[tauri::command]
+pub fn get_sales_by_cashier(token: String, start_date: String, end_date: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<CashierSalesItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT 
+            o.created_by,
+            o.created_by_name,
+            COUNT(*),
+            COALESCE(SUM(o.grand_total), 0),
+            COALESCE(SUM(o.discount), 0),
+            COALESCE(SUM(o.grand_total - COALESCE(o.tax_total, 0) - COALESCE((SELECT SUM(quantity * buying_price_snapshot) FROM order_items WHERE order_items.order_id = o.order_id), 0)), 0)
+         FROM orders o 
+         WHERE date(o.order_date) BETWEEN date(?1) AND date(?2) AND o.status NOT IN ('cancelled', 'returned')
+         GROUP BY o.created_by, o.created_by_name
+         ORDER BY 4 DESC"
+    ).map_err(|e| e.to_string())?;
+    
+    let rows = stmt.query_map(params![start_date, end_date], |row| {
+        let order_count: i64 = row.get(2)?;
+        let total_sales: f64 = row.get(3)?;
+        Ok(CashierSalesItem {
+            user_id: row.get(0)?,
+            username: row.get(1)?,
+            order_count,
+            total_sales,
+            total_discount: row.get(4)?,
+            average_order: if order_count > 0 { total_sales / order_count as f64 } else { 0.0 },
+            profit: if can_view_profit { Some(row.get(5)?) } else { None },
+        })
+    }).map_err(|e| e.to_string())?;
+    
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(row.map_err(|e| e.to_string())?);
+    }
+    
+    Ok(items)
+}
+
+#[tauri::command]
+pub fn get_inventory_report(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<InventoryReportItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
     
     let mut stmt = conn.prepare(
         "SELECT id, product_name, category, stock_quantity, unit, buying_price, default_selling_price, (stock_quantity * buying_price) as stock_value 
@@ -608,8 +866,9 @@ pub fn get_inventory_report(db: State<Database>) -> Result<Vec<InventoryReportIt
 }
 
 #[tauri::command]
-pub fn backup_db(destination_path: String, app_handle: AppHandle, db: State<Database>) -> Result<(), String> {
+pub fn backup_db(token: String, destination_path: String, app_handle: AppHandle, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_BACKUPS)?;
     
     let path_to_use = if destination_path == "INTERNAL_TEMP" {
         let app_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
@@ -630,7 +889,11 @@ pub fn backup_db(destination_path: String, app_handle: AppHandle, db: State<Data
 }
 
 #[tauri::command]
-pub fn restore_db(source_path: String, app_handle: AppHandle) -> Result<(), String> {
+pub fn restore_db(token: String, source_path: String, app_handle: AppHandle, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        sessions.require_permission(&conn, &token, permissions::RESTORE_BACKUP)?;
+    }
     let app_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
     let restore_path = app_dir.join("restore.db");
     
@@ -644,8 +907,16 @@ pub fn restore_db(source_path: String, app_handle: AppHandle) -> Result<(), Stri
 }
 
 #[tauri::command]
-pub fn list_backups(directory: String) -> Result<Vec<crate::models::BackupInfo>, String> {
-    let path = std::path::Path::new(&directory);
+pub fn list_backups(token: String, directory: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::BackupInfo>, String> {
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        sessions.require_permission(&conn, &token, permissions::VIEW_BACKUPS)?;
+    }
+    collect_backups(&directory)
+}
+
+fn collect_backups(directory: &str) -> Result<Vec<crate::models::BackupInfo>, String> {
+    let path = std::path::Path::new(directory);
     if !path.exists() {
         return Ok(Vec::new());
     }
@@ -677,8 +948,12 @@ pub fn list_backups(directory: String) -> Result<Vec<crate::models::BackupInfo>,
 }
 
 #[tauri::command]
-pub fn prune_backups(directory: String, keep_n: usize) -> Result<(), String> {
-    let mut backups = list_backups(directory.clone())?;
+pub fn prune_backups(token: String, directory: String, keep_n: usize, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        sessions.require_permission(&conn, &token, permissions::MANAGE_BACKUPS)?;
+    }
+    let mut backups = collect_backups(&directory)?;
     if backups.len() > keep_n {
         for backup in backups.drain(keep_n..) {
             let _ = std::fs::remove_file(backup.path);
@@ -689,7 +964,9 @@ pub fn prune_backups(directory: String, keep_n: usize) -> Result<(), String> {
 
 
 #[tauri::command]
-pub fn get_settings(db: State<Database>) -> Result<HashMap<String, String>, String> {
+pub fn get_settings(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<HashMap<String, String>, String> {
+    // Every screen reads shop settings (currency, receipt info), so any session may read them
+    sessions.require_session(&token)?;
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
     
     let mut stmt = conn.prepare("SELECT key, value FROM settings").map_err(|e| e.to_string())?;
@@ -707,27 +984,39 @@ pub fn get_settings(db: State<Database>) -> Result<HashMap<String, String>, Stri
 }
 
 #[tauri::command]
-pub fn update_settings(settings: HashMap<String, String>, db: State<Database>) -> Result<(), String> {
+pub fn update_settings(token: String, settings: HashMap<String, String>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SETTINGS)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let before = audit::snapshot_settings(&tx)?;
     
     for (key, value) in settings {
+        if key == "auto_lock_minutes" {
+            sessions.set_auto_lock_minutes(value.trim().parse().unwrap_or(0));
+        }
         tx.execute(
             "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
             params![key, value],
         ).map_err(|e| e.to_string())?;
     }
+
+    let after = audit::snapshot_settings(&tx)?;
+    if before != after {
+        audit::record_change(&tx, &session, "SETTINGS", "Settings", None, Some(before), Some(after))?;
+    }
     
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_purchase_items(purchase_id: i64, db: State<Database>) -> Result<Vec<crate::models::PurchaseItemDetail>, String> {
+pub fn get_purchase_items(token: String, purchase_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::PurchaseItemDetail>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
     
     let mut stmt = conn.prepare("
-        SELECT pi.id, pi.purchase_id, pi.product_id, p.product_name, pi.quantity, pi.buying_price, pi.extra_charge, pi.subtotal, pi.purchase_unit_cost
+        SELECT pi.id, pi.purchase_id, pi.product_id, p.product_name, pi.quantity, pi.buying_price, pi.extra_charge, pi.subtotal, pi.purchase_unit_cost,
+               (SELECT COALESCE(SUM(ri.quantity), 0) FROM purchase_return_items ri WHERE ri.purchase_item_id = pi.id)
         FROM purchase_items pi
         JOIN products p ON pi.product_id = p.id
         WHERE pi.purchase_id = ?1
@@ -744,6 +1033,7 @@ pub fn get_purchase_items(purchase_id: i64, db: State<Database>) -> Result<Vec<c
             extra_charge: row.get(6)?,
             subtotal: row.get(7)?,
             purchase_unit_cost: row.get(8)?,
+            returned_quantity: Some(row.get(9)?),
         })
     }).map_err(|e| e.to_string())?;
     
@@ -756,15 +1046,33 @@ pub fn get_purchase_items(purchase_id: i64, db: State<Database>) -> Result<Vec<c
 }
 
 #[tauri::command]
-pub fn delete_purchase(purchase_id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_purchase(token: String, purchase_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::DELETE_PURCHASE)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
-    
-    // 1. Get items to revert stock
-    let items: Vec<(i64, f64)> = {
-        let mut stmt = tx.prepare("SELECT product_id, quantity FROM purchase_items WHERE purchase_id = ?1").map_err(|e| e.to_string())?;
+    let before = audit::snapshot_with_items(&tx, "purchases", "purchase_id", purchase_id, "purchase_items", "purchase_id")?;
+    let paid: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM supplier_payment_allocations WHERE purchase_id = ?1",
+        params![purchase_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    if paid > 0 {
+        return Err("Purchase has supplier payments recorded against it and cannot be deleted".to_string());
+    }
+    if purchase_returns::has_returns(&tx, purchase_id)? {
+        return Err("Purchase has goods returned against it and cannot be deleted".to_string());
+    }
+    purchase_orders::reverse_receipt(&tx, purchase_id)?;
+    
+    // 1. Get items to revert stock, at the landed cost they were averaged in at
+    let items: Vec<(i64, f64, f64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT product_id, quantity,
+                    CASE WHEN purchase_unit_cost > 0 THEN purchase_unit_cost ELSE subtotal / quantity END
+             FROM purchase_items WHERE purchase_id = ?1"
+        ).map_err(|e| e.to_string())?;
         let rows = stmt.query_map(params![purchase_id], |row| {
-            Ok((row.get(0)?, row.get(1)?))
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
         }).map_err(|e| e.to_string())?;
         
         let mut result = Vec::new();
@@ -774,28 +1082,44 @@ pub fn delete_purchase(purchase_id: i64, db: State<Database>) -> Result<(), Stri
         result
     };
 
-    // 2. Revert Stock (Subtract what was added)
-    for (product_id, quantity) in items {
-        tx.execute(
-            "UPDATE products SET stock_quantity = stock_quantity - ?1 WHERE id = ?2",
-            params![quantity, product_id],
-        ).map_err(|e| e.to_string())?;
+    // 2. Revert Stock and the moving-average cost the purchase changed
+    for (product_id, quantity, unit_cost) in items {
+        purchase_returns::remove_at_cost(&tx, product_id, quantity, unit_cost)?;
     }
     
     // 3. Delete Items
     tx.execute("DELETE FROM purchase_items WHERE purchase_id = ?1", params![purchase_id]).map_err(|e| e.to_string())?;
     
-    // 4. Delete Purchase
+    // 4. Give applied return credit back, then delete Purchase
+    purchase_returns::release_credit(&tx, purchase_id)?;
     tx.execute("DELETE FROM purchases WHERE purchase_id = ?1", params![purchase_id]).map_err(|e| e.to_string())?;
+
+    audit::record_change(&tx, &session, "DELETE", "Purchase", Some(purchase_id), before, None)?;
     
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_order(order_id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_order(token: String, order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::DELETE_ORDER)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let before = audit::snapshot_with_items(&tx, "orders", "order_id", order_id, "order_items", "order_id")?;
+    let collected: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM customer_payment_allocations WHERE order_id = ?1",
+        params![order_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    if collected > 0 {
+        return Err("Order has account payments recorded against it and cannot be deleted".to_string());
+    }
+    if sales_returns::has_returns(&tx, order_id)? {
+        return Err("Order has goods returned against it and cannot be deleted".to_string());
+    }
+    loyalty::reverse_order(&tx, &session, order_id)?;
+    // Cancelled and returned orders already had their stock put back
+    let void = order_status::is_void(&tx, order_id)?;
     
     // 1. Get items to revert stock
     let items: Vec<(i64, f64)> = {
@@ -812,31 +1136,57 @@ pub fn delete_order(order_id: i64, db: State<Database>) -> Result<(), String> {
     };
 
     // 2. Revert Stock (Add back what was sold)
-    for (product_id, quantity) in items {
-        tx.execute(
-            "UPDATE products SET stock_quantity = stock_quantity + ?1 WHERE id = ?2",
-            params![quantity, product_id],
-        ).map_err(|e| e.to_string())?;
+    if !void {
+        for (product_id, quantity) in items {
+            tx.execute(
+                "UPDATE products SET stock_quantity = stock_quantity + ?1 WHERE id = ?2",
+                params![quantity, product_id],
+            ).map_err(|e| e.to_string())?;
+        }
     }
     
     // 3. Delete Items
     tx.execute("DELETE FROM order_items WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM order_status_history WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM order_payments WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM order_refunds WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM promotion_redemptions WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
     
     // 4. Delete Order
     tx.execute("DELETE FROM orders WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+
+    audit::record_change(&tx, &session, "DELETE", "Order", Some(order_id), before, None)?;
     
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_purchase(purchase_id: i64, purchase: Purchase, items: Vec<PurchaseItem>, db: State<Database>) -> Result<(), String> {
+pub fn update_purchase(token: String, purchase_id: i64, mut purchase: Purchase, mut items: Vec<PurchaseItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::EDIT_PURCHASE)?;
+    totals::purchase(&mut purchase, &mut items)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let before = audit::snapshot_with_items(&tx, "purchases", "purchase_id", purchase_id, "purchase_items", "purchase_id")?;
+    let purchase_order_id: Option<i64> = tx.query_row(
+        "SELECT purchase_order_id FROM purchases WHERE purchase_id = ?1",
+        params![purchase_id],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?.flatten();
+    if let Some(purchase_order_id) = purchase_order_id {
+        return Err(format!("This purchase was received against PO #{}. Delete it and receive the order again instead", purchase_order_id));
+    }
+    if purchase_returns::has_returns(&tx, purchase_id)? {
+        return Err("Purchase has goods returned against it and cannot be edited".to_string());
+    }
     
-    // 1. Get old items to revert stock
+    // 1. Get old items to revert stock, at the landed cost they were averaged in at
     let old_items: Vec<(i64, f64, f64)> = {
-        let mut stmt = tx.prepare("SELECT product_id, quantity, buying_price FROM purchase_items WHERE purchase_id = ?1").map_err(|e| e.to_string())?;
+        let mut stmt = tx.prepare(
+            "SELECT product_id, quantity,
+                    CASE WHEN purchase_unit_cost > 0 THEN purchase_unit_cost ELSE subtotal / quantity END
+             FROM purchase_items WHERE purchase_id = ?1"
+        ).map_err(|e| e.to_string())?;
         let rows = stmt.query_map(params![purchase_id], |row| {
             Ok((row.get(0)?, row.get(1)?, row.get(2)?))
         }).map_err(|e| e.to_string())?;
@@ -848,35 +1198,19 @@ pub fn update_purchase(purchase_id: i64, purchase: Purchase, items: Vec<Purchase
         result
     };
 
-    // 2. Revert Stock and recalculate buying_price
-    for (product_id, quantity, old_item_price) in old_items {
-        let (current_stock, current_buying_price): (f64, f64) = tx.query_row(
-            "SELECT stock_quantity, buying_price FROM products WHERE id = ?1",
-            params![product_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        ).map_err(|e| e.to_string())?;
-
-        let old_total_value = current_stock * current_buying_price;
-        let reverted_stock = current_stock - quantity;
-        
-        let new_buying_price = if reverted_stock > 0.0 {
-            (old_total_value - (quantity * old_item_price)) / reverted_stock
-        } else {
-            0.0
-        };
-
-        tx.execute(
-            "UPDATE products SET stock_quantity = ?1, buying_price = ?2 WHERE id = ?3",
-            params![reverted_stock, new_buying_price, product_id],
-        ).map_err(|e| e.to_string())?;
+    // 2. Revert Stock and the moving-average cost the purchase changed
+    for (product_id, quantity, unit_cost) in old_items {
+        purchase_returns::remove_at_cost(&tx, product_id, quantity, unit_cost)?;
     }
     
     // 3. Delete old items
     tx.execute("DELETE FROM purchase_items WHERE purchase_id = ?1", params![purchase_id]).map_err(|e| e.to_string())?;
     
     // 4. Update Purchase record
+    let supplier_id = suppliers::match_or_create(&tx, &purchase)?;
+    let (paid_amount, due_amount) = suppliers::payable_amounts(&tx, &purchase, Some(purchase_id), supplier_id)?;
     tx.execute(
-        "UPDATE purchases SET supplier_name = ?1, supplier_phone = ?2, invoice_number = ?3, purchase_date = ?4, total_amount = ?5, notes = ?6 WHERE purchase_id = ?7",
+        "UPDATE purchases SET supplier_name = ?1, supplier_phone = ?2, invoice_number = ?3, purchase_date = ?4, total_amount = ?5, notes = ?6, supplier_id = ?7, paid_amount = ?8, due_amount = ?9 WHERE purchase_id = ?10",
         params![
             purchase.supplier_name,
             purchase.supplier_phone,
@@ -884,9 +1218,13 @@ pub fn update_purchase(purchase_id: i64, purchase: Purchase, items: Vec<Purchase
             purchase.purchase_date,
             purchase.total_amount,
             purchase.notes,
+            supplier_id,
+            paid_amount,
+            due_amount,
             purchase_id
         ],
     ).map_err(|e| e.to_string())?;
+    purchase_returns::apply_open_credit(&tx, purchase_id)?;
 
     // 5. Insert new items and applying their stock/cost changes
     for item in items {
@@ -927,14 +1265,28 @@ pub fn update_purchase(purchase_id: i64, purchase: Purchase, items: Vec<Purchase
         ).map_err(|e| e.to_string())?;
     }
 
+    let after = audit::snapshot_with_items(&tx, "purchases", "purchase_id", purchase_id, "purchase_items", "purchase_id")?;
+    audit::record_change(&tx, &session, "UPDATE", "Purchase", Some(purchase_id), before, after)?;
+
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_order(order_id: i64, order: Order, items: Vec<OrderItem>, db: State<Database>) -> Result<(), String> {
+pub fn update_order(token: String, order_id: i64, mut order: Order, mut items: Vec<OrderItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::EDIT_ORDER)?;
+    let approver = order.override_pin.as_deref().map(|pin| approve_by_pin(&conn, &session, pin, permissions::OVERRIDE_STOCK)).transpose()?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let before = audit::snapshot_with_items(&tx, "orders", "order_id", order_id, "order_items", "order_id")?;
+    if sales_returns::has_returns(&tx, order_id)? {
+        return Err("Order has goods returned against it and cannot be edited".to_string());
+    }
+    if order_status::is_void(&tx, order_id)? {
+        return Err("Cancelled or returned orders cannot be edited".to_string());
+    }
+    let promotions = promotions::apply(&tx, &mut order, &mut items, Some(order_id))?;
+    let tax = totals::order(&tx, &mut order, &mut items)?;
     
     // 1. Get old items to revert stock
     let old_items: Vec<(i64, f64)> = {
@@ -960,10 +1312,19 @@ pub fn update_order(order_id: i64, order: Order, items: Vec<OrderItem>, db: Stat
     
     // 3. Delete old items
     tx.execute("DELETE FROM order_items WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    if let Some(approval) = stock_policy::check(&tx, &session, approver.as_ref(), &order, &items)? {
+        stock_policy::log_approval(&tx, &session, &approval, order_id)?;
+    }
     
     // 4. Update Order record
+    let customer_id = customers::match_or_create(&tx, &order)?;
+    loyalty::reverse_order(&tx, &session, order_id)?;
+    let points = loyalty::checkout(&tx, &order, customer_id)?;
+    let (paid_amount, due_amount) = customers::checkout_amounts(&tx, &order, Some(order_id), customer_id, points.value)?;
+    let settlement = payments::settle(&order, paid_amount)?;
+    payments::record(&tx, order_id, &settlement)?;
     tx.execute(
-        "UPDATE orders SET order_date = ?1, order_type = ?2, customer_name = ?3, customer_phone = ?4, customer_address = ?5, subtotal = ?6, extra_charge = ?7, delivery_charge = ?8, discount = ?9, grand_total = ?10, payment_method = ?11, notes = ?12 WHERE order_id = ?13",
+        "UPDATE orders SET order_date = ?1, order_type = ?2, customer_name = ?3, customer_phone = ?4, customer_address = ?5, subtotal = ?6, extra_charge = ?7, delivery_charge = ?8, discount = ?9, grand_total = ?10, payment_method = ?11, notes = ?12, customer_id = ?14, paid_amount = ?15, due_amount = ?16, points_redeemed = ?17, points_value = ?18, points_earned = ?19, change_amount = ?20, tax_total = ?21, tax_mode = ?22, coupon_code = ?23, promotion_discount = ?24 WHERE order_id = ?13",
         params![
             order.order_date,
             order.order_type,
@@ -975,14 +1336,27 @@ pub fn update_order(order_id: i64, order: Order, items: Vec<OrderItem>, db: Stat
             order.delivery_charge,
             order.discount,
             order.grand_total,
-            order.payment_method,
+            settlement.payment_method,
             order.notes,
-            order_id
+            order_id,
+            customer_id,
+            paid_amount,
+            due_amount,
+            points.redeemed,
+            points.value,
+            points.earned,
+            settlement.change,
+            tax.total,
+            tax.mode,
+            order.coupon_code,
+            order.promotion_discount
         ],
     ).map_err(|e| e.to_string())?;
+    loyalty::post_order(&tx, &session, order_id, customer_id, &points)?;
+    promotions::record(&tx, order_id, &promotions)?;
 
     // 5. Insert new items and updating product stock
-    for item in items {
+    for (item, line) in items.iter().zip(&tax.lines) {
         let buying_price: f64 = tx.query_row(
             "SELECT buying_price FROM products WHERE id = ?1",
             params![item.product_id],
@@ -990,14 +1364,19 @@ pub fn update_order(order_id: i64, order: Order, items: Vec<OrderItem>, db: Stat
         ).map_err(|e| e.to_string())?;
         
         tx.execute(
-            "INSERT INTO order_items (order_id, product_id, quantity, selling_price, subtotal, buying_price_snapshot) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO order_items (order_id, product_id, quantity, selling_price, subtotal, buying_price_snapshot, tax_rate, taxable_amount, tax_amount, promotion_id, promotion_discount) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             params![
                 order_id,
                 item.product_id,
                 item.quantity,
                 item.selling_price,
                 item.subtotal,
-                buying_price
+                buying_price,
+                line.rate,
+                line.taxable,
+                line.tax,
+                item.promotion_id,
+                item.promotion_discount
             ],
         ).map_err(|e| e.to_string())?;
         
@@ -1007,18 +1386,25 @@ pub fn update_order(order_id: i64, order: Order, items: Vec<OrderItem>, db: Stat
         ).map_err(|e| e.to_string())?;
     }
     
+    let after = audit::snapshot_with_items(&tx, "orders", "order_id", order_id, "order_items", "order_id")?;
+    audit::record_change(&tx, &session, "UPDATE", "Order", Some(order_id), before, after)?;
+
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_order_items(order_id: i64, db: State<Database>) -> Result<Vec<crate::models::OrderItemDetail>, String> {
+pub fn get_order_items(token: String, order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::OrderItemDetail>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ORDERS)?;
     
     let mut stmt = conn.prepare("
-        SELECT oi.id, oi.order_id, oi.product_id, p.product_name, oi.quantity, oi.selling_price, oi.subtotal 
+        SELECT oi.id, oi.order_id, oi.product_id, p.product_name, oi.quantity, oi.selling_price, oi.subtotal,
+               (SELECT COALESCE(SUM(ri.quantity), 0) FROM sales_return_items ri WHERE ri.order_item_id = oi.id),
+               oi.tax_rate, oi.tax_amount, oi.promotion_id, pr.name, oi.promotion_discount
         FROM order_items oi
         JOIN products p ON oi.product_id = p.id
+        LEFT JOIN promotions pr ON pr.id = oi.promotion_id
         WHERE oi.order_id = ?1
     ").map_err(|e| e.to_string())?;
     
@@ -1031,6 +1417,12 @@ pub fn get_order_items(order_id: i64, db: State<Database>) -> Result<Vec<crate::
             quantity: row.get(4)?,
             selling_price: row.get(5)?,
             subtotal: row.get(6)?,
+            returned_quantity: Some(row.get(7)?),
+            tax_rate: row.get(8)?,
+            tax_amount: row.get(9)?,
+            promotion_id: row.get(10)?,
+            promotion_name: row.get(11)?,
+            promotion_discount: row.get(12)?,
         })
     }).map_err(|e| e.to_string())?;
     
@@ -1044,28 +1436,206 @@ pub fn get_order_items(order_id: i64, db: State<Database>) -> Result<Vec<crate::
 
 
 #[tauri::command]
-pub fn login(username: String, password: String, db: State<Database>) -> Result<User, String> {
+pub fn login(username: String, password: String, db: State<Database>, sessions: State<SessionStore>) -> Result<AuthSession, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    if let Err(e) = auth::check_login_allowed(&conn, &username) {
+        audit::write_activity_log(&conn, None, &username, "LOGIN_FAILED", "System", None, &format!("Blocked login for {}: {}", username, e))?;
+        return Err(e);
+    }
     
-    let mut stmt = conn.prepare("SELECT id, username, role, created_at FROM users WHERE username = ?1 AND password = ?2")
-        .map_err(|e| e.to_string())?;
-    
-    let user = stmt.query_row(params![username, password], |row| {
-        Ok(User {
-            id: Some(row.get(0)?),
-            username: row.get(1)?,
-            password: None,
-            role: row.get(2)?,
-            created_at: Some(row.get(3)?),
-        })
-    }).map_err(|_| "Invalid username or password".to_string())?;
+    let found = conn.query_row(
+        "SELECT id, username, role, created_at, password FROM users WHERE username = ?1",
+        params![username],
+        |row| {
+            Ok((
+                User {
+                    id: Some(row.get(0)?),
+                    username: row.get(1)?,
+                    password: None,
+                    role: row.get(2)?,
+                    created_at: Some(row.get(3)?),
+                },
+                row.get::<_, String>(4)?,
+            ))
+        },
+    ).optional().map_err(|e| e.to_string())?;
+
+    let verified = match &found {
+        Some((user, stored_password)) => auth::verify_and_upgrade(&conn, user.id.unwrap_or_default(), &password, stored_password)?,
+        None => false,
+    };
+
+    let user = match found {
+        Some((user, _)) if verified => user,
+        found => {
+            let user_id = found.and_then(|(user, _)| user.id);
+            let failed = auth::record_login_failure(&conn, &username)?;
+            audit::write_activity_log(&conn, user_id, &username, "LOGIN_FAILED", "System", user_id,
+                &format!("Failed login for {} (attempt {})", username, failed))?;
+            return Err("Invalid username or password".to_string());
+        }
+    };
+
+    auth::clear_login_failures(&conn, &username)?;
+    audit::write_activity_log(&conn, user.id, &user.username, "LOGIN", "System", user.id,
+        &format!("User {} logged in", user.username))?;
+
+    let token = sessions.issue(user.id.unwrap_or_default(), &user.username, &user.role)?;
+    let permissions = permissions::permissions_for_role(&conn, &user.role)?;
     
+    Ok(AuthSession { token, user, permissions })
+}
+
+#[tauri::command]
+pub fn logout(token: String, sessions: State<SessionStore>) -> Result<(), String> {
+    sessions.revoke(&token)
+}
+
+#[tauri::command]
+pub fn get_session_user(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<AuthSession, String> {
+    let session = sessions.require_session(&token)?;
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    let user = conn.query_row(
+        "SELECT id, username, role, created_at FROM users WHERE id = ?1",
+        params![session.user_id],
+        |row| {
+            Ok(User {
+                id: Some(row.get(0)?),
+                username: row.get(1)?,
+                password: None,
+                role: row.get(2)?,
+                created_at: Some(row.get(3)?),
+            })
+        },
+    ).map_err(|e| e.to_string())?;
+    let permissions = permissions::permissions_for_role(&conn, &user.role)?;
+
+    Ok(AuthSession { token, user, permissions })
+}
+
+/// Sets (or with `None` clears) a user's quick-switch PIN. Users may set their own;
+/// user managers may set anyone they are allowed to manage.
+#[tauri::command]
+pub fn set_pin(token: String, user_id: i64, pin: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_session(&token)?;
+    if session.user_id != user_id {
+        sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
+        require_can_manage(&conn, &session, user_id)?;
+    }
+
+    let hashed = match pin {
+        Some(pin) => {
+            auth::validate_pin(&pin)?;
+            // switch_user identifies the operator by PIN alone, so PINs must be unique. A
+            // collision reveals someone else's PIN, so it counts as a failed PIN attempt.
+            let throttle_key = auth::pin_throttle_key(&session.username);
+            auth::check_login_allowed(&conn, &throttle_key)?;
+            if find_user_by_pin(&conn, &pin)?.is_some_and(|user| user.id != Some(user_id)) {
+                let failed = auth::record_login_failure(&conn, &throttle_key)?;
+                audit::write_activity_log(&conn, Some(session.user_id), &throttle_key, "LOGIN_FAILED", "User", Some(user_id),
+                    &format!("Rejected PIN matching another user's (attempt {})", failed))?;
+                return Err("PIN not accepted. Choose a different PIN".to_string());
+            }
+            Some(auth::hash_password(&pin)?)
+        }
+        None => None,
+    };
+
+    conn.execute("UPDATE users SET pin = ?1 WHERE id = ?2", params![hashed, user_id])
+        .map_err(|e| e.to_string())?;
+    let target: String = conn.query_row("SELECT username FROM users WHERE id = ?1", params![user_id], |row| row.get(0))
+        .map_err(|_| "User not found".to_string())?;
+    audit::write_activity_log(&conn, Some(session.user_id), &session.username, "PASSWORD_CHANGE", "User", Some(user_id),
+        &format!("{} PIN for: {}", if hashed.is_some() { "Set" } else { "Cleared" }, target))
+}
+
+/// Changes the operator of the current app session by PIN. Also unlocks a session
+/// that was locked by the auto-lock timeout.
+#[tauri::command]
+pub fn switch_user(token: String, pin: String, db: State<Database>, sessions: State<SessionStore>) -> Result<AuthSession, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    // PINs are short, so they share the login throttle under the session holder's account
+    let previous = sessions.sessions.lock().map_err(|e| e.to_string())?
+        .get(&token).map(|s| s.username.clone())
+        .ok_or_else(|| "Session expired. Please log in again".to_string())?;
+    let throttle_key = auth::pin_throttle_key(&previous);
+    if let Err(e) = auth::check_login_allowed(&conn, &throttle_key) {
+        audit::write_activity_log(&conn, None, &throttle_key, "LOGIN_FAILED", "System", None, &format!("Blocked PIN switch: {}", e))?;
+        return Err(e);
+    }
+
+    let Some(user) = find_user_by_pin(&conn, &pin)? else {
+        let failed = auth::record_login_failure(&conn, &throttle_key)?;
+        audit::write_activity_log(&conn, None, &throttle_key, "LOGIN_FAILED", "System", None,
+            &format!("Invalid PIN entered (attempt {})", failed))?;
+        return Err("Invalid PIN".to_string());
+    };
+
+    auth::clear_login_failures(&conn, &throttle_key)?;
+    sessions.switch_user(&token, user.id.unwrap_or_default(), &user.username, &user.role)?;
+    audit::write_activity_log(&conn, user.id, &user.username, "SWITCH_USER", "System", user.id,
+        &format!("Operator switched from {} to {}", previous, user.username))?;
+
+    let permissions = permissions::permissions_for_role(&conn, &user.role)?;
+    Ok(AuthSession { token, user, permissions })
+}
+
+#[tauri::command]
+pub fn lock_session(token: String, sessions: State<SessionStore>) -> Result<(), String> {
+    sessions.lock(&token)
+}
+
+/// Identifies who is approving something at another user's till by their PIN and
+/// checks they hold `permission`. Wrong PINs count towards the session holder's PIN throttle.
+fn approve_by_pin(conn: &rusqlite::Connection, session: &auth::Session, pin: &str, permission: &str) -> Result<User, String> {
+    let throttle_key = auth::pin_throttle_key(&session.username);
+    auth::check_login_allowed(conn, &throttle_key)?;
+    let Some(user) = find_user_by_pin(conn, pin)? else {
+        let failed = auth::record_login_failure(conn, &throttle_key)?;
+        audit::write_activity_log(conn, None, &throttle_key, "LOGIN_FAILED", "System", None,
+            &format!("Invalid approval PIN entered (attempt {})", failed))?;
+        return Err("Invalid PIN".to_string());
+    };
+    auth::clear_login_failures(conn, &throttle_key)?;
+    if !permissions::has_permission(conn, &user.role, permission)? {
+        return Err(format!("{} is not allowed to approve this", user.username));
+    }
     Ok(user)
 }
 
+fn find_user_by_pin(conn: &rusqlite::Connection, pin: &str) -> Result<Option<User>, String> {
+    let mut stmt = conn.prepare("SELECT id, username, role, created_at, pin FROM users WHERE pin IS NOT NULL")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            User {
+                id: Some(row.get(0)?),
+                username: row.get(1)?,
+                password: None,
+                role: row.get(2)?,
+                created_at: Some(row.get(3)?),
+            },
+            row.get::<_, String>(4)?,
+        ))
+    }).map_err(|e| e.to_string())?;
+
+    for row in rows {
+        let (user, stored_pin) = row.map_err(|e| e.to_string())?;
+        if auth::is_password_hash(&stored_pin) && auth::verify_password(pin, &stored_pin) {
+            return Ok(Some(user));
+        }
+    }
+    Ok(None)
+}
+
 #[tauri::command]
-pub fn get_users(db: State<Database>) -> Result<Vec<User>, String> {
+pub fn get_users(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<User>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
     
     let mut stmt = conn.prepare("SELECT id, username, role, created_at FROM users").map_err(|e| e.to_string())?;
     let user_iter = stmt.query_map([], |row| {
@@ -1087,22 +1657,38 @@ pub fn get_users(db: State<Database>) -> Result<Vec<User>, String> {
 }
 
 #[tauri::command]
-pub fn create_user(user: User, db: State<Database>) -> Result<(), String> {
+pub fn create_user(token: String, user: User, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
+    if user.role == permissions::SUPER_ADMIN && session.role != permissions::SUPER_ADMIN {
+        return Err("Only a super admin can create another super admin".to_string());
+    }
+    require_role_exists(&conn, &user.role)?;
+    let password = user.password.unwrap_or_default();
+    auth::validate_password(&password)?;
     
+    let hashed = auth::hash_password(&password)?;
     conn.execute(
         "INSERT INTO users (username, password, role) VALUES (?1, ?2, ?3)",
-        params![user.username, user.password.unwrap_or_default(), user.role],
+        params![user.username, hashed, user.role],
     ).map_err(|e| e.to_string())?;
     
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_user(id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_user(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
+    if session.user_id == id {
+        return Err("You cannot delete your own account".to_string());
+    }
+    require_can_manage(&conn, &session, id)?;
+    let before = audit::snapshot(&conn, "users", "id", id)?;
     
     conn.execute("DELETE FROM users WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    sessions.revoke_user(id)?;
+    audit::record_change(&conn, &session, "DELETE", "User", Some(id), before, None)?;
     
     Ok(())
 }
@@ -1116,8 +1702,8 @@ pub fn check_setup_required(db: State<Database>) -> Result<bool, String> {
 }
 
 #[tauri::command]
-pub fn setup_admin(username: String, password: String, db: State<Database>) -> Result<User, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+pub fn setup_admin(username: String, password: String, db: State<Database>, sessions: State<SessionStore>) -> Result<AdminSetup, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
     
     // Double-check no users exist
     let count: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
@@ -1126,60 +1712,258 @@ pub fn setup_admin(username: String, password: String, db: State<Database>) -> R
         return Err("Setup has already been completed".to_string());
     }
 
-    conn.execute(
+    auth::validate_password(&password)?;
+    let hashed = auth::hash_password(&password)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
         "INSERT INTO users (username, password, role) VALUES (?1, ?2, 'super_admin')",
-        params![username, password],
+        params![username, hashed],
     ).map_err(|e| e.to_string())?;
 
-    let id = conn.last_insert_rowid();
-    Ok(User {
-        id: Some(id),
-        username,
-        password: None,
-        role: "super_admin".to_string(),
-        created_at: None,
+    let id = tx.last_insert_rowid();
+    let recovery_codes = issue_recovery_codes(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let token = sessions.issue(id, &username, "super_admin")?;
+    Ok(AdminSetup {
+        session: AuthSession {
+            token,
+            user: User {
+                id: Some(id),
+                username,
+                password: None,
+                role: "super_admin".to_string(),
+                created_at: None,
+            },
+            permissions: permissions::permissions_for_role(&conn, "super_admin")?,
+        },
+        recovery_codes,
     })
 }
 
+/// Replaces the user's recovery codes with a fresh set and returns them in clear.
+fn issue_recovery_codes(conn: &rusqlite::Connection, user_id: i64) -> Result<Vec<String>, String> {
+    conn.execute("DELETE FROM recovery_codes WHERE user_id = ?1", params![user_id])
+        .map_err(|e| e.to_string())?;
+
+    let mut codes = Vec::with_capacity(auth::RECOVERY_CODE_COUNT);
+    for _ in 0..auth::RECOVERY_CODE_COUNT {
+        let code = auth::generate_recovery_code();
+        conn.execute(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?1, ?2)",
+            params![user_id, auth::hash_password(&auth::normalize_recovery_code(&code))?],
+        ).map_err(|e| e.to_string())?;
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+/// Lets a super admin replace their recovery codes (e.g. after using some).
 #[tauri::command]
-pub fn change_password(user_id: i64, current_password: String, new_password: String, is_super_admin: bool, db: State<Database>) -> Result<(), String> {
+pub fn regenerate_recovery_codes(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<String>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_session(&token)?;
+    if session.role != permissions::SUPER_ADMIN {
+        return Err("Only a super admin has recovery codes".to_string());
+    }
+
+    let codes = issue_recovery_codes(&conn, session.user_id)?;
+    audit::write_activity_log(&conn, Some(session.user_id), &session.username, "RECOVERY", "User", Some(session.user_id),
+        "Regenerated account recovery codes")?;
+    Ok(codes)
+}
+
+/// Resets a password with a one-time recovery code. The code is consumed and the
+/// user's existing sessions are signed out. Returns the recovered username.
+#[tauri::command]
+pub fn recover_account(code: String, new_password: String, db: State<Database>, sessions: State<SessionStore>) -> Result<String, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    auth::validate_password(&new_password)?;
+
+    // Recovery attempts share the login throttle under a fixed key
+    if let Err(e) = auth::check_login_allowed(&conn, RECOVERY_THROTTLE_KEY) {
+        audit::write_activity_log(&conn, None, RECOVERY_THROTTLE_KEY, "LOGIN_FAILED", "System", None, &format!("Blocked account recovery: {}", e))?;
+        return Err(e);
+    }
+
+    let normalized = auth::normalize_recovery_code(&code);
+    let matched = {
+        let mut stmt = conn.prepare(
+            "SELECT rc.id, rc.code_hash, u.id, u.username FROM recovery_codes rc
+             JOIN users u ON rc.user_id = u.id WHERE rc.used_at IS NULL"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+        }).map_err(|e| e.to_string())?;
+
+        let mut matched = None;
+        for row in rows {
+            let (code_id, code_hash, user_id, username) = row.map_err(|e| e.to_string())?;
+            if auth::verify_password(&normalized, &code_hash) {
+                matched = Some((code_id, user_id, username));
+                break;
+            }
+        }
+        matched
+    };
+
+    let Some((code_id, user_id, username)) = matched else {
+        let failed = auth::record_login_failure(&conn, RECOVERY_THROTTLE_KEY)?;
+        audit::write_activity_log(&conn, None, RECOVERY_THROTTLE_KEY, "LOGIN_FAILED", "System", None,
+            &format!("Invalid recovery code entered (attempt {})", failed))?;
+        return Err("Invalid or already used recovery code".to_string());
+    };
 
-    if !is_super_admin {
+    // Burning the code and setting the password succeed or fail together
+    let hashed = auth::hash_password(&new_password)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute("UPDATE recovery_codes SET used_at = CURRENT_TIMESTAMP WHERE id = ?1", params![code_id])
+        .map_err(|e| e.to_string())?;
+    tx.execute("UPDATE users SET password = ?1 WHERE id = ?2", params![hashed, user_id])
+        .map_err(|e| e.to_string())?;
+    auth::clear_login_failures(&tx, RECOVERY_THROTTLE_KEY)?;
+    auth::clear_login_failures(&tx, &username)?;
+
+    let remaining: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1 AND used_at IS NULL",
+        params![user_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    audit::write_activity_log(&tx, Some(user_id), &username, "RECOVERY", "User", Some(user_id),
+        &format!("Password reset with a recovery code ({} codes left)", remaining))?;
+    tx.commit().map_err(|e| e.to_string())?;
+    sessions.revoke_user(user_id)?;
+
+    Ok(username)
+}
+
+const RECOVERY_THROTTLE_KEY: &str = "(recovery)";
+
+#[tauri::command]
+pub fn change_password(token: String, user_id: i64, current_password: String, new_password: String, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let session = sessions.require_session(&token)?;
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    // Users changing their own password must prove they know the current one.
+    // Resetting someone else's password requires the manage_users permission.
+    if session.user_id != user_id {
+        if !permissions::has_permission(&conn, &session.role, permissions::MANAGE_USERS)? {
+            return Err("Permission denied".to_string());
+        }
+        require_can_manage(&conn, &session, user_id)?;
+    } else {
         // Verify current password
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM users WHERE id = ?1 AND password = ?2",
-            params![user_id, current_password],
+        let stored_password: String = conn.query_row(
+            "SELECT password FROM users WHERE id = ?1",
+            params![user_id],
             |row| row.get(0),
-        ).map_err(|e| e.to_string())?;
-        if count == 0 {
+        ).map_err(|_| "User not found".to_string())?;
+        if !auth::verify_password(&current_password, &stored_password) {
             return Err("Current password is incorrect".to_string());
         }
     }
 
+    auth::validate_password(&new_password)?;
+    let hashed = auth::hash_password(&new_password)?;
     conn.execute(
         "UPDATE users SET password = ?1 WHERE id = ?2",
-        params![new_password, user_id],
+        params![hashed, user_id],
     ).map_err(|e| e.to_string())?;
-
-    Ok(())
+    let target: String = conn.query_row("SELECT username FROM users WHERE id = ?1", params![user_id], |row| row.get(0))
+        .map_err(|_| "User not found".to_string())?;
+    audit::write_activity_log(&conn, Some(session.user_id), &session.username, "PASSWORD_CHANGE", "User", Some(user_id),
+        &format!("Password changed for: {}", target))
 }
 
 #[tauri::command]
-pub fn update_user_role(user_id: i64, new_role: String, db: State<Database>) -> Result<(), String> {
+pub fn update_user_role(token: String, user_id: i64, new_role: String, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
+    if new_role == permissions::SUPER_ADMIN && session.role != permissions::SUPER_ADMIN {
+        return Err("Only a super admin can grant the super admin role".to_string());
+    }
+    require_role_exists(&conn, &new_role)?;
+    require_can_manage(&conn, &session, user_id)?;
+    let before = audit::snapshot(&conn, "users", "id", user_id)?;
 
     conn.execute(
         "UPDATE users SET role = ?1 WHERE id = ?2",
         params![new_role, user_id],
     ).map_err(|e| e.to_string())?;
+    sessions.update_role(user_id, &new_role)?;
+    let after = audit::snapshot(&conn, "users", "id", user_id)?;
+    audit::record_change(&conn, &session, "ROLE_CHANGE", "User", Some(user_id), before, after)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_product_purchase_history(product_id: i64, db: State<Database>) -> Result<Vec<crate::models::ProductPurchaseHistory>, String> {
+pub fn get_locked_accounts(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<LoginLockout>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_USERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT username, failed_count, last_failed_at, locked_until FROM login_attempts
+         WHERE locked_until > datetime('now') ORDER BY locked_until DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok(LoginLockout {
+            username: row.get(0)?,
+            failed_count: row.get(1)?,
+            last_failed_at: row.get(2)?,
+            locked_until: row.get(3)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut lockouts = Vec::new();
+    for row in rows {
+        lockouts.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(lockouts)
+}
+
+#[tauri::command]
+pub fn unlock_user(token: String, username: String, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_session(&token)?;
+    if session.role != permissions::SUPER_ADMIN {
+        return Err("Only a super admin can unlock accounts".to_string());
+    }
+
+    auth::clear_login_failures(&conn, &username)?;
+    auth::clear_login_failures(&conn, &auth::pin_throttle_key(&username))?;
+    audit::write_activity_log(&conn, Some(session.user_id), &session.username, "UNLOCK", "User", None,
+        &format!("Unlocked login for {}", username))
+}
+
+// Admins may manage regular staff, but super admin accounts can only be
+// changed by another super admin.
+fn require_can_manage(conn: &rusqlite::Connection, session: &auth::Session, target_user_id: i64) -> Result<(), String> {
+    let target_role: String = conn.query_row(
+        "SELECT role FROM users WHERE id = ?1",
+        params![target_user_id],
+        |row| row.get(0),
+    ).map_err(|_| "User not found".to_string())?;
+
+    if target_role == permissions::SUPER_ADMIN && session.role != permissions::SUPER_ADMIN {
+        return Err("Permission denied".to_string());
+    }
+    Ok(())
+}
+
+fn require_role_exists(conn: &rusqlite::Connection, role: &str) -> Result<(), String> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM roles WHERE name = ?1", params![role], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if count == 0 {
+        return Err(format!("Unknown role: {}", role));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_product_purchase_history(token: String, product_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::ProductPurchaseHistory>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
     
     let mut stmt = conn.prepare("
         SELECT p.purchase_date, p.supplier_name, p.invoice_number, pi.quantity, pi.buying_price, pi.extra_charge, pi.subtotal, pi.purchase_unit_cost
@@ -1210,8 +1994,9 @@ pub fn get_product_purchase_history(product_id: i64, db: State<Database>) -> Res
     Ok(history)
 }
 #[tauri::command]
-pub fn get_product_stock_history(product_id: i64, db: State<Database>) -> Result<Vec<crate::models::StockMovement>, String> {
+pub fn get_product_stock_history(token: String, product_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::StockMovement>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PRODUCTS)?;
     
     let mut movements = Vec::new();
 
@@ -1261,35 +2046,89 @@ pub fn get_product_stock_history(product_id: i64, db: State<Database>) -> Result
         movements.push(m.map_err(|e| e.to_string())?);
     }
 
+    // 3. Fetch Returns to Supplier (OUT)
+    let mut stmt_returns = conn.prepare("
+        SELECT r.return_date, s.supplier_name, r.id, ri.quantity, ri.unit_cost
+        FROM purchase_return_items ri
+        JOIN purchase_returns r ON ri.return_id = r.id
+        LEFT JOIN suppliers s ON r.supplier_id = s.id
+        WHERE ri.product_id = ?1
+    ").map_err(|e| e.to_string())?;
+
+    let return_rows = stmt_returns.query_map(params![product_id], |row| {
+        Ok(crate::models::StockMovement {
+            date: row.get(0)?,
+            movement_type: "OUT".to_string(),
+            entity_name: row.get(1)?,
+            reference: Some(format!("Return #{}", row.get::<_, i64>(2)?)),
+            quantity: row.get(3)?,
+            price: row.get(4)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    for m in return_rows {
+        movements.push(m.map_err(|e| e.to_string())?);
+    }
+
+    // 4. Fetch Customer Returns put back on the shelf (IN)
+    let mut stmt_sales_returns = conn.prepare("
+        SELECT r.return_date, o.customer_name, r.id, ri.quantity, ri.unit_price
+        FROM sales_return_items ri
+        JOIN sales_returns r ON ri.return_id = r.id
+        LEFT JOIN orders o ON r.order_id = o.order_id
+        WHERE ri.product_id = ?1 AND ri.restock = 1
+    ").map_err(|e| e.to_string())?;
+
+    let sales_return_rows = stmt_sales_returns.query_map(params![product_id], |row| {
+        Ok(crate::models::StockMovement {
+            date: row.get(0)?,
+            movement_type: "IN".to_string(),
+            entity_name: row.get(1)?,
+            reference: Some(format!("Sales Return #{}", row.get::<_, i64>(2)?)),
+            quantity: row.get(3)?,
+            price: row.get(4)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    for m in sales_return_rows {
+        movements.push(m.map_err(|e| e.to_string())?);
+    }
+
     // Sort by date DESC
     movements.sort_by(|a, b| b.date.cmp(&a.date));
 
     Ok(movements)
 }
 
+// Actions the frontend may report itself. Security events (logins, PIN switches,
+// stock overrides, audited changes) are only ever written by the backend.
+const CLIENT_LOG_ACTIONS: &[&str] = &["CREATE", "BACKUP", "RESTORE", "CLEANUP"];
+
 #[tauri::command]
 pub fn log_activity(
-    user_id: Option<i64>,
-    username: String,
+    token: String,
     action: String,
     entity_type: String,
     entity_id: Option<i64>,
     description: String,
-    db: State<Database>
+    db: State<Database>,
+    sessions: State<SessionStore>
 ) -> Result<(), String> {
+    // The acting user always comes from the session, never from the caller
+    let session = sessions.require_session(&token)?;
+    if !CLIENT_LOG_ACTIONS.contains(&action.as_str()) {
+        return Err(format!("Action {} cannot be logged from the app", action));
+    }
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO activity_logs (user_id, username, action, entity_type, entity_id, description) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![user_id, username, action, entity_type, entity_id, description],
-    ).map_err(|e| e.to_string())?;
-    Ok(())
+    audit::write_activity_log(&conn, Some(session.user_id), &session.username, &action, &entity_type, entity_id, &description)
 }
 
 #[tauri::command]
-pub fn get_activity_logs(limit: i64, offset: i64, db: State<Database>) -> Result<Vec<crate::models::ActivityLog>, String> {
+pub fn get_activity_logs(token: String, limit: i64, offset: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<crate::models::ActivityLog>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ACTIVITY_LOG)?;
     let mut stmt = conn.prepare(
-        "SELECT id, user_id, username, action, entity_type, entity_id, description, created_at FROM activity_logs ORDER BY created_at DESC LIMIT ?1 OFFSET ?2"
+        "SELECT id, user_id, username, action, entity_type, entity_id, description, created_at, before_data, after_data FROM activity_logs ORDER BY created_at DESC LIMIT ?1 OFFSET ?2"
     ).map_err(|e| e.to_string())?;
 
     let rows = stmt.query_map(params![limit, offset], |row| {
@@ -1302,6 +2141,8 @@ pub fn get_activity_logs(limit: i64, offset: i64, db: State<Database>) -> Result
             entity_id: row.get(5)?,
             description: row.get(6)?,
             created_at: Some(row.get(7)?),
+            before_data: row.get(8)?,
+            after_data: row.get(9)?,
         })
     }).map_err(|e| e.to_string())?;
 
@@ -1312,37 +2153,24 @@ pub fn get_activity_logs(limit: i64, offset: i64, db: State<Database>) -> Result
     Ok(logs)
 }
 
-#[tauri::command]
-pub fn delete_activity_logs(ids: Vec<i64>, db: State<Database>) -> Result<(), String> {
-    if ids.is_empty() {
-        return Ok(());
-    }
-    
-    let conn = db.conn.lock().unwrap();
-    let id_list: String = ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(",");
-    let query = format!("DELETE FROM activity_logs WHERE id IN ({})", id_list);
-    
-    conn.execute(&query, []).map_err(|e| e.to_string())?;
-    
-    Ok(())
-}
-
 // --- Expenses ---
 #[tauri::command]
-pub fn create_expense(expense: Expense, db: State<Database>) -> Result<(), String> {
+pub fn create_expense(token: String, expense: Expense, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_EXPENSES)?;
     conn.execute(
-        "INSERT INTO expenses (expense_date, category, amount, notes) VALUES (?1, ?2, ?3, ?4)",
-        params![expense.expense_date, expense.category, expense.amount, expense.notes],
+        "INSERT INTO expenses (expense_date, category, amount, notes, created_by, created_by_name) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![expense.expense_date, expense.category, expense.amount, expense.notes, session.user_id, session.username],
     ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_expenses(start_date: Option<String>, end_date: Option<String>, db: State<Database>) -> Result<Vec<Expense>, String> {
+pub fn get_expenses(token: String, start_date: Option<String>, end_date: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Expense>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_EXPENSES)?;
     
-    let mut query = "SELECT id, expense_date, category, amount, notes, created_at FROM expenses".to_string();
+    let mut query = "SELECT id, expense_date, category, amount, notes, created_at, created_by, created_by_name FROM expenses".to_string();
     
     if start_date.is_some() && end_date.is_some() {
         query.push_str(" WHERE date(expense_date) >= date(?1) AND date(expense_date) <= date(?2)");
@@ -1362,6 +2190,8 @@ pub fn get_expenses(start_date: Option<String>, end_date: Option<String>, db: St
                 amount: row.get(3)?,
                 notes: row.get(4)?,
                 created_at: row.get(5)?,
+                created_by: row.get(6)?,
+                created_by_name: row.get(7)?,
             })
         }).map_err(|e| e.to_string())?;
 
@@ -1377,6 +2207,8 @@ pub fn get_expenses(start_date: Option<String>, end_date: Option<String>, db: St
                 amount: row.get(3)?,
                 notes: row.get(4)?,
                 created_at: row.get(5)?,
+                created_by: row.get(6)?,
+                created_by_name: row.get(7)?,
             })
         }).map_err(|e| e.to_string())?;
 
@@ -1389,32 +2221,41 @@ pub fn get_expenses(start_date: Option<String>, end_date: Option<String>, db: St
 }
 
 #[tauri::command]
-pub fn update_expense(id: i64, expense: Expense, db: State<Database>) -> Result<(), String> {
+pub fn update_expense(token: String, id: i64, expense: Expense, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_EXPENSES)?;
+    let before = audit::snapshot(&conn, "expenses", "id", id)?;
     conn.execute(
         "UPDATE expenses SET expense_date = ?1, category = ?2, amount = ?3, notes = ?4 WHERE id = ?5",
         params![expense.expense_date, expense.category, expense.amount, expense.notes, id],
     ).map_err(|e| e.to_string())?;
-    Ok(())
+    let after = audit::snapshot(&conn, "expenses", "id", id)?;
+    audit::record_change(&conn, &session, "UPDATE", "Expense", Some(id), before, after)
 }
 
 #[tauri::command]
-pub fn delete_expense(id: i64, db: State<Database>) -> Result<(), String> {
+pub fn delete_expense(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_EXPENSES)?;
+    let before = audit::snapshot(&conn, "expenses", "id", id)?;
     conn.execute("DELETE FROM expenses WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
-    Ok(())
+    audit::record_change(&conn, &session, "DELETE", "Expense", Some(id), before, None)
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn cleanup_database(
+    token: String,
     clean_sales: bool, 
     clean_purchases: bool, 
     clean_products: bool, 
     clean_logs: bool, 
     clean_expenses: bool,
-    db: State<Database>
+    db: State<Database>,
+    sessions: State<SessionStore>
 ) -> Result<(), String> {
     let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::RUN_CLEANUP)?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
 
     if clean_products {
@@ -1423,23 +2264,53 @@ pub fn cleanup_database(
         tx.execute("DELETE FROM product_images", []).map_err(|e| e.to_string())?;
         
         // Wipe all transaction tables completely
+        tx.execute("DELETE FROM customer_payments", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM loyalty_ledger", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM sales_return_items", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM sales_returns", []).map_err(|e| e.to_string())?;
         tx.execute("DELETE FROM order_items", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM order_status_history", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM order_payments", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM order_refunds", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM promotion_redemptions", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM held_orders", []).map_err(|e| e.to_string())?;
         tx.execute("DELETE FROM orders", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM supplier_payments", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM purchase_return_items", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM purchase_returns", []).map_err(|e| e.to_string())?;
         tx.execute("DELETE FROM purchase_items", []).map_err(|e| e.to_string())?;
         tx.execute("DELETE FROM purchases", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM purchase_order_items", []).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM purchase_orders", []).map_err(|e| e.to_string())?;
+        
+        tx.execute("DELETE FROM promotions WHERE product_id IS NOT NULL", []).map_err(|e| e.to_string())?;
         
         // Finally products
         tx.execute("DELETE FROM products", []).map_err(|e| e.to_string())?;
     } else {
         // If NOT cleaning products, check individual flags
         if clean_sales {
+            tx.execute("DELETE FROM customer_payments", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM loyalty_ledger", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM sales_return_items", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM sales_returns", []).map_err(|e| e.to_string())?;
             tx.execute("DELETE FROM order_items", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM order_status_history", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM order_payments", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM order_refunds", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM promotion_redemptions", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM held_orders", []).map_err(|e| e.to_string())?;
             tx.execute("DELETE FROM orders", []).map_err(|e| e.to_string())?;
         }
     
         if clean_purchases {
+            tx.execute("DELETE FROM supplier_payments", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM purchase_return_items", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM purchase_returns", []).map_err(|e| e.to_string())?;
             tx.execute("DELETE FROM purchase_items", []).map_err(|e| e.to_string())?;
             tx.execute("DELETE FROM purchases", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM purchase_order_items", []).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM purchase_orders", []).map_err(|e| e.to_string())?;
         }
     }
 
@@ -1448,7 +2319,12 @@ pub fn cleanup_database(
     }
 
     if clean_logs {
-        tx.execute("DELETE FROM activity_logs", []).map_err(|e| e.to_string())?;
+        // Logs are never deleted; move them all into the sealed archive
+        let up_to_id: Option<i64> = tx.query_row("SELECT MAX(id) FROM activity_logs", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = up_to_id {
+            audit::archive_logs(&tx, &session, id)?;
+        }
     }
 
     tx.commit().map_err(|e| e.to_string())?;
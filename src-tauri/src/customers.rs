@@ -0,0 +1,729 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::db::Database;
+use crate::models::{AgingRow, Customer, CustomerBalance, CustomerHistory, CustomerPayment, CustomerProductSummary, CustomerRfmItem, Order, SalesReportItem, StatementEntry};
+use crate::payments;
+use crate::permissions;
+use crate::totals::EPSILON;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Customers are keyed by a normalized phone number so that "+880 1712-345678",
+// "8801712345678" and "01712345678" all resolve to the same buyer. Orders keep
+// their own name/phone/address copy as typed at the time of sale and link to
+// the customer through `orders.customer_id`.
+//
+// Credit: `orders.paid_amount` is what was paid at checkout and `orders.due_amount`
+// what is still outstanding. Later payments go into `customer_payments` and are
+// applied to orders (given order first, then oldest first) through
+// `customer_payment_allocations`, so per-order dues and aging stay accurate.
+
+/// Digits only, with the Bangladesh country code folded into the local form.
+/// Returns None when there are no digits to match on.
+pub fn normalize_phone(phone: &str) -> Option<String> {
+    let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return None;
+    }
+    let local = match digits.strip_prefix("88") {
+        Some(rest) if rest.starts_with("01") && rest.len() == 11 => rest.to_string(),
+        _ => digits,
+    };
+    Some(local)
+}
+
+pub fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Resolves the customer for an order: an explicit `customer_id` wins, otherwise the
+/// phone number is matched and a customer is created on first sight. Orders without a
+/// usable phone number stay unlinked.
+pub fn match_or_create(conn: &Connection, order: &Order) -> Result<Option<i64>, String> {
+    if let Some(id) = order.customer_id {
+        let exists: bool = conn.query_row("SELECT COUNT(*) FROM customers WHERE id = ?1", params![id], |row| row.get::<_, i64>(0))
+            .map_err(|e| e.to_string())? > 0;
+        if !exists {
+            return Err(format!("Customer #{} not found", id));
+        }
+        return Ok(Some(id));
+    }
+
+    let phone = match non_empty(&order.customer_phone) {
+        Some(phone) => phone,
+        None => return Ok(None),
+    };
+    let normalized = match normalize_phone(phone) {
+        Some(normalized) => normalized,
+        None => return Ok(None),
+    };
+
+    let existing: Option<i64> = conn.query_row(
+        "SELECT id FROM customers WHERE phone_normalized = ?1",
+        params![normalized],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?;
+    if let Some(id) = existing {
+        // Fill in an address the customer record is still missing
+        if let Some(address) = non_empty(&order.customer_address) {
+            conn.execute(
+                "UPDATE customers SET address = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND COALESCE(address, '') = ''",
+                params![address, id],
+            ).map_err(|e| e.to_string())?;
+        }
+        return Ok(Some(id));
+    }
+
+    conn.execute(
+        "INSERT INTO customers (customer_name, phone_number, address, phone_normalized, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        params![non_empty(&order.customer_name).unwrap_or(phone), phone, non_empty(&order.customer_address), normalized],
+    ).map_err(|e| e.to_string())?;
+    Ok(Some(conn.last_insert_rowid()))
+}
+
+/// Works out `(paid_amount, due_amount)` for an order being created or edited.
+/// Payments already applied to the order on account and redeemed loyalty points count
+/// towards it; anything left unpaid must be owed by a known customer.
+pub fn checkout_amounts(conn: &Connection, order: &Order, order_id: Option<i64>, customer_id: Option<i64>, points_value: f64) -> Result<(f64, f64), String> {
+    let allocated: f64 = match order_id {
+        Some(id) => conn.query_row(
+            "SELECT COALESCE(SUM(amount), 0) FROM customer_payment_allocations WHERE order_id = ?1",
+            params![id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?,
+        None => 0.0,
+    };
+    if allocated > EPSILON {
+        let previous_customer: Option<i64> = conn.query_row(
+            "SELECT customer_id FROM orders WHERE order_id = ?1",
+            params![order_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        if previous_customer != customer_id {
+            return Err("Order has account payments recorded; its customer cannot be changed".to_string());
+        }
+    }
+
+    let outstanding = order.grand_total - points_value - allocated;
+    if outstanding < -EPSILON {
+        return Err(format!("Order total is below the {:.2} already collected on account", allocated));
+    }
+    let tendered = payments::tendered_total(order);
+    let paid = tendered.or(order.paid_amount).unwrap_or(outstanding);
+    if paid < 0.0 {
+        return Err("Paid amount cannot be negative".to_string());
+    }
+    // Anything tendered above the total is change, not a payment
+    let paid = paid.min(outstanding.max(0.0));
+    let due = (outstanding - paid).max(0.0);
+    if due > EPSILON && customer_id.is_none() {
+        return Err(match tendered {
+            Some(total) => format!("Payments of {:.2} do not cover the {:.2} due. Take the rest, or enter the customer's phone number to put it on account", total, outstanding),
+            None => "A credit sale needs a customer. Enter the customer's phone number".to_string(),
+        });
+    }
+    Ok((paid, if due > EPSILON { due } else { 0.0 }))
+}
+
+/// One-time migration: normalizes phones already in `customers` and folds the loose
+/// customer strings on historic orders into customer records (the most recent order's
+/// name and address win).
+pub fn backfill_order_customers(conn: &Connection) -> rusqlite::Result<()> {
+    let existing: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare("SELECT id, phone_number FROM customers WHERE phone_normalized IS NULL AND phone_number IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (id, phone) in existing {
+        if let Some(normalized) = normalize_phone(&phone) {
+            // Duplicates already in the table keep a NULL key rather than break the unique index
+            conn.execute(
+                "UPDATE customers SET phone_normalized = ?1 WHERE id = ?2 AND NOT EXISTS (SELECT 1 FROM customers WHERE phone_normalized = ?1)",
+                params![normalized, id],
+            )?;
+        }
+    }
+
+    let orders: Vec<(i64, String, Option<String>, Option<String>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT order_id, customer_phone, customer_name, customer_address FROM orders
+             WHERE customer_id IS NULL AND customer_phone IS NOT NULL AND TRIM(customer_phone) != ''
+             ORDER BY order_date DESC, order_id DESC"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (order_id, phone, name, address) in orders {
+        let normalized = match normalize_phone(&phone) {
+            Some(normalized) => normalized,
+            None => continue,
+        };
+        let customer_id: i64 = match conn.query_row(
+            "SELECT id FROM customers WHERE phone_normalized = ?1",
+            params![normalized],
+            |row| row.get(0),
+        ).optional()? {
+            Some(id) => id,
+            None => {
+                conn.execute(
+                    "INSERT INTO customers (customer_name, phone_number, address, phone_normalized, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                    params![non_empty(&name).unwrap_or(phone.trim()), phone.trim(), non_empty(&address), normalized],
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+        conn.execute("UPDATE orders SET customer_id = ?1 WHERE order_id = ?2", params![customer_id, order_id])?;
+    }
+    Ok(())
+}
+
+const CUSTOMER_SELECT: &str = "SELECT c.id, c.customer_name, c.phone_number, c.address, c.created_at,
+        (SELECT COUNT(*) FROM orders o WHERE o.customer_id = c.id AND o.status NOT IN ('cancelled', 'returned')),
+        (SELECT COALESCE(SUM(o.grand_total), 0) FROM orders o WHERE o.customer_id = c.id AND o.status NOT IN ('cancelled', 'returned')),
+        (SELECT COALESCE(SUM(o.due_amount), 0) FROM orders o WHERE o.customer_id = c.id),
+        (SELECT COALESCE(SUM(l.points), 0) FROM loyalty_ledger l WHERE l.customer_id = c.id)
+     FROM customers c";
+
+fn customer_from_row(row: &rusqlite::Row) -> rusqlite::Result<Customer> {
+    Ok(Customer {
+        id: Some(row.get(0)?),
+        customer_name: row.get(1)?,
+        phone_number: row.get(2)?,
+        address: row.get(3)?,
+        created_at: row.get(4)?,
+        order_count: Some(row.get(5)?),
+        total_spent: Some(row.get(6)?),
+        balance: Some(row.get(7)?),
+        loyalty_points: Some(row.get(8)?),
+    })
+}
+
+fn validate(conn: &Connection, customer: &Customer, id: Option<i64>) -> Result<Option<String>, String> {
+    if customer.customer_name.trim().is_empty() {
+        return Err("Customer name is required".to_string());
+    }
+    let normalized = non_empty(&customer.phone_number).and_then(normalize_phone);
+    if let Some(normalized) = &normalized {
+        let taken: Option<String> = conn.query_row(
+            "SELECT customer_name FROM customers WHERE phone_normalized = ?1 AND id != ?2",
+            params![normalized, id.unwrap_or(0)],
+            |row| row.get(0),
+        ).optional().map_err(|e| e.to_string())?;
+        if let Some(name) = taken {
+            return Err(format!("Phone number already belongs to customer \"{}\"", name));
+        }
+    }
+    Ok(normalized)
+}
+
+#[tauri::command]
+pub fn get_customers(token: String, search: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Customer>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+
+    let query = search.as_deref().map(str::trim).unwrap_or("").to_lowercase();
+    let phone = normalize_phone(&query).unwrap_or_default();
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE ?1 = '' OR LOWER(c.customer_name) LIKE '%' || ?1 || '%' OR (?2 != '' AND c.phone_normalized LIKE '%' || ?2 || '%')
+         ORDER BY c.customer_name COLLATE NOCASE",
+        CUSTOMER_SELECT
+    )).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![query, phone], customer_from_row).map_err(|e| e.to_string())?;
+
+    let mut customers = Vec::new();
+    for customer in rows {
+        customers.push(customer.map_err(|e| e.to_string())?);
+    }
+    Ok(customers)
+}
+
+/// Looks a customer up by any spelling of their phone number (used by the POS to prefill details).
+#[tauri::command]
+pub fn find_customer_by_phone(token: String, phone: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Option<Customer>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+    let normalized = match normalize_phone(&phone) {
+        Some(normalized) => normalized,
+        None => return Ok(None),
+    };
+    conn.query_row(
+        &format!("{} WHERE c.phone_normalized = ?1", CUSTOMER_SELECT),
+        params![normalized],
+        customer_from_row,
+    ).optional().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn create_customer(token: String, customer: Customer, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_CUSTOMERS)?;
+    let normalized = validate(&conn, &customer, None)?;
+
+    conn.execute(
+        "INSERT INTO customers (customer_name, phone_number, address, phone_normalized, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        params![customer.customer_name.trim(), non_empty(&customer.phone_number), non_empty(&customer.address), normalized],
+    ).map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+
+    let after = audit::snapshot(&conn, "customers", "id", id)?;
+    audit::record_change(&conn, &session, "CREATE", "Customer", Some(id), None, after)?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn update_customer(token: String, id: i64, customer: Customer, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_CUSTOMERS)?;
+    let before = audit::snapshot(&conn, "customers", "id", id)?;
+    if before.is_none() {
+        return Err(format!("Customer #{} not found", id));
+    }
+    let normalized = validate(&conn, &customer, Some(id))?;
+
+    conn.execute(
+        "UPDATE customers SET customer_name = ?1, phone_number = ?2, address = ?3, phone_normalized = ?4, updated_at = CURRENT_TIMESTAMP WHERE id = ?5",
+        params![customer.customer_name.trim(), non_empty(&customer.phone_number), non_empty(&customer.address), normalized, id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = audit::snapshot(&conn, "customers", "id", id)?;
+    audit::record_change(&conn, &session, "UPDATE", "Customer", Some(id), before, after)?;
+    Ok(())
+}
+
+/// Only customers without orders can be deleted; order history keeps its link.
+#[tauri::command]
+pub fn delete_customer(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_CUSTOMERS)?;
+
+    let order_count: i64 = conn.query_row("SELECT COUNT(*) FROM orders WHERE customer_id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if order_count > 0 {
+        return Err(format!("Customer has {} orders and cannot be deleted", order_count));
+    }
+    let ledger_entries: i64 = conn.query_row("SELECT COUNT(*) FROM loyalty_ledger WHERE customer_id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if ledger_entries > 0 {
+        return Err("Customer has loyalty points history and cannot be deleted".to_string());
+    }
+
+    let before = audit::snapshot(&conn, "customers", "id", id)?;
+    conn.execute("DELETE FROM customers WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    audit::record_change(&conn, &session, "DELETE", "Customer", Some(id), before, None)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn record_customer_payment(token: String, payment: CustomerPayment, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::RECEIVE_PAYMENTS)?;
+    if payment.amount <= 0.0 {
+        return Err("Payment amount must be greater than zero".to_string());
+    }
+    let method = payments::check_method(payment.payment_method.as_deref().unwrap_or("cash"))?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let customer_name: String = tx.query_row(
+        "SELECT customer_name FROM customers WHERE id = ?1",
+        params![payment.customer_id],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?.ok_or_else(|| format!("Customer #{} not found", payment.customer_id))?;
+    let outstanding: f64 = tx.query_row(
+        "SELECT COALESCE(SUM(due_amount), 0) FROM orders WHERE customer_id = ?1",
+        params![payment.customer_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    if payment.amount > outstanding + EPSILON {
+        return Err(format!("Payment exceeds the outstanding balance of {:.2}", outstanding));
+    }
+
+    let shift_id = crate::shifts::open_shift_id(&tx)?;
+    tx.execute(
+        "INSERT INTO customer_payments (customer_id, amount, payment_method, payment_date, notes, shift_id, created_by, created_by_name)
+         VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), ?5, ?6, ?7, ?8)",
+        params![payment.customer_id, payment.amount, method, payment.payment_date, payment.notes, shift_id, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let payment_id = tx.last_insert_rowid();
+
+    // The chosen order first, then the oldest dues
+    let dues: Vec<(i64, f64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT order_id, due_amount FROM orders WHERE customer_id = ?1 AND due_amount > 0
+             ORDER BY (order_id = ?2) DESC, datetime(order_date), order_id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![payment.customer_id, payment.order_id.unwrap_or(0)], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    if let Some(order_id) = payment.order_id {
+        if dues.first().map(|(id, _)| *id) != Some(order_id) {
+            return Err(format!("Order #{} has nothing due for this customer", order_id));
+        }
+    }
+    let mut remaining = payment.amount;
+    for (order_id, due) in dues {
+        if remaining <= EPSILON {
+            break;
+        }
+        let applied = remaining.min(due);
+        tx.execute(
+            "INSERT INTO customer_payment_allocations (payment_id, order_id, amount) VALUES (?1, ?2, ?3)",
+            params![payment_id, order_id, applied],
+        ).map_err(|e| e.to_string())?;
+        let left = due - applied;
+        tx.execute(
+            "UPDATE orders SET due_amount = ?1 WHERE order_id = ?2",
+            params![if left > EPSILON { left } else { 0.0 }, order_id],
+        ).map_err(|e| e.to_string())?;
+        remaining -= applied;
+    }
+
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        "PAYMENT",
+        "Customer",
+        Some(payment.customer_id),
+        &format!(
+            "Received {:.2} from {} ({})",
+            payment.amount,
+            customer_name,
+            method
+        ),
+    )?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(payment_id)
+}
+
+#[tauri::command]
+pub fn get_customer_balance(token: String, customer_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<CustomerBalance, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+
+    conn.query_row(
+        "SELECT c.id, c.customer_name,
+            (SELECT COALESCE(SUM(grand_total), 0) FROM orders WHERE customer_id = c.id AND status NOT IN ('cancelled', 'returned')),
+            (SELECT COALESCE(SUM(due_amount), 0) FROM orders WHERE customer_id = c.id),
+            (SELECT COUNT(*) FROM orders WHERE customer_id = c.id AND due_amount > 0)
+         FROM customers c WHERE c.id = ?1",
+        params![customer_id],
+        |row| {
+            let total_sales: f64 = row.get(2)?;
+            let balance: f64 = row.get(3)?;
+            Ok(CustomerBalance {
+                customer_id: row.get(0)?,
+                customer_name: row.get(1)?,
+                total_sales,
+                total_paid: total_sales - balance,
+                balance,
+                open_orders: row.get(4)?,
+            })
+        },
+    ).optional().map_err(|e| e.to_string())?.ok_or_else(|| format!("Customer #{} not found", customer_id))
+}
+
+/// Statement of account: sales as debits, checkout and later payments as credits,
+/// with a running balance. Entries before `start_date` roll into an opening balance.
+#[tauri::command]
+pub fn get_customer_statement(
+    token: String,
+    customer_id: i64,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    db: State<Database>,
+    sessions: State<SessionStore>,
+) -> Result<Vec<StatementEntry>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date, entry_type, reference, description, debit, credit FROM (
+            SELECT order_date AS date, 'SALE' AS entry_type, 'Order #' || order_id AS reference,
+                   order_type AS description, grand_total AS debit, 0 AS credit, order_id AS seq, 0 AS sub
+            FROM orders WHERE customer_id = ?1 AND status NOT IN ('cancelled', 'returned')
+            UNION ALL
+            SELECT order_date, 'PAYMENT', 'Order #' || order_id, 'Paid at checkout (' || COALESCE(payment_method, 'cash') || ')',
+                   0, paid_amount, order_id, 1
+            FROM orders WHERE customer_id = ?1 AND paid_amount > 0 AND status NOT IN ('cancelled', 'returned')
+            UNION ALL
+            SELECT order_date, 'PAYMENT', 'Order #' || order_id, points_redeemed || ' loyalty points redeemed',
+                   0, points_value, order_id, 1
+            FROM orders WHERE customer_id = ?1 AND points_value > 0 AND status NOT IN ('cancelled', 'returned')
+            UNION ALL
+            SELECT payment_date, 'PAYMENT', 'Payment #' || id, COALESCE(notes, COALESCE(payment_method, 'cash')),
+                   0, amount, id, 2
+            FROM customer_payments WHERE customer_id = ?1
+            UNION ALL
+            SELECT r.return_date, 'RETURN', 'Return #' || r.id, COALESCE(r.reason, 'Goods returned'),
+                   0, r.credit_amount, r.id, 3
+            FROM sales_returns r JOIN orders o ON o.order_id = r.order_id
+            WHERE r.customer_id = ?1 AND r.credit_amount > 0 AND o.status NOT IN ('cancelled', 'returned')
+         ) ORDER BY datetime(date), sub, seq"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![customer_id], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, f64>(4)?,
+            row.get::<_, f64>(5)?,
+        ))
+    }).map_err(|e| e.to_string())?;
+
+    let mut rows = rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| e.to_string())?;
+
+    // Dates are compared on their YYYY-MM-DD prefix, which every stored format shares
+    let day = |date: &Option<String>| date.as_deref().map(|d| d.chars().take(10).collect::<String>()).unwrap_or_default();
+    let mut entries = Vec::new();
+    let mut balance = 0.0;
+    if let Some(start) = &start_date {
+        let (before, rest): (Vec<_>, Vec<_>) = rows.into_iter().partition(|r| day(&r.0).as_str() < start.as_str());
+        balance = before.iter().map(|r| r.4 - r.5).sum();
+        entries.push(StatementEntry {
+            date: Some(start.clone()),
+            entry_type: "OPENING".to_string(),
+            reference: None,
+            description: Some("Opening balance".to_string()),
+            debit: balance.max(0.0),
+            credit: (-balance).max(0.0),
+            balance,
+        });
+        rows = rest;
+    }
+    for (date, entry_type, reference, description, debit, credit) in rows {
+        if let Some(end) = &end_date {
+            if day(&date).as_str() > end.as_str() {
+                break;
+            }
+        }
+        balance += debit - credit;
+        entries.push(StatementEntry { date, entry_type, reference, description, debit, credit, balance });
+    }
+    Ok(entries)
+}
+
+/// Outstanding dues per customer bucketed by the age of the order.
+#[tauri::command]
+pub fn get_receivables_aging(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<AgingRow>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.customer_name, c.phone_number,
+            SUM(CASE WHEN age <= 30 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 30 AND age <= 60 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 60 AND age <= 90 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 90 THEN due ELSE 0 END),
+            SUM(due)
+         FROM (
+            SELECT customer_id, due_amount AS due,
+                   CAST(julianday('now', 'localtime') - julianday(date(order_date)) AS INTEGER) AS age
+            FROM orders WHERE due_amount > 0 AND customer_id IS NOT NULL
+         ) o
+         JOIN customers c ON c.id = o.customer_id
+         GROUP BY c.id
+         ORDER BY SUM(due) DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok(AgingRow {
+            party_id: row.get(0)?,
+            party_name: row.get(1)?,
+            phone_number: row.get(2)?,
+            days_0_30: row.get(3)?,
+            days_31_60: row.get(4)?,
+            days_61_90: row.get(5)?,
+            days_90_plus: row.get(6)?,
+            total: row.get(7)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut aging = Vec::new();
+    for row in rows {
+        aging.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(aging)
+}
+
+/// Every order for a phone number, whichever way it was typed, with lifetime totals
+/// and the customer's most bought products.
+#[tauri::command]
+pub fn get_customer_history(token: String, phone: String, db: State<Database>, sessions: State<SessionStore>) -> Result<CustomerHistory, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
+    let normalized = normalize_phone(&phone).ok_or_else(|| "Enter a valid phone number".to_string())?;
+
+    let customer = conn.query_row(
+        &format!("{} WHERE c.phone_normalized = ?1", CUSTOMER_SELECT),
+        params![normalized],
+        customer_from_row,
+    ).optional().map_err(|e| e.to_string())?;
+    let customer_id = customer.as_ref().and_then(|c| c.id);
+
+    // Orders with a phone number are linked to their customer when saved (and by
+    // backfill_order_customers for older ones), so the link finds them all
+    let mut stmt = conn.prepare(
+        "SELECT
+            o.order_id,
+            o.order_date,
+            o.customer_name,
+            o.grand_total,
+            COALESCE(o.discount, 0),
+            COUNT(oi.order_id),
+            o.grand_total - COALESCE(o.tax_total, 0) - COALESCE(SUM(oi.quantity * oi.buying_price_snapshot), 0),
+            o.created_by_name
+         FROM orders o
+         LEFT JOIN order_items oi ON oi.order_id = o.order_id
+         WHERE o.customer_id = ?1 AND o.status NOT IN ('cancelled', 'returned')
+         GROUP BY o.order_id
+         ORDER BY datetime(o.order_date) DESC, o.order_id DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![customer_id.unwrap_or(0)], |row| {
+        let profit: f64 = row.get(6)?;
+        Ok((
+            SalesReportItem {
+                order_id: row.get(0)?,
+                date: row.get(1)?,
+                customer: row.get(2)?,
+                total: row.get(3)?,
+                discount: row.get(4)?,
+                items_count: row.get(5)?,
+                profit: if can_view_profit { Some(profit) } else { None },
+                cashier: row.get(7)?,
+            },
+            profit,
+        ))
+    }).map_err(|e| e.to_string())?;
+
+    let mut orders = Vec::new();
+    let mut total_profit = 0.0;
+    for row in rows {
+        let (order, profit) = row.map_err(|e| e.to_string())?;
+        total_profit += profit;
+        orders.push(order);
+    }
+
+    let mut top_products = Vec::new();
+    if !orders.is_empty() {
+        let mut stmt = conn.prepare(
+            "SELECT oi.product_id, p.product_name, SUM(oi.quantity), SUM(oi.subtotal)
+             FROM orders o
+             JOIN order_items oi ON oi.order_id = o.order_id
+             JOIN products p ON p.id = oi.product_id
+             WHERE o.customer_id = ?1 AND o.status NOT IN ('cancelled', 'returned')
+             GROUP BY oi.product_id
+             ORDER BY SUM(oi.subtotal) DESC
+             LIMIT 10"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![customer_id], |row| {
+            Ok(CustomerProductSummary {
+                product_id: row.get(0)?,
+                product_name: row.get(1)?,
+                quantity: row.get(2)?,
+                total: row.get(3)?,
+            })
+        }).map_err(|e| e.to_string())?;
+        for row in rows {
+            top_products.push(row.map_err(|e| e.to_string())?);
+        }
+    }
+
+    let order_count = orders.len() as i64;
+    let total_spent: f64 = orders.iter().map(|o| o.total).sum();
+    Ok(CustomerHistory {
+        customer,
+        phone_normalized: normalized,
+        order_count,
+        total_spent,
+        total_profit: if can_view_profit { Some(total_profit) } else { None },
+        average_order: if order_count > 0 { total_spent / order_count as f64 } else { 0.0 },
+        first_order: orders.last().map(|o| o.date.clone()),
+        last_order: orders.first().map(|o| o.date.clone()),
+        orders,
+        top_products,
+    })
+}
+
+/// 1-5 score from a value's percentile rank among all customers (ties share the middle).
+fn rfm_scores(values: &[f64], higher_is_better: bool) -> Vec<i64> {
+    let n = values.len();
+    values.iter().map(|&v| {
+        if n < 2 {
+            return 3;
+        }
+        let below = values.iter().filter(|&&o| if higher_is_better { o < v } else { o > v }).count();
+        let equal = values.iter().filter(|&&o| o == v).count();
+        let rank = (below as f64 + (equal - 1) as f64 / 2.0) / (n - 1) as f64;
+        1 + (rank * 4.0).round() as i64
+    }).collect()
+}
+
+/// `orders` is the raw order count, so one-time buyers read as "New" even when ties
+/// lift their frequency score.
+fn rfm_segment(r: i64, f: i64, m: i64, orders: i64) -> &'static str {
+    match (r, f) {
+        (4..=5, 4..=5) => "Champions",
+        (3, 4..=5) => "Loyal",
+        (1..=2, 4..=5) if m >= 4 => "Can't Lose Them",
+        (4..=5, _) if orders == 1 => "New",
+        (4..=5, _) => "Potential Loyalist",
+        (3, _) => "Needs Attention",
+        (1..=2, 3..=5) => "At Risk",
+        (2, _) => "Hibernating",
+        _ => "Lost",
+    }
+}
+
+/// Recency, frequency and monetary value per customer, scored against each other and
+/// bucketed into segments for targeting. Profit uses the cost captured on each sale.
+#[tauri::command]
+pub fn get_customer_rfm_report(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<CustomerRfmItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+    let can_view_profit = permissions::has_permission(&conn, &session.role, permissions::VIEW_PROFIT)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.customer_name, c.phone_number,
+            MAX(datetime(o.order_date)),
+            CAST(julianday('now', 'localtime') - julianday(date(MAX(datetime(o.order_date)))) AS INTEGER),
+            COUNT(*),
+            COALESCE(SUM(o.grand_total), 0),
+            COALESCE(SUM(o.grand_total - COALESCE(o.tax_total, 0) - COALESCE((SELECT SUM(quantity * buying_price_snapshot) FROM order_items WHERE order_items.order_id = o.order_id), 0)), 0)
+         FROM orders o
+         JOIN customers c ON c.id = o.customer_id
+         WHERE o.status NOT IN ('cancelled', 'returned')
+         GROUP BY c.id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok(CustomerRfmItem {
+            customer_id: row.get(0)?,
+            customer_name: row.get(1)?,
+            phone_number: row.get(2)?,
+            last_order: row.get(3)?,
+            recency_days: row.get(4)?,
+            frequency: row.get(5)?,
+            monetary: row.get(6)?,
+            lifetime_profit: if can_view_profit { Some(row.get(7)?) } else { None },
+            r_score: 0,
+            f_score: 0,
+            m_score: 0,
+            segment: String::new(),
+        })
+    }).map_err(|e| e.to_string())?;
+    let mut items = rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| e.to_string())?;
+
+    let recency = rfm_scores(&items.iter().map(|i| i.recency_days as f64).collect::<Vec<_>>(), false);
+    let frequency = rfm_scores(&items.iter().map(|i| i.frequency as f64).collect::<Vec<_>>(), true);
+    let monetary = rfm_scores(&items.iter().map(|i| i.monetary).collect::<Vec<_>>(), true);
+    for (i, item) in items.iter_mut().enumerate() {
+        item.r_score = recency[i];
+        item.f_score = frequency[i];
+        item.m_score = monetary[i];
+        item.segment = rfm_segment(item.r_score, item.f_score, item.m_score, item.frequency).to_string();
+    }
+    items.sort_by(|a, b| (b.r_score + b.f_score + b.m_score).cmp(&(a.r_score + a.f_score + a.m_score)).then(b.monetary.total_cmp(&a.monetary)));
+    Ok(items)
+}
@@ -94,6 +94,309 @@ pub fn init_db(app_handle: &AppHandle) -> Result<Connection> {
             notes TEXT
         );
 
+        CREATE TABLE IF NOT EXISTS shifts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            status TEXT NOT NULL DEFAULT 'open', -- open / closed
+            opened_by INTEGER,
+            opened_by_name TEXT NOT NULL,
+            opened_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            opening_float REAL NOT NULL DEFAULT 0,
+            closed_by INTEGER,
+            closed_by_name TEXT,
+            closed_at DATETIME,
+            cash_sales REAL,
+            expected_cash REAL,
+            counted_cash REAL,
+            variance REAL,
+            notes TEXT
+        );
+
+        -- Single cash drawer: only one shift may be open
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_shifts_one_open ON shifts(status) WHERE status = 'open';
+
+        CREATE TABLE IF NOT EXISTS cash_movements (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            shift_id INTEGER NOT NULL,
+            movement_type TEXT NOT NULL, -- IN / OUT
+            amount REAL NOT NULL,
+            reason TEXT,
+            user_id INTEGER,
+            username TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(shift_id) REFERENCES shifts(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS customer_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            customer_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            payment_method TEXT,
+            payment_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            notes TEXT,
+            shift_id INTEGER,
+            created_by INTEGER,
+            created_by_name TEXT,
+            FOREIGN KEY(customer_id) REFERENCES customers(id),
+            FOREIGN KEY(shift_id) REFERENCES shifts(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS suppliers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            supplier_name TEXT NOT NULL,
+            phone_number TEXT,
+            phone_normalized TEXT,
+            address TEXT,
+            notes TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME
+        );
+
+        CREATE TABLE IF NOT EXISTS supplier_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            supplier_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            payment_method TEXT,
+            payment_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            notes TEXT,
+            created_by INTEGER,
+            created_by_name TEXT,
+            FOREIGN KEY(supplier_id) REFERENCES suppliers(id)
+        );
+
+        -- How each supplier payment was applied to purchases with money owing
+        CREATE TABLE IF NOT EXISTS supplier_payment_allocations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            payment_id INTEGER NOT NULL,
+            purchase_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            FOREIGN KEY(payment_id) REFERENCES supplier_payments(id) ON DELETE CASCADE,
+            FOREIGN KEY(purchase_id) REFERENCES purchases(purchase_id)
+        );
+
+        -- Purchase orders: what we ordered from a supplier. Stock only moves when a
+        -- receipt is posted as a purchase (purchases.purchase_order_id).
+        CREATE TABLE IF NOT EXISTS purchase_orders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            supplier_id INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'draft', -- draft / sent / partially_received / received / closed / cancelled
+            order_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expected_date DATETIME,
+            notes TEXT,
+            created_by INTEGER,
+            created_by_name TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME,
+            FOREIGN KEY(supplier_id) REFERENCES suppliers(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS purchase_order_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            purchase_order_id INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            quantity_ordered REAL NOT NULL,
+            quantity_received REAL NOT NULL DEFAULT 0,
+            unit_cost REAL NOT NULL DEFAULT 0,
+            FOREIGN KEY(purchase_order_id) REFERENCES purchase_orders(id) ON DELETE CASCADE,
+            FOREIGN KEY(product_id) REFERENCES products(id)
+        );
+
+        -- Goods sent back to the supplier from a purchase. The value is credited against
+        -- what we owe; credit_remaining is what is left once every due is settled and is
+        -- used up by the supplier's next dues.
+        CREATE TABLE IF NOT EXISTS purchase_returns (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            purchase_id INTEGER NOT NULL,
+            supplier_id INTEGER NOT NULL,
+            return_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            total_amount REAL NOT NULL,
+            credit_applied REAL NOT NULL DEFAULT 0,
+            credit_remaining REAL NOT NULL DEFAULT 0,
+            reason TEXT,
+            notes TEXT,
+            created_by INTEGER,
+            created_by_name TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(purchase_id) REFERENCES purchases(purchase_id),
+            FOREIGN KEY(supplier_id) REFERENCES suppliers(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS purchase_return_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            return_id INTEGER NOT NULL,
+            purchase_item_id INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            quantity REAL NOT NULL,
+            unit_cost REAL NOT NULL,
+            subtotal REAL NOT NULL,
+            FOREIGN KEY(return_id) REFERENCES purchase_returns(id) ON DELETE CASCADE,
+            FOREIGN KEY(purchase_item_id) REFERENCES purchase_items(id),
+            FOREIGN KEY(product_id) REFERENCES products(id)
+        );
+
+        -- How each return's credit was applied to purchases with money owing
+        CREATE TABLE IF NOT EXISTS purchase_return_allocations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            return_id INTEGER NOT NULL,
+            purchase_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            FOREIGN KEY(return_id) REFERENCES purchase_returns(id) ON DELETE CASCADE,
+            FOREIGN KEY(purchase_id) REFERENCES purchases(purchase_id)
+        );
+
+        -- Carts parked at the till. The cart is kept as JSON and only becomes an order
+        -- (touching stock and revenue) when it is finalized through create_order.
+        CREATE TABLE IF NOT EXISTS held_orders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT,
+            customer_name TEXT,
+            cart TEXT NOT NULL,
+            item_count INTEGER NOT NULL DEFAULT 0,
+            total REAL NOT NULL DEFAULT 0,
+            held_by INTEGER,
+            held_by_name TEXT,
+            held_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- Tenders taken for an order. `amount` is what went towards the sale; `tendered`
+        -- is what was handed over, more than `amount` only for cash that got change.
+        CREATE TABLE IF NOT EXISTS order_payments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            order_id INTEGER NOT NULL REFERENCES orders(order_id),
+            payment_method TEXT NOT NULL, -- cash / card / mobile
+            amount REAL NOT NULL,
+            tendered REAL NOT NULL,
+            reference TEXT, -- card slip or wallet transaction ID
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- What was handed back when a paid order was cancelled or returned, per tender
+        -- method, in the shift that was open at the time
+        CREATE TABLE IF NOT EXISTS order_refunds (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            order_id INTEGER NOT NULL REFERENCES orders(order_id),
+            refund_method TEXT NOT NULL, -- cash / card / mobile
+            amount REAL NOT NULL,
+            shift_id INTEGER REFERENCES shifts(id),
+            refund_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            created_by INTEGER,
+            created_by_name TEXT
+        );
+
+        -- Discount rules evaluated when a cart is priced (see promotions.rs)
+        CREATE TABLE IF NOT EXISTS promotions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            scope TEXT NOT NULL, -- item / category / order
+            kind TEXT NOT NULL, -- percent / fixed / bogo
+            value REAL NOT NULL,
+            product_id INTEGER REFERENCES products(id),
+            category TEXT,
+            buy_quantity REAL,
+            get_quantity REAL,
+            min_spend REAL,
+            start_date TEXT,
+            end_date TEXT,
+            days_of_week TEXT, -- comma separated, 0 = Sunday
+            start_time TEXT, -- HH:MM
+            end_time TEXT,
+            coupon_code TEXT UNIQUE, -- NULL for automatic promotions
+            usage_limit INTEGER,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- Promotions used on each order and what they took off
+        CREATE TABLE IF NOT EXISTS promotion_redemptions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            promotion_id INTEGER NOT NULL REFERENCES promotions(id),
+            order_id INTEGER NOT NULL REFERENCES orders(order_id),
+            coupon_code TEXT,
+            discount REAL NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- Every fulfilment status an order has been through, newest last
+        CREATE TABLE IF NOT EXISTS order_status_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            order_id INTEGER NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            notes TEXT,
+            changed_by INTEGER,
+            changed_by_name TEXT,
+            changed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(order_id) REFERENCES orders(order_id) ON DELETE CASCADE
+        );
+
+        -- Goods a customer brought back from an order. The value first comes off what the
+        -- customer still owed on the order (credit_amount); the rest is refunded.
+        CREATE TABLE IF NOT EXISTS sales_returns (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            order_id INTEGER NOT NULL,
+            customer_id INTEGER,
+            return_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            total_amount REAL NOT NULL,
+            credit_amount REAL NOT NULL DEFAULT 0,
+            refund_amount REAL NOT NULL DEFAULT 0,
+            refund_method TEXT,
+            points_restored INTEGER NOT NULL DEFAULT 0,
+            points_value REAL NOT NULL DEFAULT 0,
+            reason TEXT,
+            notes TEXT,
+            shift_id INTEGER,
+            created_by INTEGER,
+            created_by_name TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(order_id) REFERENCES orders(order_id),
+            FOREIGN KEY(customer_id) REFERENCES customers(id),
+            FOREIGN KEY(shift_id) REFERENCES shifts(id)
+        );
+
+        -- restock = 0 means the goods were written off instead of going back on the shelf
+        CREATE TABLE IF NOT EXISTS sales_return_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            return_id INTEGER NOT NULL,
+            order_item_id INTEGER NOT NULL,
+            product_id INTEGER NOT NULL,
+            quantity REAL NOT NULL,
+            unit_price REAL NOT NULL,
+            subtotal REAL NOT NULL,
+            restock INTEGER NOT NULL DEFAULT 1,
+            cost_snapshot REAL NOT NULL DEFAULT 0,
+            FOREIGN KEY(return_id) REFERENCES sales_returns(id) ON DELETE CASCADE,
+            FOREIGN KEY(order_item_id) REFERENCES order_items(id),
+            FOREIGN KEY(product_id) REFERENCES products(id)
+        );
+
+        -- Loyalty points: every balance change is a row. Positive rows are lots that
+        -- redemptions and expiry consume oldest-first through `remaining`.
+        CREATE TABLE IF NOT EXISTS loyalty_ledger (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            customer_id INTEGER NOT NULL,
+            order_id INTEGER,
+            entry_type TEXT NOT NULL, -- EARN / REDEEM / REVERSE / RETURN / EXPIRE / ADJUST
+            points INTEGER NOT NULL,
+            remaining INTEGER NOT NULL DEFAULT 0,
+            balance_after INTEGER NOT NULL,
+            expires_at DATETIME,
+            notes TEXT,
+            created_by INTEGER,
+            created_by_name TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(customer_id) REFERENCES customers(id)
+        );
+
+        -- How each customer payment was applied to outstanding orders
+        CREATE TABLE IF NOT EXISTS customer_payment_allocations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            payment_id INTEGER NOT NULL,
+            order_id INTEGER NOT NULL,
+            amount REAL NOT NULL,
+            FOREIGN KEY(payment_id) REFERENCES customer_payments(id) ON DELETE CASCADE,
+            FOREIGN KEY(order_id) REFERENCES orders(order_id)
+        );
+
         CREATE TABLE IF NOT EXISTS order_items (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             order_id INTEGER NOT NULL,
@@ -128,6 +431,36 @@ pub fn init_db(app_handle: &AppHandle) -> Result<Connection> {
             created_at DATETIME DEFAULT CURRENT_TIMESTAMP
         );
 
+        CREATE TABLE IF NOT EXISTS recovery_codes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            code_hash TEXT NOT NULL,
+            used_at DATETIME,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS login_attempts (
+            username TEXT PRIMARY KEY,
+            failed_count INTEGER NOT NULL DEFAULT 0,
+            last_failed_at DATETIME,
+            locked_until DATETIME
+        );
+
+        CREATE TABLE IF NOT EXISTS roles (
+            name TEXT PRIMARY KEY,
+            description TEXT,
+            is_system INTEGER DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS role_permissions (
+            role TEXT NOT NULL,
+            permission TEXT NOT NULL,
+            PRIMARY KEY (role, permission),
+            FOREIGN KEY(role) REFERENCES roles(name) ON DELETE CASCADE
+        );
+
         CREATE TABLE IF NOT EXISTS activity_logs (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             user_id INTEGER,
@@ -139,6 +472,33 @@ pub fn init_db(app_handle: &AppHandle) -> Result<Connection> {
             created_at DATETIME DEFAULT CURRENT_TIMESTAMP
         );
 
+        CREATE TABLE IF NOT EXISTS activity_log_seals (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            first_log_id INTEGER NOT NULL,
+            last_log_id INTEGER NOT NULL,
+            row_count INTEGER NOT NULL,
+            last_hash TEXT NOT NULL,
+            sealed_by TEXT NOT NULL,
+            sealed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS activity_logs_archive (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER,
+            username TEXT NOT NULL,
+            action TEXT NOT NULL,
+            entity_type TEXT NOT NULL,
+            entity_id INTEGER,
+            description TEXT NOT NULL,
+            created_at DATETIME,
+            before_data TEXT,
+            after_data TEXT,
+            prev_hash TEXT,
+            hash TEXT,
+            seal_id INTEGER NOT NULL,
+            FOREIGN KEY(seal_id) REFERENCES activity_log_seals(id)
+        );
+
         CREATE TABLE IF NOT EXISTS conversations (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             title TEXT,
@@ -199,7 +559,245 @@ pub fn init_db(app_handle: &AppHandle) -> Result<Connection> {
         }
     }
 
+    {
+        // Migrations for customers
+        let mut stmt = conn.prepare("PRAGMA table_info(customers)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        // Match key for phone numbers (see customers.rs)
+        if !current_columns.contains("phone_normalized") {
+            conn.execute("ALTER TABLE customers ADD COLUMN phone_normalized TEXT", [])?;
+        }
+        if !current_columns.contains("created_at") {
+            conn.execute("ALTER TABLE customers ADD COLUMN created_at DATETIME", [])?;
+        }
+        if !current_columns.contains("updated_at") {
+            conn.execute("ALTER TABLE customers ADD COLUMN updated_at DATETIME", [])?;
+        }
+    }
+
+    {
+        // Migrations for orders
+        let mut stmt = conn.prepare("PRAGMA table_info(orders)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        if !current_columns.contains("shift_id") {
+            conn.execute("ALTER TABLE orders ADD COLUMN shift_id INTEGER REFERENCES shifts(id)", [])?;
+        }
+        if !current_columns.contains("customer_id") {
+            // One-time: fold historic order customers into the customers table. The column and
+            // backfill commit together so an interrupted start retries the whole step.
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("ALTER TABLE orders ADD COLUMN customer_id INTEGER REFERENCES customers(id)", [])?;
+            crate::customers::backfill_order_customers(&tx)?;
+            tx.commit()?;
+        }
+        // Credit sales: existing orders were paid in full
+        if !current_columns.contains("paid_amount") {
+            conn.execute("ALTER TABLE orders ADD COLUMN paid_amount REAL", [])?;
+            conn.execute("UPDATE orders SET paid_amount = grand_total WHERE paid_amount IS NULL", [])?;
+        }
+        if !current_columns.contains("due_amount") {
+            conn.execute("ALTER TABLE orders ADD COLUMN due_amount REAL DEFAULT 0", [])?;
+        }
+        // Loyalty points redeemed as a tender (and their currency value) and earned on the sale
+        if !current_columns.contains("points_redeemed") {
+            conn.execute("ALTER TABLE orders ADD COLUMN points_redeemed INTEGER DEFAULT 0", [])?;
+        }
+        if !current_columns.contains("points_value") {
+            conn.execute("ALTER TABLE orders ADD COLUMN points_value REAL DEFAULT 0", [])?;
+        }
+        if !current_columns.contains("points_earned") {
+            conn.execute("ALTER TABLE orders ADD COLUMN points_earned INTEGER DEFAULT 0", [])?;
+        }
+        // Who rang the sale up; the name is kept so attribution survives user deletion
+        if !current_columns.contains("created_by") {
+            conn.execute("ALTER TABLE orders ADD COLUMN created_by INTEGER", [])?;
+        }
+        if !current_columns.contains("created_by_name") {
+            conn.execute("ALTER TABLE orders ADD COLUMN created_by_name TEXT", [])?;
+        }
+        // Fulfilment status (see order_status.rs). Online orders taken before the
+        // workflow existed were fulfilled outside the app.
+        if !current_columns.contains("status") {
+            conn.execute("ALTER TABLE orders ADD COLUMN status TEXT NOT NULL DEFAULT 'completed'", [])?;
+            conn.execute("UPDATE orders SET status = 'delivered' WHERE order_type = 'online'", [])?;
+        }
+        // Split tenders: orders taken before them were paid with their single method
+        if !current_columns.contains("change_amount") {
+            conn.execute("ALTER TABLE orders ADD COLUMN change_amount REAL DEFAULT 0", [])?;
+            conn.execute(
+                "INSERT INTO order_payments (order_id, payment_method, amount, tendered)
+                 SELECT order_id, LOWER(COALESCE(payment_method, 'cash')), COALESCE(paid_amount, grand_total), COALESCE(paid_amount, grand_total)
+                 FROM orders WHERE COALESCE(paid_amount, grand_total) > 0",
+                [],
+            )?;
+        }
+        // Sales tax (see tax.rs). Orders taken before it charged none.
+        if !current_columns.contains("tax_total") {
+            conn.execute("ALTER TABLE orders ADD COLUMN tax_total REAL DEFAULT 0", [])?;
+        }
+        if !current_columns.contains("tax_mode") {
+            conn.execute("ALTER TABLE orders ADD COLUMN tax_mode TEXT DEFAULT 'inclusive'", [])?;
+        }
+        // Promotions (see promotions.rs)
+        if !current_columns.contains("coupon_code") {
+            conn.execute("ALTER TABLE orders ADD COLUMN coupon_code TEXT", [])?;
+        }
+        if !current_columns.contains("promotion_discount") {
+            conn.execute("ALTER TABLE orders ADD COLUMN promotion_discount REAL DEFAULT 0", [])?;
+        }
+    }
+
+    {
+        // Migrations for order_items
+        let mut stmt = conn.prepare("PRAGMA table_info(order_items)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        if !current_columns.contains("tax_rate") {
+            conn.execute("ALTER TABLE order_items ADD COLUMN tax_rate REAL DEFAULT 0", [])?;
+        }
+        if !current_columns.contains("tax_amount") {
+            conn.execute("ALTER TABLE order_items ADD COLUMN tax_amount REAL DEFAULT 0", [])?;
+        }
+        // Untaxed past lines still count as zero-rated sales
+        if !current_columns.contains("taxable_amount") {
+            conn.execute("ALTER TABLE order_items ADD COLUMN taxable_amount REAL DEFAULT 0", [])?;
+            conn.execute("UPDATE order_items SET taxable_amount = subtotal", [])?;
+        }
+        if !current_columns.contains("promotion_id") {
+            conn.execute("ALTER TABLE order_items ADD COLUMN promotion_id INTEGER REFERENCES promotions(id)", [])?;
+        }
+        if !current_columns.contains("promotion_discount") {
+            conn.execute("ALTER TABLE order_items ADD COLUMN promotion_discount REAL DEFAULT 0", [])?;
+        }
+    }
+
+    {
+        // Migrations for purchases
+        let mut stmt = conn.prepare("PRAGMA table_info(purchases)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        if !current_columns.contains("created_by") {
+            conn.execute("ALTER TABLE purchases ADD COLUMN created_by INTEGER", [])?;
+        }
+        if !current_columns.contains("created_by_name") {
+            conn.execute("ALTER TABLE purchases ADD COLUMN created_by_name TEXT", [])?;
+        }
+        if !current_columns.contains("supplier_id") {
+            // One-time: fold historic free-text suppliers into the suppliers table, committed
+            // together with the column like the orders.customer_id backfill
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("ALTER TABLE purchases ADD COLUMN supplier_id INTEGER REFERENCES suppliers(id)", [])?;
+            crate::suppliers::backfill_purchase_suppliers(&tx)?;
+            tx.commit()?;
+        }
+        // Payables: existing purchases were paid in full
+        if !current_columns.contains("paid_amount") {
+            conn.execute("ALTER TABLE purchases ADD COLUMN paid_amount REAL", [])?;
+            conn.execute("UPDATE purchases SET paid_amount = total_amount WHERE paid_amount IS NULL", [])?;
+        }
+        if !current_columns.contains("due_amount") {
+            conn.execute("ALTER TABLE purchases ADD COLUMN due_amount REAL DEFAULT 0", [])?;
+        }
+        if !current_columns.contains("purchase_order_id") {
+            conn.execute("ALTER TABLE purchases ADD COLUMN purchase_order_id INTEGER REFERENCES purchase_orders(id)", [])?;
+        }
+    }
+
+    {
+        // Migrations for expenses
+        let mut stmt = conn.prepare("PRAGMA table_info(expenses)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        if !current_columns.contains("created_by") {
+            conn.execute("ALTER TABLE expenses ADD COLUMN created_by INTEGER", [])?;
+        }
+        if !current_columns.contains("created_by_name") {
+            conn.execute("ALTER TABLE expenses ADD COLUMN created_by_name TEXT", [])?;
+        }
+    }
+
+    {
+        // Migrations for users
+        let mut stmt = conn.prepare("PRAGMA table_info(users)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        if !current_columns.contains("pin") {
+            // Optional quick-switch PIN, hashed like the password
+            conn.execute("ALTER TABLE users ADD COLUMN pin TEXT", [])?;
+        }
+    }
+
+    {
+        // Migrations for activity_logs
+        let mut stmt = conn.prepare("PRAGMA table_info(activity_logs)")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        let mut current_columns = std::collections::HashSet::new();
+        for col_res in rows {
+            current_columns.insert(col_res?);
+        }
+
+        // JSON snapshots written by the backend audit trail
+        if !current_columns.contains("before_data") {
+            conn.execute("ALTER TABLE activity_logs ADD COLUMN before_data TEXT", [])?;
+        }
+        if !current_columns.contains("after_data") {
+            conn.execute("ALTER TABLE activity_logs ADD COLUMN after_data TEXT", [])?;
+        }
+        // Hash chain (see audit.rs)
+        if !current_columns.contains("prev_hash") {
+            conn.execute("ALTER TABLE activity_logs ADD COLUMN prev_hash TEXT", [])?;
+        }
+        if !current_columns.contains("hash") {
+            conn.execute("ALTER TABLE activity_logs ADD COLUMN hash TEXT", [])?;
+        }
+    }
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_customers_phone ON customers(phone_normalized) WHERE phone_normalized IS NOT NULL",
+        [],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_suppliers_phone ON suppliers(phone_normalized) WHERE phone_normalized IS NOT NULL",
+        [],
+    )?;
+    // Customer lookups read a customer's orders and their lines
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_orders_customer ON orders(customer_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_order_items_order ON order_items(order_id)", [])?;
+    crate::audit::seal_unhashed_rows(&conn)?;
 
+    // Default role -> permission matrix (only seeded on first run)
+    crate::permissions::seed_default_roles(&conn)?;
     
     Ok(conn)
 }
+
+/// Reads a single value from the key/value `settings` table.
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    use rusqlite::OptionalExtension;
+    conn.query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| row.get(0)).optional()
+}
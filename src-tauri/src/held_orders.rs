@@ -0,0 +1,131 @@
+use crate::auth::SessionStore;
+use crate::db::{self, Database};
+use crate::models::{HeldCart, HeldOrder, Order, OrderItem};
+use crate::permissions;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Held orders park a cart while the customer steps away. Nothing is reserved: stock
+// and revenue only move when the cart is finalized through create_order, which
+// removes the hold named by `Order::held_order_id`.
+//
+// Setting: held_order_expiry_hours — carts older than this are dropped (0 = never).
+
+const DEFAULT_EXPIRY_HOURS: i64 = 24;
+
+fn expiry_hours(conn: &Connection) -> Result<i64, String> {
+    let value = db::get_setting(conn, "held_order_expiry_hours").map_err(|e| e.to_string())?;
+    Ok(value.and_then(|v| v.trim().parse::<i64>().ok()).unwrap_or(DEFAULT_EXPIRY_HOURS).max(0))
+}
+
+/// Drops held carts older than the configured age, returning that age in hours.
+fn expire(conn: &Connection) -> Result<i64, String> {
+    let hours = expiry_hours(conn)?;
+    if hours > 0 {
+        conn.execute(
+            "DELETE FROM held_orders WHERE held_at < datetime('now', '-' || ?1 || ' hours')",
+            params![hours],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(hours)
+}
+
+/// Removes the hold an order was finalized from. Gone already (expired or finalized
+/// elsewhere) is fine.
+pub fn release(conn: &Connection, held_order_id: Option<i64>) -> Result<(), String> {
+    if let Some(id) = held_order_id {
+        conn.execute("DELETE FROM held_orders WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Parks a cart. Passing the `held_order_id` of a resumed cart replaces that hold.
+#[tauri::command]
+pub fn hold_order(token: String, mut order: Order, items: Vec<OrderItem>, label: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_ORDER)?;
+    expire(&conn)?;
+    if items.is_empty() {
+        return Err("The cart is empty".to_string());
+    }
+
+    let label = label.filter(|l| !l.trim().is_empty());
+    // Approvals are given for the sale at hand, never parked with the cart
+    order.allow_short_stock = None;
+    order.override_pin = None;
+    let cart = serde_json::json!({ "order": order, "items": items }).to_string();
+    let existing = match order.held_order_id {
+        Some(id) => conn.query_row("SELECT id FROM held_orders WHERE id = ?1", params![id], |row| row.get::<_, i64>(0))
+            .optional().map_err(|e| e.to_string())?,
+        None => None,
+    };
+    match existing {
+        Some(id) => {
+            conn.execute(
+                "UPDATE held_orders SET label = COALESCE(?1, label), customer_name = ?2, cart = ?3, item_count = ?4, total = ?5,
+                     held_by = ?6, held_by_name = ?7, held_at = CURRENT_TIMESTAMP
+                 WHERE id = ?8",
+                params![label, order.customer_name, cart, items.len() as i64, order.grand_total, session.user_id, session.username, id],
+            ).map_err(|e| e.to_string())?;
+            Ok(id)
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO held_orders (label, customer_name, cart, item_count, total, held_by, held_by_name) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![label, order.customer_name, cart, items.len() as i64, order.grand_total, session.user_id, session.username],
+            ).map_err(|e| e.to_string())?;
+            Ok(conn.last_insert_rowid())
+        }
+    }
+}
+
+#[tauri::command]
+pub fn list_held_orders(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<HeldOrder>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::CREATE_ORDER)?;
+    let hours = expire(&conn)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, label, customer_name, item_count, total, held_by_name, held_at,
+                CASE WHEN ?1 > 0 THEN datetime(held_at, '+' || ?1 || ' hours') END
+         FROM held_orders ORDER BY held_at, id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![hours], |row| {
+        Ok(HeldOrder {
+            id: row.get(0)?,
+            label: row.get(1)?,
+            customer_name: row.get(2)?,
+            item_count: row.get(3)?,
+            total: row.get(4)?,
+            held_by_name: row.get(5)?,
+            held_at: row.get(6)?,
+            expires_at: row.get(7)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+/// Returns a parked cart to the till. The hold stays until the order is created or
+/// the cart is discarded, so nothing is lost if the sale is abandoned again.
+#[tauri::command]
+pub fn resume_held_order(token: String, held_order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<HeldCart, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::CREATE_ORDER)?;
+    expire(&conn)?;
+
+    let cart: String = conn.query_row("SELECT cart FROM held_orders WHERE id = ?1", params![held_order_id], |row| row.get(0))
+        .optional().map_err(|e| e.to_string())?
+        .ok_or("This held cart no longer exists (it may have expired)")?;
+    let mut value: serde_json::Value = serde_json::from_str(&cart).map_err(|e| e.to_string())?;
+    let mut order: Order = serde_json::from_value(value["order"].take()).map_err(|e| e.to_string())?;
+    let items: Vec<OrderItem> = serde_json::from_value(value["items"].take()).map_err(|e| e.to_string())?;
+    order.held_order_id = Some(held_order_id);
+    Ok(HeldCart { held_order_id, order, items })
+}
+
+#[tauri::command]
+pub fn discard_held_order(token: String, held_order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::CREATE_ORDER)?;
+    release(&conn, Some(held_order_id))
+}
@@ -1,6 +1,23 @@
+mod audit;
+mod auth;
 mod commands;
+mod customers;
 mod db;
+mod held_orders;
+mod loyalty;
 mod models;
+mod order_status;
+mod payments;
+mod permissions;
+mod promotions;
+mod purchase_orders;
+mod purchase_returns;
+mod sales_returns;
+mod shifts;
+mod stock_policy;
+mod suppliers;
+mod tax;
+mod totals;
 mod chat;
 
 use db::Database;
@@ -27,8 +44,13 @@ pub fn run() {
             let conn = db::init_db(app.handle())
                 .expect("failed to init db");
             
+            let sessions = auth::SessionStore::default();
+            let auto_lock = db::get_setting(&conn, "auto_lock_minutes").ok().flatten();
+            sessions.set_auto_lock_minutes(auto_lock.and_then(|v| v.parse().ok()).unwrap_or(0));
+
             // Manage database connection in state
             app.manage(Database { conn: Mutex::new(conn) });
+            app.manage(sessions);
             
             Ok(())
         })
@@ -54,12 +76,24 @@ pub fn run() {
             commands::get_orders,
             commands::get_dashboard_stats,
             commands::get_sales_report,
+            commands::get_sales_returns_report,
+            commands::get_sales_by_cashier,
             commands::get_inventory_report,
             commands::backup_db,
             commands::restore_db,
             commands::get_settings,
             commands::update_settings,
             commands::login,
+            commands::logout,
+            commands::get_session_user,
+            commands::get_locked_accounts,
+            commands::unlock_user,
+            commands::recover_account,
+            commands::regenerate_recovery_codes,
+            audit::get_activity_log_diff,
+            commands::set_pin,
+            commands::switch_user,
+            commands::lock_session,
             commands::get_users,
             commands::create_user,
             commands::delete_user,
@@ -73,8 +107,67 @@ pub fn run() {
             commands::update_user_role,
             commands::log_activity,
             commands::get_activity_logs,
-            commands::delete_activity_logs,
+            audit::verify_activity_log_chain,
+            audit::archive_activity_logs,
             commands::cleanup_database,
+            permissions::get_roles,
+            permissions::get_all_permissions,
+            permissions::create_role,
+            permissions::delete_role,
+            permissions::set_role_permissions,
+            customers::get_customers,
+            customers::find_customer_by_phone,
+            customers::create_customer,
+            customers::update_customer,
+            customers::delete_customer,
+            customers::record_customer_payment,
+            customers::get_customer_balance,
+            customers::get_customer_statement,
+            customers::get_receivables_aging,
+            customers::get_customer_history,
+            customers::get_customer_rfm_report,
+            loyalty::get_loyalty_ledger,
+            loyalty::adjust_loyalty_points,
+            suppliers::get_suppliers,
+            suppliers::create_supplier,
+            suppliers::update_supplier,
+            suppliers::delete_supplier,
+            suppliers::record_supplier_payment,
+            suppliers::get_supplier_statement,
+            suppliers::get_payables_aging,
+            purchase_orders::get_purchase_orders,
+            purchase_orders::get_purchase_order_items,
+            purchase_orders::create_purchase_order,
+            purchase_orders::update_purchase_order,
+            purchase_orders::delete_purchase_order,
+            purchase_orders::set_purchase_order_status,
+            purchase_orders::receive_purchase_order,
+            purchase_orders::get_outstanding_purchase_report,
+            purchase_returns::get_purchase_returns,
+            purchase_returns::create_purchase_return,
+            held_orders::hold_order,
+            held_orders::list_held_orders,
+            held_orders::resume_held_order,
+            held_orders::discard_held_order,
+            order_status::set_order_status,
+            order_status::get_order_status_history,
+            payments::get_order_payments,
+            payments::get_payment_breakdown_report,
+            tax::get_tax_summary_report,
+            promotions::price_cart,
+            promotions::get_promotions,
+            promotions::create_promotion,
+            promotions::update_promotion,
+            promotions::delete_promotion,
+            promotions::get_promotion_report,
+            sales_returns::get_sales_returns,
+            sales_returns::create_sales_return,
+            shifts::get_current_shift,
+            shifts::open_shift,
+            shifts::add_cash_movement,
+            shifts::close_shift,
+            shifts::get_shifts,
+            shifts::get_shift_cash_movements,
             commands::create_expense,
             commands::get_expenses,
             commands::update_expense,
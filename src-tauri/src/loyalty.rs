@@ -0,0 +1,306 @@
+use crate::audit;
+use crate::auth::{Session, SessionStore};
+use crate::db::{self, Database};
+use crate::models::{LoyaltyEntry, Order};
+use crate::permissions;
+use rusqlite::{params, Connection};
+use tauri::State;
+
+// Loyalty points. Rules live in `settings`:
+//   loyalty_enabled        "1" to earn and redeem
+//   loyalty_earn_rate      points earned per currency unit spent (e.g. 0.01 = 1 point per 100)
+//   loyalty_point_value    currency value of one point when redeemed
+//   loyalty_expiry_days    days before earned points expire (0 = never)
+//
+// Every change to a customer's balance is a row in `loyalty_ledger`; the balance is
+// the sum of `points`. Positive rows are lots whose `remaining` is consumed oldest
+// first by redemptions, reversals and expiry, so expiry only ever takes points that
+// were never spent. Redeemed points act as a tender: they reduce what the customer
+// pays, not the sale total.
+
+pub struct Rules {
+    pub enabled: bool,
+    pub earn_rate: f64,
+    pub point_value: f64,
+    pub expiry_days: i64,
+}
+
+pub fn rules(conn: &Connection) -> Result<Rules, String> {
+    let get = |key: &str| db::get_setting(conn, key).map_err(|e| e.to_string());
+    let number = |value: Option<String>| value.and_then(|v| v.trim().parse::<f64>().ok()).unwrap_or(0.0).max(0.0);
+    Ok(Rules {
+        enabled: get("loyalty_enabled")?.as_deref() == Some("1"),
+        earn_rate: number(get("loyalty_earn_rate")?),
+        point_value: number(get("loyalty_point_value")?),
+        expiry_days: number(get("loyalty_expiry_days")?) as i64,
+    })
+}
+
+/// Points redeemed on an order, their value and the points it earns.
+pub struct Checkout {
+    pub redeemed: i64,
+    pub value: f64,
+    pub earned: i64,
+}
+
+/// Current points balance after expiring any lapsed lots.
+pub fn balance(conn: &Connection, customer_id: i64) -> Result<i64, String> {
+    expire_points(conn, customer_id)?;
+    conn.query_row(
+        "SELECT COALESCE(SUM(points), 0) FROM loyalty_ledger WHERE customer_id = ?1",
+        params![customer_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())
+}
+
+/// Validates the points tendered on `order` and works out what the sale earns.
+/// Call after `reverse_order` when editing so the order's own points are back in the balance.
+pub fn checkout(conn: &Connection, order: &Order, customer_id: Option<i64>) -> Result<Checkout, String> {
+    let rules = rules(conn)?;
+    let redeemed = order.points_redeemed.unwrap_or(0);
+    if redeemed < 0 {
+        return Err("Redeemed points cannot be negative".to_string());
+    }
+    if redeemed > 0 {
+        if !rules.enabled {
+            return Err("Loyalty points are not enabled".to_string());
+        }
+        let customer_id = customer_id.ok_or_else(|| "Redeeming points needs a customer".to_string())?;
+        let available = balance(conn, customer_id)?;
+        if redeemed > available {
+            return Err(format!("Customer has only {} points", available));
+        }
+    }
+    let value = redeemed as f64 * rules.point_value;
+    if value > order.grand_total + 0.005 {
+        return Err(format!("Points worth {:.2} exceed the order total", value));
+    }
+    let earned = match customer_id {
+        Some(_) if rules.enabled => ((order.grand_total - value).max(0.0) * rules.earn_rate).floor() as i64,
+        _ => 0,
+    };
+    Ok(Checkout { redeemed, value, earned })
+}
+
+/// Writes the REDEEM and EARN ledger rows for a saved order.
+pub fn post_order(conn: &Connection, session: &Session, order_id: i64, customer_id: Option<i64>, checkout: &Checkout) -> Result<(), String> {
+    let Some(customer_id) = customer_id else { return Ok(()) };
+    if checkout.redeemed > 0 {
+        consume(conn, customer_id, checkout.redeemed, None)?;
+        append(conn, session, customer_id, Some(order_id), "REDEEM", -checkout.redeemed, false, &format!("Redeemed on order #{}", order_id))?;
+    }
+    if checkout.earned > 0 {
+        append(conn, session, customer_id, Some(order_id), "EARN", checkout.earned, true, &format!("Earned on order #{}", order_id))?;
+    }
+    Ok(())
+}
+
+/// Undoes an order's loyalty effect before it is edited or deleted: redeemed points are
+/// returned and earned points taken back. Earned points the customer has already spent
+/// cannot be recovered, so the take-back is limited to the current balance.
+pub fn reverse_order(conn: &Connection, session: &Session, order_id: i64) -> Result<(), String> {
+    let (customer_id, redeemed, earned): (Option<i64>, i64, i64) = conn.query_row(
+        "SELECT customer_id, COALESCE(points_redeemed, 0), COALESCE(points_earned, 0) FROM orders WHERE order_id = ?1",
+        params![order_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).map_err(|e| e.to_string())?;
+    let Some(customer_id) = customer_id else { return Ok(()) };
+
+    if redeemed > 0 {
+        append(conn, session, customer_id, Some(order_id), "REVERSE", redeemed, true, &format!("Redemption on order #{} reversed", order_id))?;
+    }
+    if earned > 0 {
+        let earn_lot: Option<i64> = conn.query_row(
+            "SELECT MAX(id) FROM loyalty_ledger WHERE order_id = ?1 AND entry_type = 'EARN'",
+            params![order_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        let take_back = earned.min(balance(conn, customer_id)?.max(0));
+        if take_back > 0 {
+            consume(conn, customer_id, take_back, earn_lot)?;
+            append(conn, session, customer_id, Some(order_id), "REVERSE", -take_back, false, &format!("Points earned on order #{} reversed", order_id))?;
+        }
+    }
+    conn.execute(
+        "UPDATE orders SET points_redeemed = 0, points_value = 0, points_earned = 0 WHERE order_id = ?1",
+        params![order_id],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Settles the loyalty side of a sales return, in proportion to the value returned so
+/// far: points redeemed on the order are given back for the share of the goods they paid
+/// for, and points the order earned are taken back. Like `reverse_order`, it cannot take
+/// back points already spent. Returns the points given back and their value at checkout,
+/// which the caller must not also refund in money.
+pub fn reverse_return(conn: &Connection, session: &Session, order_id: i64, return_id: i64) -> Result<(i64, f64), String> {
+    let (customer_id, earned, redeemed, points_value, grand_total): (Option<i64>, i64, i64, f64, f64) = conn.query_row(
+        "SELECT customer_id, COALESCE(points_earned, 0), COALESCE(points_redeemed, 0), COALESCE(points_value, 0), COALESCE(grand_total, 0)
+         FROM orders WHERE order_id = ?1",
+        params![order_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    ).map_err(|e| e.to_string())?;
+    let Some(customer_id) = customer_id else { return Ok((0, 0.0)) };
+    if grand_total <= 0.0 {
+        return Ok((0, 0.0));
+    }
+
+    // Worked out on running totals so rounding never moves more points than the order did
+    let (returned, restored, taken_back): (f64, i64, i64) = conn.query_row(
+        "SELECT (SELECT COALESCE(SUM(total_amount), 0) FROM sales_returns WHERE order_id = ?1),
+                (SELECT COALESCE(SUM(points), 0) FROM loyalty_ledger WHERE order_id = ?1 AND entry_type = 'RETURN' AND points > 0),
+                (SELECT COALESCE(-SUM(points), 0) FROM loyalty_ledger WHERE order_id = ?1 AND entry_type = 'RETURN' AND points < 0)",
+        params![order_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).map_err(|e| e.to_string())?;
+    let share = (returned / grand_total).min(1.0);
+
+    let give_back = (redeemed as f64 * share).round() as i64 - restored;
+    let give_back_value = if give_back > 0 {
+        append(conn, session, customer_id, Some(order_id), "RETURN", give_back, true,
+            &format!("Points redeemed on goods returned from order #{} (return #{})", order_id, return_id))?;
+        give_back as f64 * points_value / redeemed as f64
+    } else {
+        0.0
+    };
+
+    let due_back = (earned as f64 * share).round() as i64;
+    let take_back = (due_back - taken_back).min(balance(conn, customer_id)?.max(0));
+    if take_back > 0 {
+        let earn_lot: Option<i64> = conn.query_row(
+            "SELECT MAX(id) FROM loyalty_ledger WHERE order_id = ?1 AND entry_type = 'EARN'",
+            params![order_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        consume(conn, customer_id, take_back, earn_lot)?;
+        append(conn, session, customer_id, Some(order_id), "RETURN", -take_back, false,
+            &format!("Points earned on goods returned from order #{} (return #{})", order_id, return_id))?;
+    }
+    Ok((give_back.max(0), give_back_value))
+}
+
+/// Takes `points` out of the customer's lots, the preferred lot first, then oldest first.
+fn consume(conn: &Connection, customer_id: i64, points: i64, preferred_lot: Option<i64>) -> Result<(), String> {
+    let lots: Vec<(i64, i64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, remaining FROM loyalty_ledger WHERE customer_id = ?1 AND remaining > 0
+             ORDER BY (id = ?2) DESC, COALESCE(expires_at, '9999'), id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![customer_id, preferred_lot.unwrap_or(0)], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    let mut left = points;
+    for (id, remaining) in lots {
+        if left == 0 {
+            break;
+        }
+        let taken = left.min(remaining);
+        conn.execute("UPDATE loyalty_ledger SET remaining = remaining - ?1 WHERE id = ?2", params![taken, id])
+            .map_err(|e| e.to_string())?;
+        left -= taken;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn append(conn: &Connection, session: &Session, customer_id: i64, order_id: Option<i64>, entry_type: &str, points: i64, is_lot: bool, notes: &str) -> Result<(), String> {
+    let expiry_days = rules(conn)?.expiry_days;
+    conn.execute(
+        "INSERT INTO loyalty_ledger (customer_id, order_id, entry_type, points, remaining, balance_after, expires_at, notes, created_by, created_by_name)
+         VALUES (?1, ?2, ?3, ?4, ?5,
+                 (SELECT COALESCE(SUM(points), 0) FROM loyalty_ledger WHERE customer_id = ?1) + ?4,
+                 CASE WHEN ?5 > 0 AND ?6 > 0 THEN datetime('now', 'localtime', '+' || ?6 || ' days') END,
+                 ?7, ?8, ?9)",
+        params![customer_id, order_id, entry_type, points, if is_lot { points } else { 0 }, expiry_days, notes, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Writes an EXPIRE row for every lapsed lot that still has points left.
+fn expire_points(conn: &Connection, customer_id: i64) -> Result<(), String> {
+    let lapsed: Vec<(i64, i64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, remaining FROM loyalty_ledger
+             WHERE customer_id = ?1 AND remaining > 0 AND expires_at IS NOT NULL AND expires_at <= datetime('now', 'localtime')
+             ORDER BY id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![customer_id], |row| Ok((row.get(0)?, row.get(1)?))).map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    for (lot_id, remaining) in lapsed {
+        conn.execute("UPDATE loyalty_ledger SET remaining = 0 WHERE id = ?1", params![lot_id]).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO loyalty_ledger (customer_id, entry_type, points, balance_after, notes, created_by_name)
+             VALUES (?1, 'EXPIRE', ?2, (SELECT COALESCE(SUM(points), 0) FROM loyalty_ledger WHERE customer_id = ?1) + ?2, ?3, 'system')",
+            params![customer_id, -remaining, format!("Points from entry #{} expired", lot_id)],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_loyalty_ledger(token: String, customer_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<LoyaltyEntry>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_CUSTOMERS)?;
+    expire_points(&conn, customer_id)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, customer_id, order_id, entry_type, points, balance_after, expires_at, notes, created_by_name, created_at
+         FROM loyalty_ledger WHERE customer_id = ?1 ORDER BY id DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![customer_id], |row| {
+        Ok(LoyaltyEntry {
+            id: row.get(0)?,
+            customer_id: row.get(1)?,
+            order_id: row.get(2)?,
+            entry_type: row.get(3)?,
+            points: row.get(4)?,
+            balance_after: row.get(5)?,
+            expires_at: row.get(6)?,
+            notes: row.get(7)?,
+            created_by_name: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut entries = Vec::new();
+    for entry in rows {
+        entries.push(entry.map_err(|e| e.to_string())?);
+    }
+    Ok(entries)
+}
+
+/// Manual correction of a customer's points (positive adds a lot, negative takes points away).
+#[tauri::command]
+pub fn adjust_loyalty_points(token: String, customer_id: i64, points: i64, notes: String, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_CUSTOMERS)?;
+    if points == 0 {
+        return Err("Adjustment cannot be zero".to_string());
+    }
+    if notes.trim().is_empty() {
+        return Err("Give a reason for the adjustment".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let available = balance(&tx, customer_id)?;
+    if points < 0 {
+        if -points > available {
+            return Err(format!("Customer has only {} points", available));
+        }
+        consume(&tx, customer_id, -points, None)?;
+    }
+    append(&tx, &session, customer_id, None, "ADJUST", points, points > 0, notes.trim())?;
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        "LOYALTY_ADJUST",
+        "Customer",
+        Some(customer_id),
+        &format!("Adjusted loyalty points by {:+}: {}", points, notes.trim()),
+    )?;
+    let new_balance = balance(&tx, customer_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(new_balance)
+}
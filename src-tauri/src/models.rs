@@ -41,6 +41,37 @@ pub struct Purchase {
     pub total_amount: f64,
     pub notes: Option<String>,
     pub created_at: Option<String>,
+    pub supplier_id: Option<i64>, // Matched or created from supplier_phone / supplier_name when not given
+    pub paid_amount: Option<f64>, // Paid on receipt; None means paid in full
+    pub due_amount: Option<f64>,  // Still owed to the supplier, maintained by the backend
+    pub purchase_order_id: Option<i64>, // Set when the purchase is a receipt against a purchase order
+    pub created_by: Option<i64>, // Set by the backend from the session
+    pub created_by_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Supplier {
+    pub id: Option<i64>,
+    pub supplier_name: String,
+    pub phone_number: Option<String>,
+    pub address: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: Option<String>,
+    pub purchase_count: Option<i64>, // Not a DB column, populated on read
+    pub total_purchased: Option<f64>, // Not a DB column, populated on read
+    pub balance: Option<f64>, // Not a DB column, amount we owe (less unused return credit) populated on read
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SupplierPayment {
+    pub id: Option<i64>,
+    pub supplier_id: i64,
+    pub purchase_id: Option<i64>, // Settle this purchase first; the rest goes to the oldest dues
+    pub amount: f64,
+    pub payment_method: Option<String>,
+    pub payment_date: Option<String>,
+    pub notes: Option<String>,
+    pub created_by_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -55,6 +86,123 @@ pub struct PurchaseItem {
     pub purchase_unit_cost: f64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub id: Option<i64>,
+    pub supplier_id: i64,
+    pub supplier_name: Option<String>, // Not a DB column, populated on read
+    pub status: Option<String>,        // Maintained by the backend
+    pub order_date: Option<String>,
+    pub expected_date: Option<String>,
+    pub notes: Option<String>,
+    pub total_amount: Option<f64>, // Not a DB column, ordered value populated on read
+    pub created_at: Option<String>,
+    pub created_by: Option<i64>,
+    pub created_by_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseOrderItem {
+    pub id: Option<i64>,
+    pub purchase_order_id: Option<i64>,
+    pub product_id: i64,
+    pub product_name: Option<String>, // Not a DB column, populated on read
+    pub quantity_ordered: f64,
+    pub quantity_received: Option<f64>, // Maintained by receipts
+    pub unit_cost: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseOrderReceipt {
+    pub purchase_order_id: i64,
+    pub invoice_number: Option<String>,
+    pub receipt_date: Option<String>,
+    pub paid_amount: Option<f64>, // None means paid in full
+    pub notes: Option<String>,
+    pub lines: Vec<PurchaseOrderReceiptLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseOrderReceiptLine {
+    pub product_id: i64,
+    pub quantity: f64,
+    pub buying_price: Option<f64>, // Defaults to the ordered unit cost
+    pub extra_charge: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutstandingPurchaseItem {
+    pub supplier_id: i64,
+    pub supplier_name: String,
+    pub purchase_order_id: i64,
+    pub status: String,
+    pub order_date: Option<String>,
+    pub expected_date: Option<String>,
+    pub product_id: i64,
+    pub product_name: String,
+    pub quantity_ordered: f64,
+    pub quantity_received: f64,
+    pub quantity_outstanding: f64,
+    pub outstanding_value: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseReturn {
+    pub id: Option<i64>,
+    pub purchase_id: i64,
+    pub supplier_id: Option<i64>,      // Taken from the purchase
+    pub supplier_name: Option<String>, // Not a DB column, populated on read
+    pub return_date: Option<String>,
+    pub total_amount: Option<f64>,     // Computed by the backend from the purchase costs
+    pub credit_applied: Option<f64>,   // Taken off what we owed the supplier
+    pub credit_remaining: Option<f64>, // Credit left over once every due was settled
+    pub reason: Option<String>,
+    pub notes: Option<String>,
+    pub created_by_name: Option<String>,
+    pub items: Vec<PurchaseReturnItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseReturnItem {
+    pub id: Option<i64>,
+    pub purchase_item_id: i64,
+    pub product_id: Option<i64>,       // Taken from the purchase item
+    pub product_name: Option<String>,  // Not a DB column, populated on read
+    pub quantity: f64,
+    pub unit_cost: Option<f64>,        // Landed cost the item was bought at
+    pub subtotal: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SalesReturn {
+    pub id: Option<i64>,
+    pub order_id: i64,
+    pub customer_name: Option<String>, // Not a DB column, populated on read
+    pub return_date: Option<String>,
+    pub total_amount: Option<f64>,     // Computed by the backend from the order's prices
+    pub credit_amount: Option<f64>,    // Taken off what the customer still owed on the order
+    pub refund_amount: Option<f64>,    // Handed back to the customer
+    pub refund_method: Option<String>, // cash / card / mobile
+    pub reason: Option<String>,
+    pub notes: Option<String>,
+    pub created_by_name: Option<String>,
+    pub points_restored: Option<i64>,  // Redeemed loyalty points given back for goods they paid for
+    pub points_value: Option<f64>,     // Value of points_restored, not refunded in money
+    pub items: Vec<SalesReturnItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SalesReturnItem {
+    pub id: Option<i64>,
+    pub order_item_id: i64,
+    pub product_id: Option<i64>,       // Taken from the order item
+    pub product_name: Option<String>,  // Not a DB column, populated on read
+    pub quantity: f64,
+    pub unit_price: Option<f64>,       // Line price (after promotions) less its share of the order discount
+    pub subtotal: Option<f64>,
+    pub restock: bool,                 // false = written off (damaged, expired, ...)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PurchaseItemDetail {
     pub id: Option<i64>,
@@ -66,6 +214,7 @@ pub struct PurchaseItemDetail {
     pub extra_charge: f64,
     pub subtotal: f64,
     pub purchase_unit_cost: f64,
+    pub returned_quantity: Option<f64>, // Not a DB column, already sent back to the supplier
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -79,10 +228,208 @@ pub struct Order {
     pub subtotal: f64,
     pub extra_charge: f64,
     pub delivery_charge: f64,
-    pub discount: f64,
+    pub discount: f64, // Cashier's discount when sent; stored with the order-level promotion added
     pub grand_total: f64,
     pub payment_method: Option<String>,
     pub notes: Option<String>,
+    pub paid_amount: Option<f64>, // Paid at checkout; None means paid in full
+    pub due_amount: Option<f64>,  // Outstanding on account, maintained by the backend
+    pub points_redeemed: Option<i64>, // Loyalty points tendered towards the total
+    pub points_value: Option<f64>,    // Currency value of points_redeemed, set by the backend
+    pub points_earned: Option<i64>,   // Set by the backend from the loyalty rules
+    pub customer_id: Option<i64>, // Matched or created from customer_phone when not given
+    pub shift_id: Option<i64>, // Set by the backend from the open shift
+    pub created_by: Option<i64>, // Set by the backend from the session
+    pub created_by_name: Option<String>,
+    pub status: Option<String>, // Fulfilment status, changed through set_order_status
+    pub held_order_id: Option<i64>, // Not a DB column: the parked cart this order came from
+    pub payments: Option<Vec<OrderPayment>>, // Tenders; when absent the order is paid with payment_method
+    pub change_amount: Option<f64>, // Cash handed back, set by the backend from the tenders
+    pub allow_short_stock: Option<bool>, // Not a DB column: go ahead after a stock shortage was reported
+    pub override_pin: Option<String>,    // Not a DB column: PIN of the manager approving a blocked shortage
+    pub tax_total: Option<f64>,  // Set by the backend from the products' tax rates
+    pub tax_mode: Option<String>, // "inclusive" or "exclusive", set by the backend from settings
+    pub coupon_code: Option<String>,
+    pub promotion_discount: Option<f64>, // Order-level promotion, set by the backend
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockShortage {
+    pub product_id: i64,
+    pub product_name: String,
+    pub requested: f64,
+    pub available: f64,
+}
+
+/// Returned (as JSON in the error string) when a sale would take stock below zero.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockShortageError {
+    pub code: String,  // "insufficient_stock"
+    pub policy: String, // "block" or "warn"
+    pub can_override: bool, // Whether the current user may approve it without a manager PIN
+    pub message: String,
+    pub items: Vec<StockShortage>,
+}
+
+/// Returned (as JSON in the error string) when an amount sent with an order or purchase
+/// does not add up (see totals.rs).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub code: String,  // "validation_failed"
+    pub field: String, // e.g. "grand_total" or "items[2].subtotal"
+    pub expected: Option<f64>,
+    pub received: Option<f64>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderPayment {
+    pub id: Option<i64>,
+    pub order_id: Option<i64>,
+    pub payment_method: String,
+    pub amount: f64,           // Tendered amount when sent; went towards the sale when read back
+    pub tendered: Option<f64>, // Handed over, set by the backend
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeldOrder {
+    pub id: i64,
+    pub label: Option<String>,
+    pub customer_name: Option<String>,
+    pub item_count: i64,
+    pub total: f64,
+    pub held_by_name: Option<String>,
+    pub held_at: String,
+    pub expires_at: Option<String>, // None when held carts never expire
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeldCart {
+    pub held_order_id: i64,
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderStatusChange {
+    pub id: i64,
+    pub order_id: i64,
+    pub from_status: Option<String>, // None for the entry made when the order was taken
+    pub to_status: String,
+    pub notes: Option<String>,
+    pub changed_by_name: Option<String>,
+    pub changed_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Customer {
+    pub id: Option<i64>,
+    pub customer_name: String,
+    pub phone_number: Option<String>,
+    pub address: Option<String>,
+    pub created_at: Option<String>,
+    pub order_count: Option<i64>, // Not a DB column, populated on read
+    pub total_spent: Option<f64>, // Not a DB column, populated on read
+    pub balance: Option<f64>, // Not a DB column, outstanding dues populated on read
+    pub loyalty_points: Option<i64>, // Not a DB column, points balance populated on read
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoyaltyEntry {
+    pub id: i64,
+    pub customer_id: i64,
+    pub order_id: Option<i64>,
+    pub entry_type: String,
+    pub points: i64,
+    pub balance_after: i64,
+    pub expires_at: Option<String>,
+    pub notes: Option<String>,
+    pub created_by_name: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerHistory {
+    pub customer: Option<Customer>,
+    pub phone_normalized: String,
+    pub order_count: i64,
+    pub total_spent: f64,
+    pub total_profit: Option<f64>,
+    pub average_order: f64,
+    pub first_order: Option<String>,
+    pub last_order: Option<String>,
+    pub orders: Vec<SalesReportItem>,
+    pub top_products: Vec<CustomerProductSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerProductSummary {
+    pub product_id: i64,
+    pub product_name: String,
+    pub quantity: f64,
+    pub total: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerRfmItem {
+    pub customer_id: i64,
+    pub customer_name: String,
+    pub phone_number: Option<String>,
+    pub last_order: String,
+    pub recency_days: i64,
+    pub frequency: i64,
+    pub monetary: f64,
+    pub lifetime_profit: Option<f64>,
+    pub r_score: i64, // 1-5, 5 = bought most recently
+    pub f_score: i64,
+    pub m_score: i64,
+    pub segment: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerPayment {
+    pub id: Option<i64>,
+    pub customer_id: i64,
+    pub order_id: Option<i64>, // Settle this order first; the rest goes to the oldest dues
+    pub amount: f64,
+    pub payment_method: Option<String>,
+    pub payment_date: Option<String>,
+    pub notes: Option<String>,
+    pub created_by_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomerBalance {
+    pub customer_id: i64,
+    pub customer_name: String,
+    pub total_sales: f64,
+    pub total_paid: f64,
+    pub balance: f64,
+    pub open_orders: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatementEntry {
+    pub date: Option<String>,
+    pub entry_type: String, // OPENING, SALE / PURCHASE, PAYMENT
+    pub reference: Option<String>,
+    pub description: Option<String>,
+    pub debit: f64,
+    pub credit: f64,
+    pub balance: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgingRow {
+    pub party_id: i64,
+    pub party_name: String,
+    pub phone_number: Option<String>,
+    pub days_0_30: f64,
+    pub days_31_60: f64,
+    pub days_61_90: f64,
+    pub days_90_plus: f64,
+    pub total: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,6 +441,10 @@ pub struct OrderItem {
     pub selling_price: f64,
     pub subtotal: f64,
     pub buying_price_snapshot: Option<f64>,
+    pub tax_rate: Option<f64>,   // Set by the backend from the product
+    pub tax_amount: Option<f64>, // Set by the backend
+    pub promotion_id: Option<i64>,         // Set by the backend from the promotions
+    pub promotion_discount: Option<f64>,   // Taken off the line subtotal, set by the backend
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -105,6 +456,12 @@ pub struct OrderItemDetail {
     pub quantity: f64,
     pub selling_price: f64,
     pub subtotal: f64,
+    pub returned_quantity: Option<f64>, // Not a DB column, already returned by the customer
+    pub tax_rate: Option<f64>,
+    pub tax_amount: Option<f64>,
+    pub promotion_id: Option<i64>,
+    pub promotion_name: Option<String>, // Not a DB column
+    pub promotion_discount: Option<f64>,
 }
 
 
@@ -115,6 +472,12 @@ pub struct DashboardStats {
     pub sales_month: f64,
     pub sales_year: f64,
     pub total_sales: f64,
+
+    // Sales returns, by the date the goods came back
+    pub returns_today: f64,
+    pub returns_month: f64,
+    pub returns_year: f64,
+    pub total_returns: f64,
     
     // Purchases
     pub purchases_today: f64,
@@ -122,14 +485,16 @@ pub struct DashboardStats {
     pub purchases_year: f64,
     pub total_purchases: f64,
     
-    // Profit
-    pub profit_today: f64,
-    pub profit_month: f64,
-    pub profit_year: f64,
-    pub total_profit: f64,
+    // Profit (None when the user lacks the view_profit permission)
+    pub profit_today: Option<f64>,
+    pub profit_month: Option<f64>,
+    pub profit_year: Option<f64>,
+    pub total_profit: Option<f64>,
     
     // Inventory & General
     pub inventory_value: f64,
+    pub total_receivables: f64,
+    pub total_payables: f64,
     pub low_stock_count: i64,
     pub order_count: i64,
     pub product_count: i64,
@@ -143,7 +508,111 @@ pub struct SalesReportItem {
     pub total: f64,
     pub discount: f64,
     pub items_count: i64,
-    pub profit: f64,
+    pub profit: Option<f64>,
+    pub cashier: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SalesReturnReportItem {
+    pub return_id: i64,
+    pub order_id: i64,
+    pub date: String,
+    pub customer: Option<String>,
+    pub total: f64,
+    pub refund_method: Option<String>,
+    pub reason: Option<String>,
+    pub items_count: i64,
+    pub profit: Option<f64>, // Profit given back: the refund less the cost of what was restocked
+    pub cashier: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentBreakdownItem {
+    pub date: String,
+    pub payment_method: String,
+    pub payment_count: i64,
+    pub amount: f64,  // Taken for sales on the day
+    pub refunds: f64, // Paid back for returns on the day
+    pub net: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaxSummaryItem {
+    pub period: String,
+    pub tax_rate: f64,
+    pub taxable_sales: f64,   // Net of tax and discounts
+    pub tax_collected: f64,
+    pub taxable_returns: f64, // Returned in the period, net of tax
+    pub tax_refunded: f64,
+    pub net_taxable: f64,
+    pub net_tax: f64,         // Owed for the period
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Promotion {
+    pub id: Option<i64>,
+    pub name: String,
+    pub scope: String, // "item", "category" or "order"
+    pub kind: String,  // "percent", "fixed" or "bogo" (not for order scope)
+    pub value: f64,    // Percent off, amount off (per unit for lines), or percent off the free units
+    pub product_id: Option<i64>,    // Item scope
+    pub product_name: Option<String>, // Not a DB column, populated on read
+    pub category: Option<String>,   // Category scope
+    pub buy_quantity: Option<f64>,  // Buy X ...
+    pub get_quantity: Option<f64>,  // ... get Y
+    pub min_spend: Option<f64>,     // Cart value before promotions
+    pub start_date: Option<String>, // YYYY-MM-DD, inclusive
+    pub end_date: Option<String>,
+    pub days_of_week: Option<String>, // e.g. "1,2,3", 0 = Sunday
+    pub start_time: Option<String>, // HH:MM; a window may run past midnight
+    pub end_time: Option<String>,
+    pub coupon_code: Option<String>, // None means the promotion applies automatically
+    pub usage_limit: Option<i64>,    // Orders it may be used on
+    pub active: bool,
+    pub created_at: Option<String>,
+    pub times_used: Option<i64>, // Not a DB column, populated on read
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppliedPromotion {
+    pub promotion_id: i64,
+    pub name: String,
+    pub coupon_code: Option<String>,
+    pub discount: f64,
+}
+
+/// A cart priced by the backend, ready to send to create_order / update_order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PricedCart {
+    pub items: Vec<OrderItem>,
+    pub subtotal: f64,           // After line promotions
+    pub promotion_discount: f64, // Order-level promotion
+    pub discount: f64,           // Cashier's discount plus the order-level promotion
+    pub tax_total: f64,
+    pub grand_total: f64,
+    pub promotions: Vec<AppliedPromotion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromotionReportItem {
+    pub promotion_id: i64,
+    pub name: String,
+    pub coupon_code: Option<String>,
+    pub order_count: i64,
+    pub units: f64,    // Units sold with the promotion on their line
+    pub discount: f64, // Given away
+    pub sales: f64,    // Grand total of the orders it was used on
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CashierSalesItem {
+    pub user_id: Option<i64>,
+    pub username: Option<String>, // None for orders taken before attribution existed
+    pub order_count: i64,
+    pub total_sales: f64,
+    pub total_discount: f64,
+    pub average_order: f64,
+    pub profit: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -167,6 +636,36 @@ pub struct User {
     pub created_at: Option<String>,
 }
 
+/// Returned by `setup_admin`; the recovery codes are shown once and never stored in clear.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminSetup {
+    pub session: AuthSession,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginLockout {
+    pub username: String,
+    pub failed_count: i64,
+    pub last_failed_at: Option<String>,
+    pub locked_until: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub token: String,
+    pub user: User,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub description: Option<String>,
+    pub is_system: bool,
+    pub permissions: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BackupInfo {
     pub name: String,
@@ -205,6 +704,53 @@ pub struct ActivityLog {
     pub entity_id: Option<i64>,
     pub description: String,
     pub created_at: Option<String>,
+    pub before_data: Option<String>,   // JSON snapshot before the change (backend-recorded entries)
+    pub after_data: Option<String>,    // JSON snapshot after the change
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogChainStatus {
+    pub valid: bool,
+    pub checked_rows: i64,
+    pub first_broken_id: Option<i64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Shift {
+    pub id: i64,
+    pub status: String, // "open" or "closed"
+    pub opened_by_name: String,
+    pub opened_at: String,
+    pub opening_float: f64,
+    pub closed_by_name: Option<String>,
+    pub closed_at: Option<String>,
+    pub cash_sales: f64,
+    pub cash_in: f64,
+    pub cash_out: f64,
+    pub expected_cash: f64,
+    pub counted_cash: Option<f64>,
+    pub variance: Option<f64>, // counted - expected
+    pub order_count: i64,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CashMovement {
+    pub id: i64,
+    pub shift_id: i64,
+    pub movement_type: String, // "IN" or "OUT"
+    pub amount: f64,
+    pub reason: Option<String>,
+    pub username: String,
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -215,4 +761,6 @@ pub struct Expense {
     pub amount: f64,
     pub notes: Option<String>,
     pub created_at: Option<String>,
+    pub created_by: Option<i64>, // Set by the backend from the session
+    pub created_by_name: Option<String>,
 }
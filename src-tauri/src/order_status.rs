@@ -0,0 +1,128 @@
+use crate::audit;
+use crate::auth::{Session, SessionStore};
+use crate::db::Database;
+use crate::loyalty;
+use crate::models::OrderStatusChange;
+use crate::payments;
+use crate::permissions;
+use crate::sales_returns;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Fulfilment workflow. Online orders start as pending and move forward one step at a
+// time; local orders are handed over at the counter and are simply completed.
+// Cancelled and returned (sent back by the courier) orders are void: their stock is
+// put back, what was paid is refunded, nothing is owed on them and they drop out of
+// sales figures, but the order and its history are kept.
+
+pub const COMPLETED: &str = "completed";
+pub const PENDING: &str = "pending";
+
+// Statuses an order may move to from each status
+const TRANSITIONS: &[(&str, &[&str])] = &[
+    ("pending", &["confirmed", "cancelled"]),
+    ("confirmed", &["packed", "cancelled"]),
+    ("packed", &["shipped", "cancelled"]),
+    ("shipped", &["delivered", "returned"]),
+];
+
+// Statuses that undo the sale
+const VOID_STATUSES: &[&str] = &["cancelled", "returned"];
+
+/// Status a newly taken order starts in.
+pub fn initial_status(order_type: &str) -> &'static str {
+    if order_type.eq_ignore_ascii_case("online") { PENDING } else { COMPLETED }
+}
+
+/// Whether the order was cancelled or returned by the courier.
+pub fn is_void(conn: &Connection, order_id: i64) -> Result<bool, String> {
+    let status: Option<String> = conn.query_row("SELECT status FROM orders WHERE order_id = ?1", params![order_id], |row| row.get(0))
+        .optional().map_err(|e| e.to_string())?;
+    Ok(status.is_some_and(|s| VOID_STATUSES.contains(&s.as_str())))
+}
+
+/// Appends a row to the order's status history.
+pub fn record(conn: &Connection, session: &Session, order_id: i64, from_status: Option<&str>, to_status: &str, notes: Option<&str>) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO order_status_history (order_id, from_status, to_status, notes, changed_by, changed_by_name) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![order_id, from_status, to_status, notes, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Moves an order to `status`, restoring stock and refunding what was paid when the
+/// order is cancelled or returned.
+#[tauri::command]
+pub fn set_order_status(token: String, order_id: i64, status: String, notes: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::EDIT_ORDER)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let status = status.trim().to_lowercase();
+
+    let current: String = tx.query_row("SELECT status FROM orders WHERE order_id = ?1", params![order_id], |row| row.get(0))
+        .optional().map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Sale #{} not found", order_id))?;
+    let allowed = TRANSITIONS.iter().find(|(from, _)| *from == current).map(|(_, to)| *to).unwrap_or(&[]);
+    if !allowed.contains(&status.as_str()) {
+        return Err(if allowed.is_empty() {
+            format!("Sale #{} is {} and its status can no longer change", order_id, current)
+        } else {
+            format!("A {} order can only move to {}", current, allowed.join(" or "))
+        });
+    }
+    let before = audit::snapshot(&tx, "orders", "order_id", order_id)?;
+
+    if VOID_STATUSES.contains(&status.as_str()) {
+        let collected: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM customer_payment_allocations WHERE order_id = ?1",
+            params![order_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        if collected > 0 {
+            return Err("Order has account payments recorded against it and cannot be voided".to_string());
+        }
+        if sales_returns::has_returns(&tx, order_id)? {
+            return Err("Order has goods returned against it and cannot be voided".to_string());
+        }
+        loyalty::reverse_order(&tx, &session, order_id)?;
+        payments::refund_order(&tx, &session, order_id)?;
+        tx.execute(
+            "UPDATE products SET stock_quantity = stock_quantity + (SELECT COALESCE(SUM(oi.quantity), 0) FROM order_items oi WHERE oi.order_id = ?1 AND oi.product_id = products.id),
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id IN (SELECT product_id FROM order_items WHERE order_id = ?1)",
+            params![order_id],
+        ).map_err(|e| e.to_string())?;
+        tx.execute("UPDATE orders SET due_amount = 0 WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    }
+
+    tx.execute("UPDATE orders SET status = ?1 WHERE order_id = ?2", params![status, order_id]).map_err(|e| e.to_string())?;
+    record(&tx, &session, order_id, Some(&current), &status, notes.as_deref().filter(|n| !n.trim().is_empty()))?;
+
+    let after = audit::snapshot(&tx, "orders", "order_id", order_id)?;
+    audit::record_change(&tx, &session, "UPDATE", "Order", Some(order_id), before, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_order_status_history(token: String, order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<OrderStatusChange>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ORDERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, order_id, from_status, to_status, notes, changed_by_name, changed_at
+         FROM order_status_history WHERE order_id = ?1 ORDER BY id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![order_id], |row| {
+        Ok(OrderStatusChange {
+            id: row.get(0)?,
+            order_id: row.get(1)?,
+            from_status: row.get(2)?,
+            to_status: row.get(3)?,
+            notes: row.get(4)?,
+            changed_by_name: row.get(5)?,
+            changed_at: row.get(6)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
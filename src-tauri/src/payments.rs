@@ -0,0 +1,188 @@
+use crate::auth::{Session, SessionStore};
+use crate::db::Database;
+use crate::models::{Order, OrderPayment, PaymentBreakdownItem};
+use crate::permissions;
+use crate::shifts;
+use crate::totals::EPSILON;
+use rusqlite::{params, Connection};
+use tauri::State;
+
+// Split tenders. An order may be paid with several methods, one order_payments row
+// each. Together they must cover what is left after loyalty points, unless the rest
+// goes on a known customer's account (see customers::checkout_amounts). Only cash can
+// be overpaid: the excess is change handed back and is never counted as takings.
+// Orders sent without `payments` are paid with their single `payment_method`.
+// Tenders stay with the shift that took them; when a paid order is voided the money
+// goes back out as `order_refunds` rows in the shift open at the time.
+
+const METHODS: &[&str] = &["cash", "card", "mobile"];
+
+// Summary stored in orders.payment_method when more than one method was used
+const SPLIT: &str = "split";
+
+/// Normalises a payment method and rejects anything but cash, card or mobile, so
+/// every cash payment is found by the shift reconciliation.
+pub fn check_method(method: &str) -> Result<String, String> {
+    let method = method.trim().to_lowercase();
+    if !METHODS.contains(&method.as_str()) {
+        return Err(format!("Unknown payment method '{}'. Use cash, card or mobile", method));
+    }
+    Ok(method)
+}
+
+/// Sum of the tenders sent with an order, if it was sent with any.
+pub fn tendered_total(order: &Order) -> Option<f64> {
+    order.payments.as_ref().filter(|p| !p.is_empty()).map(|p| p.iter().map(|t| t.amount).sum())
+}
+
+/// How an order was paid, worked out from its tenders.
+pub struct Settlement {
+    tenders: Vec<(String, f64, f64, Option<String>)>, // method, towards the sale, tendered, reference
+    pub change: f64,
+    pub payment_method: Option<String>,
+}
+
+/// Validates the tenders for an order of which `paid` goes towards the sale (as worked
+/// out by checkout_amounts) and splits off the change.
+pub fn settle(order: &Order, paid: f64) -> Result<Settlement, String> {
+    let sent: Vec<(String, f64, Option<String>)> = match order.payments.as_ref().filter(|p| !p.is_empty()) {
+        Some(payments) => payments.iter()
+            .map(|t| (t.payment_method.clone(), t.amount, t.reference.clone()))
+            .collect(),
+        None => {
+            let method = order.payment_method.clone().unwrap_or_else(|| "cash".to_string());
+            let tendered = order.paid_amount.unwrap_or(paid);
+            if tendered > EPSILON { vec![(method, tendered, None)] } else { Vec::new() }
+        }
+    };
+
+    let mut tenders = Vec::new();
+    for (method, amount, reference) in sent {
+        let method = check_method(&method)?;
+        if amount <= 0.0 {
+            return Err("Each payment needs an amount above zero".to_string());
+        }
+        let reference = reference.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+        tenders.push((method, amount, amount, reference));
+    }
+
+    let total: f64 = tenders.iter().map(|t| t.2).sum();
+    let change = if total - paid > EPSILON { total - paid } else { 0.0 };
+    let cash: f64 = tenders.iter().filter(|t| t.0 == "cash").map(|t| t.2).sum();
+    if change > cash + EPSILON {
+        return Err("Card and mobile payments cannot be more than the amount due; only cash is given change".to_string());
+    }
+    // Change comes out of the cash tenders, last one first
+    let mut left = change;
+    for tender in tenders.iter_mut().rev().filter(|t| t.0 == "cash") {
+        let back = left.min(tender.1);
+        tender.1 -= back;
+        left -= back;
+    }
+
+    let payment_method = match tenders.first() {
+        Some(first) if tenders.iter().all(|t| t.0 == first.0) => Some(first.0.clone()),
+        Some(_) => Some(SPLIT.to_string()),
+        None => order.payment_method.clone(),
+    };
+    Ok(Settlement { tenders, change, payment_method })
+}
+
+/// Replaces the order's tender rows with the settled ones.
+pub fn record(conn: &Connection, order_id: i64, settlement: &Settlement) -> Result<(), String> {
+    conn.execute("DELETE FROM order_payments WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    for (method, amount, tendered, reference) in &settlement.tenders {
+        conn.execute(
+            "INSERT INTO order_payments (order_id, payment_method, amount, tendered, reference) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![order_id, method, amount, tendered, reference],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Hands back what was paid at checkout for an order being voided, by the method it
+/// was paid with. Cash comes out of the open shift's drawer, so it needs one.
+pub fn refund_order(conn: &Connection, session: &Session, order_id: i64) -> Result<(), String> {
+    let tenders: Vec<(String, f64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT payment_method, SUM(amount) FROM order_payments WHERE order_id = ?1
+             GROUP BY payment_method HAVING SUM(amount) > 0 ORDER BY MIN(id)"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![order_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    if tenders.iter().any(|(method, _)| method == "cash") {
+        shifts::require_open_shift(conn)?;
+    }
+    let shift_id = shifts::open_shift_id(conn)?;
+    for (method, amount) in tenders {
+        conn.execute(
+            "INSERT INTO order_refunds (order_id, refund_method, amount, shift_id, created_by, created_by_name) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![order_id, method, amount, shift_id, session.user_id, session.username],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_order_payments(token: String, order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<OrderPayment>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ORDERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, order_id, payment_method, amount, tendered, reference FROM order_payments WHERE order_id = ?1 ORDER BY id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![order_id], |row| {
+        Ok(OrderPayment {
+            id: Some(row.get(0)?),
+            order_id: Some(row.get(1)?),
+            payment_method: row.get(2)?,
+            amount: row.get(3)?,
+            tendered: Some(row.get(4)?),
+            reference: row.get(5)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+/// Money taken per day and payment method: sale tenders (less change) against refunds
+/// paid for returns and for cancelled or returned orders.
+#[tauri::command]
+pub fn get_payment_breakdown_report(token: String, start_date: String, end_date: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<PaymentBreakdownItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT day, method, SUM(n), SUM(taken), SUM(refunded)
+         FROM (
+            SELECT date(o.order_date) AS day, p.payment_method AS method, 1 AS n, p.amount AS taken, 0 AS refunded
+            FROM order_payments p
+            JOIN orders o ON o.order_id = p.order_id
+            WHERE date(o.order_date) BETWEEN date(?1) AND date(?2)
+            UNION ALL
+            SELECT date(r.return_date), r.refund_method, 0, 0, r.refund_amount
+            FROM sales_returns r
+            WHERE r.refund_amount > 0 AND date(r.return_date) BETWEEN date(?1) AND date(?2)
+            UNION ALL
+            SELECT date(v.refund_date), v.refund_method, 0, 0, v.amount
+            FROM order_refunds v
+            WHERE date(v.refund_date) BETWEEN date(?1) AND date(?2)
+         )
+         GROUP BY day, method
+         ORDER BY day DESC, method"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![start_date, end_date], |row| {
+        let amount: f64 = row.get(3)?;
+        let refunds: f64 = row.get(4)?;
+        Ok(PaymentBreakdownItem {
+            date: row.get(0)?,
+            payment_method: row.get(1)?,
+            payment_count: row.get(2)?,
+            amount,
+            refunds,
+            net: amount - refunds,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
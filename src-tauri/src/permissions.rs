@@ -0,0 +1,284 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::db::Database;
+use crate::models::Role;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use tauri::State;
+
+// Fine-grained permissions. Each command checks exactly one of these through
+// `SessionStore::require_permission`; the role -> permission matrix lives in the
+// `roles` and `role_permissions` tables and can be edited at runtime.
+pub const VIEW_PRODUCTS: &str = "view_products";
+pub const MANAGE_PRODUCTS: &str = "manage_products";
+pub const VIEW_PURCHASES: &str = "view_purchases";
+pub const CREATE_PURCHASE: &str = "create_purchase";
+pub const EDIT_PURCHASE: &str = "edit_purchase";
+pub const DELETE_PURCHASE: &str = "delete_purchase";
+pub const VIEW_SUPPLIERS: &str = "view_suppliers";
+pub const MANAGE_SUPPLIERS: &str = "manage_suppliers";
+pub const PAY_SUPPLIERS: &str = "pay_suppliers";
+pub const VIEW_ORDERS: &str = "view_orders";
+pub const CREATE_ORDER: &str = "create_order";
+pub const EDIT_ORDER: &str = "edit_order";
+pub const DELETE_ORDER: &str = "delete_order";
+pub const MANAGE_SHIFTS: &str = "manage_shifts";
+pub const PROCESS_RETURNS: &str = "process_returns";
+pub const OVERRIDE_STOCK: &str = "override_stock";
+pub const MANAGE_PROMOTIONS: &str = "manage_promotions";
+pub const VIEW_CUSTOMERS: &str = "view_customers";
+pub const MANAGE_CUSTOMERS: &str = "manage_customers";
+pub const RECEIVE_PAYMENTS: &str = "receive_payments";
+pub const VIEW_DASHBOARD: &str = "view_dashboard";
+pub const VIEW_PROFIT: &str = "view_profit";
+pub const VIEW_REPORTS: &str = "view_reports";
+pub const VIEW_SETTINGS: &str = "view_settings";
+pub const MANAGE_SETTINGS: &str = "manage_settings";
+pub const VIEW_BACKUPS: &str = "view_backups";
+pub const MANAGE_BACKUPS: &str = "manage_backups";
+pub const RESTORE_BACKUP: &str = "restore_backup";
+pub const MANAGE_USERS: &str = "manage_users";
+pub const MANAGE_ROLES: &str = "manage_roles";
+pub const VIEW_ACTIVITY_LOG: &str = "view_activity_log";
+pub const ARCHIVE_ACTIVITY_LOG: &str = "archive_activity_log";
+pub const RUN_CLEANUP: &str = "run_cleanup";
+pub const VIEW_EXPENSES: &str = "view_expenses";
+pub const MANAGE_EXPENSES: &str = "manage_expenses";
+pub const USE_CHAT: &str = "use_chat";
+
+pub const ALL_PERMISSIONS: &[&str] = &[
+    VIEW_PRODUCTS, MANAGE_PRODUCTS,
+    VIEW_PURCHASES, CREATE_PURCHASE, EDIT_PURCHASE, DELETE_PURCHASE,
+    VIEW_SUPPLIERS, MANAGE_SUPPLIERS, PAY_SUPPLIERS,
+    VIEW_ORDERS, CREATE_ORDER, EDIT_ORDER, DELETE_ORDER, MANAGE_SHIFTS, PROCESS_RETURNS, OVERRIDE_STOCK, MANAGE_PROMOTIONS,
+    VIEW_CUSTOMERS, MANAGE_CUSTOMERS, RECEIVE_PAYMENTS,
+    VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS,
+    VIEW_SETTINGS, MANAGE_SETTINGS,
+    VIEW_BACKUPS, MANAGE_BACKUPS, RESTORE_BACKUP,
+    MANAGE_USERS, MANAGE_ROLES,
+    VIEW_ACTIVITY_LOG, ARCHIVE_ACTIVITY_LOG, RUN_CLEANUP,
+    VIEW_EXPENSES, MANAGE_EXPENSES,
+    USE_CHAT,
+];
+
+// super_admin always holds every permission so the matrix can never lock the owner out.
+pub const SUPER_ADMIN: &str = "super_admin";
+
+// Default matrix, seeded once when the roles table is empty.
+const DEFAULT_ROLES: &[(&str, &str, &[&str])] = &[
+    ("super_admin", "Full access", ALL_PERMISSIONS),
+    ("admin", "Everything except destructive maintenance", &[
+        VIEW_PRODUCTS, MANAGE_PRODUCTS,
+        VIEW_PURCHASES, CREATE_PURCHASE, EDIT_PURCHASE, DELETE_PURCHASE,
+        VIEW_SUPPLIERS, MANAGE_SUPPLIERS, PAY_SUPPLIERS,
+        VIEW_ORDERS, CREATE_ORDER, EDIT_ORDER, DELETE_ORDER, MANAGE_SHIFTS, PROCESS_RETURNS, OVERRIDE_STOCK, MANAGE_PROMOTIONS,
+        VIEW_CUSTOMERS, MANAGE_CUSTOMERS, RECEIVE_PAYMENTS,
+        VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS,
+        VIEW_SETTINGS, MANAGE_SETTINGS,
+        VIEW_BACKUPS, MANAGE_BACKUPS, RESTORE_BACKUP,
+        MANAGE_USERS, VIEW_ACTIVITY_LOG,
+        VIEW_EXPENSES, MANAGE_EXPENSES, USE_CHAT,
+    ]),
+    ("manager", "Buying, selling and reports", &[
+        VIEW_PRODUCTS, MANAGE_PRODUCTS,
+        VIEW_PURCHASES, CREATE_PURCHASE, EDIT_PURCHASE, DELETE_PURCHASE,
+        VIEW_SUPPLIERS, MANAGE_SUPPLIERS, PAY_SUPPLIERS,
+        VIEW_ORDERS, CREATE_ORDER, EDIT_ORDER, DELETE_ORDER, MANAGE_SHIFTS, PROCESS_RETURNS, OVERRIDE_STOCK, MANAGE_PROMOTIONS,
+        VIEW_CUSTOMERS, MANAGE_CUSTOMERS, RECEIVE_PAYMENTS,
+        VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS, VIEW_ACTIVITY_LOG,
+        VIEW_EXPENSES, MANAGE_EXPENSES, USE_CHAT,
+    ]),
+    ("buy_manager", "Products and purchases", &[
+        VIEW_PRODUCTS, MANAGE_PRODUCTS,
+        VIEW_PURCHASES, CREATE_PURCHASE, EDIT_PURCHASE, DELETE_PURCHASE,
+        VIEW_SUPPLIERS, MANAGE_SUPPLIERS, PAY_SUPPLIERS,
+        VIEW_DASHBOARD, VIEW_EXPENSES, MANAGE_EXPENSES, USE_CHAT,
+    ]),
+    ("sell_manager", "Sales", &[
+        VIEW_PRODUCTS,
+        VIEW_ORDERS, CREATE_ORDER, EDIT_ORDER, DELETE_ORDER, MANAGE_SHIFTS, PROCESS_RETURNS, OVERRIDE_STOCK, MANAGE_PROMOTIONS,
+        VIEW_CUSTOMERS, MANAGE_CUSTOMERS, RECEIVE_PAYMENTS,
+        VIEW_DASHBOARD, VIEW_EXPENSES, MANAGE_EXPENSES, USE_CHAT,
+    ]),
+    ("cashier", "Ring up sales only", &[
+        VIEW_PRODUCTS, VIEW_ORDERS, CREATE_ORDER, MANAGE_SHIFTS, VIEW_CUSTOMERS, RECEIVE_PAYMENTS, VIEW_DASHBOARD,
+    ]),
+    ("report_checker", "Read-only reports", &[
+        VIEW_PRODUCTS, VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS, VIEW_EXPENSES, USE_CHAT,
+    ]),
+    ("inspector", "Read-only reports and activity log", &[
+        VIEW_PRODUCTS, VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS, VIEW_ACTIVITY_LOG, VIEW_EXPENSES, USE_CHAT,
+    ]),
+    ("worker", "Stock lookup and expenses", &[
+        VIEW_PRODUCTS, VIEW_DASHBOARD, VIEW_EXPENSES, MANAGE_EXPENSES, USE_CHAT,
+    ]),
+    ("demo", "Read-only showcase account", &[
+        VIEW_PRODUCTS, VIEW_DASHBOARD, VIEW_PROFIT, VIEW_REPORTS, VIEW_SETTINGS,
+        VIEW_BACKUPS, VIEW_ACTIVITY_LOG, VIEW_EXPENSES, USE_CHAT,
+    ]),
+];
+
+pub fn seed_default_roles(conn: &Connection) -> rusqlite::Result<()> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM roles", [], |row| row.get(0))?;
+    if count == 0 {
+        for (name, description, permissions) in DEFAULT_ROLES {
+            conn.execute(
+                "INSERT INTO roles (name, description, is_system) VALUES (?1, ?2, ?3)",
+                params![name, description, *name == SUPER_ADMIN],
+            )?;
+            for permission in permissions.iter() {
+                conn.execute(
+                    "INSERT INTO role_permissions (role, permission) VALUES (?1, ?2)",
+                    params![name, permission],
+                )?;
+            }
+        }
+    }
+
+    // Keep any free-text roles already assigned to users visible in the matrix (with no permissions)
+    conn.execute(
+        "INSERT OR IGNORE INTO roles (name, description) SELECT DISTINCT role, 'Imported role' FROM users",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn has_permission(conn: &Connection, role: &str, permission: &str) -> Result<bool, String> {
+    if role == SUPER_ADMIN {
+        return Ok(true);
+    }
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM role_permissions WHERE role = ?1 AND permission = ?2",
+        params![role, permission],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    Ok(count > 0)
+}
+
+pub fn permissions_for_role(conn: &Connection, role: &str) -> Result<Vec<String>, String> {
+    if role == SUPER_ADMIN {
+        return Ok(ALL_PERMISSIONS.iter().map(|p| p.to_string()).collect());
+    }
+    let mut stmt = conn.prepare("SELECT permission FROM role_permissions WHERE role = ?1 ORDER BY permission")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![role], |row| row.get::<_, String>(0)).map_err(|e| e.to_string())?;
+
+    let mut permissions = Vec::new();
+    for row in rows {
+        permissions.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(permissions)
+}
+
+/// Captures a role and its permission set for the audit log.
+fn role_snapshot(conn: &Connection, name: &str) -> Result<Option<Value>, String> {
+    let description: Option<Option<String>> = conn.query_row(
+        "SELECT description FROM roles WHERE name = ?1",
+        params![name],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?;
+    let Some(description) = description else {
+        return Ok(None);
+    };
+    Ok(Some(json!({
+        "name": name,
+        "description": description,
+        "permissions": permissions_for_role(conn, name)?,
+    })))
+}
+
+#[tauri::command]
+pub fn get_roles(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Role>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    // The user management screen needs the role list for its dropdowns
+    sessions.require_permission(&conn, &token, MANAGE_USERS)?;
+
+    let mut stmt = conn.prepare("SELECT name, description, is_system FROM roles ORDER BY is_system DESC, name")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Role {
+            name: row.get(0)?,
+            description: row.get(1)?,
+            is_system: row.get(2)?,
+            permissions: Vec::new(),
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut roles = Vec::new();
+    for row in rows {
+        let mut role = row.map_err(|e| e.to_string())?;
+        role.permissions = permissions_for_role(&conn, &role.name)?;
+        roles.push(role);
+    }
+    Ok(roles)
+}
+
+#[tauri::command]
+pub fn get_all_permissions(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<String>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, MANAGE_ROLES)?;
+    Ok(ALL_PERMISSIONS.iter().map(|p| p.to_string()).collect())
+}
+
+#[tauri::command]
+pub fn create_role(token: String, name: String, description: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, MANAGE_ROLES)?;
+
+    let name = name.trim().to_lowercase().replace(' ', "_");
+    if name.is_empty() {
+        return Err("Role name is required".to_string());
+    }
+    conn.execute(
+        "INSERT INTO roles (name, description) VALUES (?1, ?2)",
+        params![name, description],
+    ).map_err(|e| e.to_string())?;
+    let after = role_snapshot(&conn, &name)?;
+    audit::record_change(&conn, &session, "CREATE", "Role", None, None, after)
+}
+
+#[tauri::command]
+pub fn delete_role(token: String, name: String, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, MANAGE_ROLES)?;
+
+    if name == SUPER_ADMIN {
+        return Err("The super admin role cannot be deleted".to_string());
+    }
+    let users: i64 = conn.query_row("SELECT COUNT(*) FROM users WHERE role = ?1", params![name], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if users > 0 {
+        return Err(format!("Role is still assigned to {} user(s)", users));
+    }
+    let before = role_snapshot(&conn, &name)?;
+    conn.execute("DELETE FROM roles WHERE name = ?1", params![name]).map_err(|e| e.to_string())?;
+    audit::record_change(&conn, &session, "DELETE", "Role", None, before, None)
+}
+
+#[tauri::command]
+pub fn set_role_permissions(token: String, role: String, permissions: Vec<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, MANAGE_ROLES)?;
+
+    if role == SUPER_ADMIN {
+        return Err("The super admin role always has every permission".to_string());
+    }
+    if let Some(unknown) = permissions.iter().find(|p| !ALL_PERMISSIONS.contains(&p.as_str())) {
+        return Err(format!("Unknown permission: {}", unknown));
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let before = role_snapshot(&tx, &role)?.ok_or_else(|| "Role not found".to_string())?;
+
+    tx.execute("DELETE FROM role_permissions WHERE role = ?1", params![role]).map_err(|e| e.to_string())?;
+    for permission in permissions {
+        tx.execute(
+            "INSERT OR IGNORE INTO role_permissions (role, permission) VALUES (?1, ?2)",
+            params![role, permission],
+        ).map_err(|e| e.to_string())?;
+    }
+    let after = role_snapshot(&tx, &role)?;
+    audit::record_change(&tx, &session, "UPDATE", "Role", None, Some(before), after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,530 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::customers::non_empty;
+use crate::db::Database;
+use crate::models::{AppliedPromotion, Order, OrderItem, PricedCart, Promotion, PromotionReportItem};
+use crate::permissions;
+use crate::totals;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Promotions take money off a sale, automatically or once their coupon code is entered:
+//   item / category - percent off the line, a fixed amount off each unit, or buy X get Y
+//                     (of every X + Y units on the line, Y get `value` percent off)
+//   order           - percent or a fixed amount off what is left after line promotions
+// A promotion applies only while active, inside its dates, weekdays and hours, once the
+// cart is worth its minimum spend and while it has uses left. Each line gets at most one
+// promotion (the entered coupon's, otherwise the best) and the order at most one
+// order-level promotion on top. Line promotions come off the line subtotal and are kept
+// on the line; the order-level one is added to `orders.discount`. The promotions an
+// order used go into `promotion_redemptions`, which usage limits and the promotions
+// report count, leaving out cancelled and returned orders.
+
+fn round2(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+const PROMOTION_SELECT: &str = "SELECT p.id, p.name, p.scope, p.kind, p.value, p.product_id, pr.product_name, p.category,
+        p.buy_quantity, p.get_quantity, p.min_spend, p.start_date, p.end_date, p.days_of_week, p.start_time, p.end_time,
+        p.coupon_code, p.usage_limit, p.active, p.created_at,
+        (SELECT COUNT(*) FROM promotion_redemptions r JOIN orders o ON o.order_id = r.order_id
+         WHERE r.promotion_id = p.id AND o.status NOT IN ('cancelled', 'returned'))
+     FROM promotions p
+     LEFT JOIN products pr ON pr.id = p.product_id";
+
+fn promotion_from_row(row: &rusqlite::Row) -> rusqlite::Result<Promotion> {
+    Ok(Promotion {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        scope: row.get(2)?,
+        kind: row.get(3)?,
+        value: row.get(4)?,
+        product_id: row.get(5)?,
+        product_name: row.get(6)?,
+        category: row.get(7)?,
+        buy_quantity: row.get(8)?,
+        get_quantity: row.get(9)?,
+        min_spend: row.get(10)?,
+        start_date: row.get(11)?,
+        end_date: row.get(12)?,
+        days_of_week: row.get(13)?,
+        start_time: row.get(14)?,
+        end_time: row.get(15)?,
+        coupon_code: row.get(16)?,
+        usage_limit: row.get(17)?,
+        active: row.get(18)?,
+        created_at: row.get(19)?,
+        times_used: Some(row.get(20)?),
+    })
+}
+
+fn load<P: rusqlite::Params>(conn: &Connection, filter: &str, params: P) -> Result<Vec<Promotion>, String> {
+    let mut stmt = conn.prepare(&format!("{} {}", PROMOTION_SELECT, filter)).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params, promotion_from_row).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+/// Local date, weekday (0 = Sunday) and HH:MM that promotions are checked against.
+struct Moment {
+    date: String,
+    weekday: String,
+    time: String,
+}
+
+/// Now for a new order; an edited order keeps the promotions of the time it was sold.
+fn moment(conn: &Connection, order_id: Option<i64>) -> Result<Moment, String> {
+    let sold_at: Option<String> = match order_id {
+        Some(id) => conn.query_row("SELECT order_date FROM orders WHERE order_id = ?1", params![id], |row| row.get(0))
+            .optional().map_err(|e| e.to_string())?.flatten(),
+        None => None,
+    };
+    conn.query_row(
+        "SELECT date(t), strftime('%w', t), strftime('%H:%M', t)
+         FROM (SELECT COALESCE(datetime(?1, 'localtime'), datetime('now', 'localtime')) AS t)",
+        params![sold_at],
+        |row| Ok(Moment { date: row.get(0)?, weekday: row.get(1)?, time: row.get(2)? }),
+    ).map_err(|e| e.to_string())
+}
+
+/// Why the promotion cannot be used at `at`, if it cannot.
+fn unavailable(promotion: &Promotion, at: &Moment) -> Option<&'static str> {
+    if !promotion.active {
+        return Some("is not active");
+    }
+    if promotion.start_date.as_deref().is_some_and(|start| at.date.as_str() < start) {
+        return Some("has not started yet");
+    }
+    if promotion.end_date.as_deref().is_some_and(|end| at.date.as_str() > end) {
+        return Some("has expired");
+    }
+    if let Some(days) = &promotion.days_of_week {
+        if !days.split(',').any(|day| day == at.weekday) {
+            return Some("is not valid today");
+        }
+    }
+    let after_start = promotion.start_time.as_deref().is_none_or(|start| at.time.as_str() >= start);
+    let before_end = promotion.end_time.as_deref().is_none_or(|end| at.time.as_str() < end);
+    let in_hours = match (&promotion.start_time, &promotion.end_time) {
+        (Some(start), Some(end)) if start > end => after_start || before_end, // Runs past midnight
+        _ => after_start && before_end,
+    };
+    if !in_hours {
+        return Some("is not valid at this time");
+    }
+    None
+}
+
+/// Whether the promotion has uses left, not counting the order being edited.
+fn has_uses_left(conn: &Connection, promotion: &Promotion, order_id: Option<i64>) -> Result<bool, String> {
+    let Some(limit) = promotion.usage_limit else {
+        return Ok(true);
+    };
+    let used: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM promotion_redemptions r JOIN orders o ON o.order_id = r.order_id
+         WHERE r.promotion_id = ?1 AND r.order_id != ?2 AND o.status NOT IN ('cancelled', 'returned')",
+        params![promotion.id, order_id.unwrap_or(0)],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    Ok(used < limit)
+}
+
+/// Looks up an entered coupon and checks it can be used on this cart.
+fn coupon(conn: &Connection, code: &str, at: &Moment, cart_value: f64, order_id: Option<i64>) -> Result<Promotion, String> {
+    let code = code.to_uppercase();
+    let promotion = load(conn, "WHERE p.coupon_code = ?1", params![code])?.pop()
+        .ok_or_else(|| format!("Coupon {} is not valid", code))?;
+    if let Some(reason) = unavailable(&promotion, at) {
+        return Err(format!("Coupon {} {}", code, reason));
+    }
+    if !has_uses_left(conn, &promotion, order_id)? {
+        return Err(format!("Coupon {} has been used up", code));
+    }
+    if let Some(min_spend) = promotion.min_spend.filter(|min| cart_value + 1e-9 < *min) {
+        return Err(format!("Coupon {} needs a spend of at least {:.2}", code, min_spend));
+    }
+    Ok(promotion)
+}
+
+fn covers(promotion: &Promotion, product_id: i64, category: Option<&str>) -> bool {
+    match promotion.scope.as_str() {
+        "item" => promotion.product_id == Some(product_id),
+        "category" => match (promotion.category.as_deref(), category) {
+            (Some(wanted), Some(category)) => wanted.trim().eq_ignore_ascii_case(category.trim()),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn line_discount(promotion: &Promotion, item: &OrderItem, gross: f64) -> f64 {
+    let discount = match promotion.kind.as_str() {
+        "percent" => gross * promotion.value / 100.0,
+        "fixed" => promotion.value * item.quantity,
+        "bogo" => {
+            let group = promotion.buy_quantity.unwrap_or(0.0) + promotion.get_quantity.unwrap_or(0.0);
+            if group > 0.0 {
+                let discounted = ((item.quantity + 1e-9) / group).floor() * promotion.get_quantity.unwrap_or(0.0);
+                discounted * item.selling_price * promotion.value / 100.0
+            } else {
+                0.0
+            }
+        }
+        _ => 0.0,
+    };
+    round2(discount.clamp(0.0, gross.max(0.0)))
+}
+
+fn order_discount(promotion: &Promotion, base: f64) -> f64 {
+    let discount = match promotion.kind.as_str() {
+        "percent" => base * promotion.value / 100.0,
+        "fixed" => promotion.value,
+        _ => 0.0,
+    };
+    round2(discount.clamp(0.0, base.max(0.0)))
+}
+
+fn add(applied: &mut Vec<AppliedPromotion>, promotion: &Promotion, discount: f64) {
+    let id = promotion.id.unwrap_or_default();
+    match applied.iter_mut().find(|a| a.promotion_id == id) {
+        Some(entry) => entry.discount = round2(entry.discount + discount),
+        None => applied.push(AppliedPromotion {
+            promotion_id: id,
+            name: promotion.name.clone(),
+            coupon_code: promotion.coupon_code.clone(),
+            discount,
+        }),
+    }
+}
+
+/// Evaluates the promotions for a cart: sets each line's promotion and the order-level
+/// promotion discount, and returns what each promotion took off. `order_id` is the
+/// order being edited, if any. An entered coupon that cannot be used is an error.
+pub fn apply(conn: &Connection, order: &mut Order, items: &mut [OrderItem], order_id: Option<i64>) -> Result<Vec<AppliedPromotion>, String> {
+    let at = moment(conn, order_id)?;
+    let cart_value = round2(items.iter().map(|item| item.quantity * item.selling_price).sum());
+
+    let mut automatic = Vec::new();
+    for promotion in load(conn, "WHERE p.coupon_code IS NULL AND p.active = 1", [])? {
+        if unavailable(&promotion, &at).is_none()
+            && cart_value + 1e-9 >= promotion.min_spend.unwrap_or(0.0)
+            && has_uses_left(conn, &promotion, order_id)?
+        {
+            automatic.push(promotion);
+        }
+    }
+    let coupon = match non_empty(&order.coupon_code) {
+        Some(code) => Some(coupon(conn, code, &at, cart_value, order_id)?),
+        None => None,
+    };
+    order.coupon_code = coupon.as_ref().and_then(|c| c.coupon_code.clone());
+
+    let mut applied = Vec::new();
+    for item in items.iter_mut() {
+        let category: Option<String> = conn.query_row("SELECT category FROM products WHERE id = ?1", params![item.product_id], |row| row.get(0))
+            .optional().map_err(|e| e.to_string())?.flatten();
+        let gross = round2(item.quantity * item.selling_price);
+        let best = match coupon.as_ref().filter(|c| covers(c, item.product_id, category.as_deref())) {
+            Some(c) => Some((c, line_discount(c, item, gross))),
+            None => automatic.iter()
+                .filter(|p| covers(p, item.product_id, category.as_deref()))
+                .map(|p| (p, line_discount(p, item, gross)))
+                .max_by(|a, b| a.1.total_cmp(&b.1)),
+        };
+        match best.filter(|(_, discount)| *discount > 0.0) {
+            Some((promotion, discount)) => {
+                item.promotion_id = promotion.id;
+                item.promotion_discount = Some(discount);
+                add(&mut applied, promotion, discount);
+            }
+            None => {
+                item.promotion_id = None;
+                item.promotion_discount = Some(0.0);
+            }
+        }
+    }
+
+    // The order-level promotion works on what is left beside the cashier's own discount
+    let remaining: f64 = items.iter()
+        .map(|item| round2(item.quantity * item.selling_price) - item.promotion_discount.unwrap_or(0.0))
+        .sum::<f64>() - order.discount;
+    let best = match coupon.as_ref().filter(|c| c.scope == "order") {
+        Some(c) => Some((c, order_discount(c, remaining))),
+        None => automatic.iter()
+            .filter(|p| p.scope == "order")
+            .map(|p| (p, order_discount(p, remaining)))
+            .max_by(|a, b| a.1.total_cmp(&b.1)),
+    };
+    order.promotion_discount = Some(0.0);
+    if let Some((promotion, discount)) = best.filter(|(_, discount)| *discount > 0.0) {
+        order.promotion_discount = Some(discount);
+        add(&mut applied, promotion, discount);
+    }
+
+    if let Some(c) = &coupon {
+        if !applied.iter().any(|a| Some(a.promotion_id) == c.id) {
+            return Err(format!("Coupon {} does not apply to anything in this cart", c.coupon_code.as_deref().unwrap_or_default()));
+        }
+    }
+    Ok(applied)
+}
+
+/// Replaces the promotions recorded against an order.
+pub fn record(conn: &Connection, order_id: i64, applied: &[AppliedPromotion]) -> Result<(), String> {
+    conn.execute("DELETE FROM promotion_redemptions WHERE order_id = ?1", params![order_id]).map_err(|e| e.to_string())?;
+    for promotion in applied {
+        conn.execute(
+            "INSERT INTO promotion_redemptions (promotion_id, order_id, coupon_code, discount) VALUES (?1, ?2, ?3, ?4)",
+            params![promotion.promotion_id, order_id, promotion.coupon_code, promotion.discount],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Prices a cart with the promotions and tax that create_order / update_order will apply.
+/// Pass `order_id` when pricing an edit of an existing order.
+#[tauri::command]
+pub fn price_cart(token: String, mut order: Order, mut items: Vec<OrderItem>, order_id: Option<i64>, db: State<Database>, sessions: State<SessionStore>) -> Result<PricedCart, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, if order_id.is_some() { permissions::EDIT_ORDER } else { permissions::CREATE_ORDER })?;
+
+    let promotions = apply(&conn, &mut order, &mut items, order_id)?;
+    let tax = totals::price(&conn, &mut order, &mut items)?;
+    for (item, line) in items.iter_mut().zip(&tax.lines) {
+        item.tax_rate = Some(line.rate);
+        item.tax_amount = Some(line.tax);
+    }
+    Ok(PricedCart {
+        items,
+        subtotal: order.subtotal,
+        promotion_discount: order.promotion_discount.unwrap_or(0.0),
+        discount: order.discount,
+        tax_total: tax.total,
+        grand_total: order.grand_total,
+        promotions,
+    })
+}
+
+fn optional_text(value: &Option<String>) -> Option<String> {
+    non_empty(value).map(str::to_string)
+}
+
+fn check_date(conn: &Connection, label: &str, value: &Option<String>) -> Result<Option<String>, String> {
+    let Some(value) = optional_text(value) else {
+        return Ok(None);
+    };
+    let parsed: Option<String> = conn.query_row("SELECT date(?1)", params![value], |row| row.get(0)).map_err(|e| e.to_string())?;
+    if parsed.as_deref() != Some(value.as_str()) {
+        return Err(format!("{} must be a date (YYYY-MM-DD)", label));
+    }
+    Ok(Some(value))
+}
+
+fn check_time(conn: &Connection, label: &str, value: &Option<String>) -> Result<Option<String>, String> {
+    let Some(value) = optional_text(value) else {
+        return Ok(None);
+    };
+    let parsed: Option<String> = conn.query_row("SELECT strftime('%H:%M', '2000-01-01 ' || ?1)", params![value], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if parsed.as_deref() != Some(value.as_str()) {
+        return Err(format!("{} must be a time (HH:MM)", label));
+    }
+    Ok(Some(value))
+}
+
+/// Checks a promotion and tidies it up for saving: trims text, upper-cases the coupon
+/// code and clears the fields its scope and kind do not use.
+fn validate(conn: &Connection, promotion: &mut Promotion, id: Option<i64>) -> Result<(), String> {
+    promotion.name = promotion.name.trim().to_string();
+    if promotion.name.is_empty() {
+        return Err("Promotion name is required".to_string());
+    }
+    match (promotion.scope.as_str(), promotion.kind.as_str()) {
+        ("item" | "category", "percent" | "fixed" | "bogo") | ("order", "percent" | "fixed") => {}
+        ("order", "bogo") => return Err("Buy X get Y needs an item or category to apply to".to_string()),
+        ("item" | "category" | "order", kind) => return Err(format!("Unknown discount type '{}'. Use percent, fixed or bogo", kind)),
+        (scope, _) => return Err(format!("Unknown scope '{}'. Use item, category or order", scope)),
+    }
+    if !promotion.value.is_finite() || promotion.value <= 0.0 {
+        return Err("Discount value must be above zero".to_string());
+    }
+    if promotion.kind != "fixed" && promotion.value > 100.0 {
+        return Err("A percentage cannot be more than 100".to_string());
+    }
+
+    if promotion.scope == "item" {
+        let product_id = promotion.product_id.ok_or("Choose the product the promotion applies to")?;
+        let exists: i64 = conn.query_row("SELECT COUNT(*) FROM products WHERE id = ?1", params![product_id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if exists == 0 {
+            return Err(format!("Product #{} not found", product_id));
+        }
+    } else {
+        promotion.product_id = None;
+    }
+    promotion.category = if promotion.scope == "category" {
+        Some(optional_text(&promotion.category).ok_or("Enter the category the promotion applies to")?)
+    } else {
+        None
+    };
+    if promotion.kind == "bogo" {
+        for quantity in [promotion.buy_quantity, promotion.get_quantity] {
+            if !quantity.is_some_and(|q| q >= 1.0 && q.fract() == 0.0) {
+                return Err("Buy X get Y needs whole quantities of at least 1".to_string());
+            }
+        }
+    } else {
+        promotion.buy_quantity = None;
+        promotion.get_quantity = None;
+    }
+
+    if promotion.min_spend.is_some_and(|min| min < 0.0 || !min.is_finite()) {
+        return Err("Minimum spend cannot be negative".to_string());
+    }
+    promotion.min_spend = promotion.min_spend.filter(|min| *min > 0.0);
+    promotion.start_date = check_date(conn, "Start date", &promotion.start_date)?;
+    promotion.end_date = check_date(conn, "End date", &promotion.end_date)?;
+    if let (Some(start), Some(end)) = (&promotion.start_date, &promotion.end_date) {
+        if start > end {
+            return Err("End date cannot be before the start date".to_string());
+        }
+    }
+    promotion.start_time = check_time(conn, "Start time", &promotion.start_time)?;
+    promotion.end_time = check_time(conn, "End time", &promotion.end_time)?;
+    promotion.days_of_week = match optional_text(&promotion.days_of_week) {
+        Some(days) => {
+            let mut parsed = Vec::new();
+            for day in days.split(',').map(str::trim).filter(|day| !day.is_empty()) {
+                match day.parse::<u8>() {
+                    Ok(day) if day <= 6 => parsed.push(day),
+                    _ => return Err("Days of the week must be numbers from 0 (Sunday) to 6".to_string()),
+                }
+            }
+            parsed.sort_unstable();
+            parsed.dedup();
+            Some(parsed.iter().map(u8::to_string).collect::<Vec<_>>().join(",")).filter(|days| !days.is_empty())
+        }
+        None => None,
+    };
+
+    promotion.coupon_code = optional_text(&promotion.coupon_code).map(|code| code.to_uppercase());
+    if let Some(code) = &promotion.coupon_code {
+        if code.chars().any(char::is_whitespace) {
+            return Err("Coupon codes cannot contain spaces".to_string());
+        }
+        let taken: Option<String> = conn.query_row(
+            "SELECT name FROM promotions WHERE coupon_code = ?1 AND id != ?2",
+            params![code, id.unwrap_or(0)],
+            |row| row.get(0),
+        ).optional().map_err(|e| e.to_string())?;
+        if let Some(name) = taken {
+            return Err(format!("Coupon code {} is already used by \"{}\"", code, name));
+        }
+    }
+    if promotion.usage_limit.is_some_and(|limit| limit < 1) {
+        return Err("Usage limit must be at least 1".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_promotions(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Promotion>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_PROMOTIONS)?;
+    load(&conn, "ORDER BY p.active DESC, p.name COLLATE NOCASE", [])
+}
+
+#[tauri::command]
+pub fn create_promotion(token: String, mut promotion: Promotion, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_PROMOTIONS)?;
+    validate(&conn, &mut promotion, None)?;
+
+    conn.execute(
+        "INSERT INTO promotions (name, scope, kind, value, product_id, category, buy_quantity, get_quantity, min_spend, start_date, end_date, days_of_week, start_time, end_time, coupon_code, usage_limit, active, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        params![promotion.name, promotion.scope, promotion.kind, promotion.value, promotion.product_id, promotion.category,
+            promotion.buy_quantity, promotion.get_quantity, promotion.min_spend, promotion.start_date, promotion.end_date,
+            promotion.days_of_week, promotion.start_time, promotion.end_time, promotion.coupon_code, promotion.usage_limit, promotion.active],
+    ).map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+
+    let after = audit::snapshot(&conn, "promotions", "id", id)?;
+    audit::record_change(&conn, &session, "CREATE", "Promotion", Some(id), None, after)?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn update_promotion(token: String, id: i64, mut promotion: Promotion, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_PROMOTIONS)?;
+    let before = audit::snapshot(&conn, "promotions", "id", id)?;
+    if before.is_none() {
+        return Err(format!("Promotion #{} not found", id));
+    }
+    validate(&conn, &mut promotion, Some(id))?;
+
+    conn.execute(
+        "UPDATE promotions SET name = ?1, scope = ?2, kind = ?3, value = ?4, product_id = ?5, category = ?6, buy_quantity = ?7, get_quantity = ?8,
+            min_spend = ?9, start_date = ?10, end_date = ?11, days_of_week = ?12, start_time = ?13, end_time = ?14, coupon_code = ?15,
+            usage_limit = ?16, active = ?17, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?18",
+        params![promotion.name, promotion.scope, promotion.kind, promotion.value, promotion.product_id, promotion.category,
+            promotion.buy_quantity, promotion.get_quantity, promotion.min_spend, promotion.start_date, promotion.end_date,
+            promotion.days_of_week, promotion.start_time, promotion.end_time, promotion.coupon_code, promotion.usage_limit, promotion.active, id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = audit::snapshot(&conn, "promotions", "id", id)?;
+    audit::record_change(&conn, &session, "UPDATE", "Promotion", Some(id), before, after)?;
+    Ok(())
+}
+
+/// Promotions already used on an order are kept for reporting; deactivate them instead.
+#[tauri::command]
+pub fn delete_promotion(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_PROMOTIONS)?;
+
+    let used: i64 = conn.query_row("SELECT COUNT(*) FROM promotion_redemptions WHERE promotion_id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if used > 0 {
+        return Err(format!("Promotion was used on {} orders and cannot be deleted. Deactivate it instead", used));
+    }
+
+    let before = audit::snapshot(&conn, "promotions", "id", id)?;
+    conn.execute("DELETE FROM promotions WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    audit::record_change(&conn, &session, "DELETE", "Promotion", Some(id), before, None)?;
+    Ok(())
+}
+
+/// What each promotion gave away on orders in the date range, cancelled and returned
+/// orders left out.
+#[tauri::command]
+pub fn get_promotion_report(token: String, start_date: String, end_date: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<PromotionReportItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.name, p.coupon_code, COUNT(*), COALESCE(SUM(r.discount), 0), COALESCE(SUM(o.grand_total), 0),
+                (SELECT COALESCE(SUM(oi.quantity), 0) FROM order_items oi JOIN orders o2 ON o2.order_id = oi.order_id
+                 WHERE oi.promotion_id = p.id AND date(o2.order_date) BETWEEN date(?1) AND date(?2)
+                   AND o2.status NOT IN ('cancelled', 'returned'))
+         FROM promotion_redemptions r
+         JOIN orders o ON o.order_id = r.order_id
+         JOIN promotions p ON p.id = r.promotion_id
+         WHERE date(o.order_date) BETWEEN date(?1) AND date(?2) AND o.status NOT IN ('cancelled', 'returned')
+         GROUP BY p.id
+         ORDER BY 5 DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![start_date, end_date], |row| {
+        Ok(PromotionReportItem {
+            promotion_id: row.get(0)?,
+            name: row.get(1)?,
+            coupon_code: row.get(2)?,
+            order_count: row.get(3)?,
+            discount: row.get(4)?,
+            sales: row.get(5)?,
+            units: row.get(6)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
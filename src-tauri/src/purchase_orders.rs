@@ -0,0 +1,408 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::commands;
+use crate::db::Database;
+use crate::models::{OutstandingPurchaseItem, Purchase, PurchaseItem, PurchaseOrder, PurchaseOrderItem, PurchaseOrderReceipt};
+use crate::permissions;
+use crate::totals::QUANTITY_EPSILON;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use tauri::State;
+
+// Purchase orders record what was ordered from a supplier without touching stock.
+// Goods arrive through receipts: each receipt is posted as an ordinary purchase
+// (linked by `purchases.purchase_order_id`) so stock, weighted-average cost and
+// payables work exactly as for direct buying. Receipt quantities are tracked per
+// product in `purchase_order_items.quantity_received`.
+//
+// Lifecycle: draft -> sent -> partially_received -> received, or closed when the
+// rest will not arrive. Draft and sent orders with nothing received can be cancelled.
+
+const PO_SELECT: &str = "SELECT po.id, po.supplier_id, s.supplier_name, po.status, po.order_date, po.expected_date, po.notes,
+        (SELECT COALESCE(SUM(i.quantity_ordered * i.unit_cost), 0) FROM purchase_order_items i WHERE i.purchase_order_id = po.id),
+        po.created_at, po.created_by, po.created_by_name
+     FROM purchase_orders po
+     JOIN suppliers s ON s.id = po.supplier_id";
+
+fn order_from_row(row: &rusqlite::Row) -> rusqlite::Result<PurchaseOrder> {
+    Ok(PurchaseOrder {
+        id: Some(row.get(0)?),
+        supplier_id: row.get(1)?,
+        supplier_name: row.get(2)?,
+        status: row.get(3)?,
+        order_date: row.get(4)?,
+        expected_date: row.get(5)?,
+        notes: row.get(6)?,
+        total_amount: Some(row.get(7)?),
+        created_at: row.get(8)?,
+        created_by: row.get(9)?,
+        created_by_name: row.get(10)?,
+    })
+}
+
+fn status_of(conn: &Connection, id: i64) -> Result<String, String> {
+    conn.query_row("SELECT status FROM purchase_orders WHERE id = ?1", params![id], |row| row.get(0))
+        .optional().map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Purchase order #{} not found", id))
+}
+
+fn validate(conn: &Connection, order: &PurchaseOrder, items: &[PurchaseOrderItem]) -> Result<(), String> {
+    let supplier: i64 = conn.query_row("SELECT COUNT(*) FROM suppliers WHERE id = ?1", params![order.supplier_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if supplier == 0 {
+        return Err(format!("Supplier #{} not found", order.supplier_id));
+    }
+    if items.is_empty() {
+        return Err("Add at least one product to the purchase order".to_string());
+    }
+    // Receipts are matched to order lines by product, so each product appears once
+    let mut seen = HashSet::new();
+    for item in items {
+        if item.quantity_ordered <= 0.0 {
+            return Err("Ordered quantities must be greater than zero".to_string());
+        }
+        if item.unit_cost < 0.0 {
+            return Err("Unit cost cannot be negative".to_string());
+        }
+        if !seen.insert(item.product_id) {
+            return Err("Each product can only appear once on a purchase order".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn insert_items(conn: &Connection, id: i64, items: &[PurchaseOrderItem]) -> Result<(), String> {
+    for item in items {
+        conn.execute(
+            "INSERT INTO purchase_order_items (purchase_order_id, product_id, quantity_ordered, unit_cost) VALUES (?1, ?2, ?3, ?4)",
+            params![id, item.product_id, item.quantity_ordered, item.unit_cost],
+        ).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Moves an order between sent / partially_received / received from what has
+/// arrived. Closed and cancelled orders are left alone.
+fn refresh_status(conn: &Connection, id: i64) -> Result<(), String> {
+    let (received, outstanding): (f64, f64) = conn.query_row(
+        "SELECT COALESCE(SUM(quantity_received), 0), COALESCE(SUM(MAX(quantity_ordered - quantity_received, 0)), 0)
+         FROM purchase_order_items WHERE purchase_order_id = ?1",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).map_err(|e| e.to_string())?;
+    let status = if outstanding <= QUANTITY_EPSILON {
+        "received"
+    } else if received > QUANTITY_EPSILON {
+        "partially_received"
+    } else {
+        "sent"
+    };
+    conn.execute(
+        "UPDATE purchase_orders SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2 AND status IN ('sent', 'partially_received', 'received')",
+        params![status, id],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Takes a receipt's quantities back off its purchase order before the purchase is
+/// deleted, so they show as outstanding again.
+pub fn reverse_receipt(conn: &Connection, purchase_id: i64) -> Result<(), String> {
+    let purchase_order_id: Option<i64> = conn.query_row(
+        "SELECT purchase_order_id FROM purchases WHERE purchase_id = ?1",
+        params![purchase_id],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?.flatten();
+    let Some(purchase_order_id) = purchase_order_id else {
+        return Ok(());
+    };
+    conn.execute(
+        "UPDATE purchase_order_items SET quantity_received = MAX(quantity_received - COALESCE(
+            (SELECT SUM(pi.quantity) FROM purchase_items pi WHERE pi.purchase_id = ?1 AND pi.product_id = purchase_order_items.product_id), 0), 0)
+         WHERE purchase_order_id = ?2",
+        params![purchase_id, purchase_order_id],
+    ).map_err(|e| e.to_string())?;
+    refresh_status(conn, purchase_order_id)
+}
+
+fn snapshot(conn: &Connection, id: i64) -> Result<Option<serde_json::Value>, String> {
+    audit::snapshot_with_items(conn, "purchase_orders", "id", id, "purchase_order_items", "purchase_order_id")
+}
+
+#[tauri::command]
+pub fn get_purchase_orders(token: String, status: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<PurchaseOrder>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
+
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE ?1 IS NULL OR po.status = ?1 ORDER BY po.order_date DESC, po.id DESC",
+        PO_SELECT
+    )).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![status], order_from_row).map_err(|e| e.to_string())?;
+
+    let mut orders = Vec::new();
+    for order in rows {
+        orders.push(order.map_err(|e| e.to_string())?);
+    }
+    Ok(orders)
+}
+
+#[tauri::command]
+pub fn get_purchase_order_items(token: String, purchase_order_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<PurchaseOrderItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT i.id, i.purchase_order_id, i.product_id, p.product_name, i.quantity_ordered, i.quantity_received, i.unit_cost
+         FROM purchase_order_items i
+         JOIN products p ON p.id = i.product_id
+         WHERE i.purchase_order_id = ?1
+         ORDER BY i.id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![purchase_order_id], |row| {
+        Ok(PurchaseOrderItem {
+            id: Some(row.get(0)?),
+            purchase_order_id: Some(row.get(1)?),
+            product_id: row.get(2)?,
+            product_name: row.get(3)?,
+            quantity_ordered: row.get(4)?,
+            quantity_received: Some(row.get(5)?),
+            unit_cost: row.get(6)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut items = Vec::new();
+    for item in rows {
+        items.push(item.map_err(|e| e.to_string())?);
+    }
+    Ok(items)
+}
+
+#[tauri::command]
+pub fn create_purchase_order(token: String, order: PurchaseOrder, items: Vec<PurchaseOrderItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    validate(&tx, &order, &items)?;
+    tx.execute(
+        "INSERT INTO purchase_orders (supplier_id, status, order_date, expected_date, notes, created_by, created_by_name, updated_at)
+         VALUES (?1, 'draft', COALESCE(?2, CURRENT_TIMESTAMP), ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)",
+        params![order.supplier_id, order.order_date, order.expected_date, order.notes, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid();
+    insert_items(&tx, id, &items)?;
+
+    let after = snapshot(&tx, id)?;
+    audit::record_change(&tx, &session, "CREATE", "PurchaseOrder", Some(id), None, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+/// Only drafts can be edited; once sent the order is what the supplier has.
+#[tauri::command]
+pub fn update_purchase_order(token: String, id: i64, order: PurchaseOrder, items: Vec<PurchaseOrderItem>, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::EDIT_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    if status_of(&tx, id)? != "draft" {
+        return Err("Only draft purchase orders can be edited".to_string());
+    }
+    validate(&tx, &order, &items)?;
+    let before = snapshot(&tx, id)?;
+
+    tx.execute(
+        "UPDATE purchase_orders SET supplier_id = ?1, order_date = COALESCE(?2, order_date), expected_date = ?3, notes = ?4, updated_at = CURRENT_TIMESTAMP WHERE id = ?5",
+        params![order.supplier_id, order.order_date, order.expected_date, order.notes, id],
+    ).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM purchase_order_items WHERE purchase_order_id = ?1", params![id]).map_err(|e| e.to_string())?;
+    insert_items(&tx, id, &items)?;
+
+    let after = snapshot(&tx, id)?;
+    audit::record_change(&tx, &session, "UPDATE", "PurchaseOrder", Some(id), before, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_purchase_order(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::DELETE_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let status = status_of(&tx, id)?;
+    if status != "draft" && status != "cancelled" {
+        return Err("Only draft or cancelled purchase orders can be deleted".to_string());
+    }
+    let before = snapshot(&tx, id)?;
+    tx.execute("DELETE FROM purchase_order_items WHERE purchase_order_id = ?1", params![id]).map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM purchase_orders WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+
+    audit::record_change(&tx, &session, "DELETE", "PurchaseOrder", Some(id), before, None)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Manual status changes: `sent` (from draft), `closed` (stop waiting for the rest of
+/// a sent or partially received order) and `cancelled` (nothing received yet).
+#[tauri::command]
+pub fn set_purchase_order_status(token: String, id: i64, status: String, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let current = status_of(&tx, id)?;
+    let allowed = match status.as_str() {
+        "sent" => current == "draft",
+        "closed" => current == "sent" || current == "partially_received",
+        "cancelled" => current == "draft" || current == "sent",
+        _ => return Err(format!("Unknown purchase order status: {}", status)),
+    };
+    if !allowed {
+        return Err(format!("Cannot change a {} purchase order to {}", current.replace('_', " "), status));
+    }
+    let before = snapshot(&tx, id)?;
+    tx.execute(
+        "UPDATE purchase_orders SET status = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+        params![status, id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = snapshot(&tx, id)?;
+    audit::record_change(&tx, &session, "UPDATE", "PurchaseOrder", Some(id), before, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Posts the goods that arrived as a purchase against the order. Only the received
+/// quantities go into stock (weighted-average cost as for any purchase); what is
+/// left stays outstanding on the order.
+#[tauri::command]
+pub fn receive_purchase_order(token: String, receipt: PurchaseOrderReceipt, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::CREATE_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let id = receipt.purchase_order_id;
+
+    let status = status_of(&tx, id)?;
+    if status != "sent" && status != "partially_received" {
+        return Err(format!("Cannot receive against a {} purchase order", status.replace('_', " ")));
+    }
+    let before = snapshot(&tx, id)?;
+
+    let mut items = Vec::new();
+    for line in receipt.lines.iter().filter(|line| line.quantity != 0.0) {
+        if line.quantity < 0.0 {
+            return Err("Received quantities cannot be negative".to_string());
+        }
+        let (product_name, ordered, received, unit_cost): (String, f64, f64, f64) = tx.query_row(
+            "SELECT p.product_name, i.quantity_ordered, i.quantity_received, i.unit_cost
+             FROM purchase_order_items i JOIN products p ON p.id = i.product_id
+             WHERE i.purchase_order_id = ?1 AND i.product_id = ?2",
+            params![id, line.product_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).optional().map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Product #{} is not on purchase order #{}", line.product_id, id))?;
+        let outstanding = ordered - received;
+        if line.quantity > outstanding + QUANTITY_EPSILON {
+            return Err(format!("Only {} of {} are still outstanding on this order", outstanding.max(0.0), product_name));
+        }
+
+        let buying_price = line.buying_price.unwrap_or(unit_cost);
+        let extra_charge = line.extra_charge.unwrap_or(0.0);
+        let subtotal = line.quantity * buying_price + extra_charge;
+        items.push(PurchaseItem {
+            id: None,
+            purchase_id: None,
+            product_id: line.product_id,
+            quantity: line.quantity,
+            buying_price,
+            extra_charge,
+            subtotal,
+            purchase_unit_cost: subtotal / line.quantity,
+        });
+        tx.execute(
+            "UPDATE purchase_order_items SET quantity_received = quantity_received + ?1 WHERE purchase_order_id = ?2 AND product_id = ?3",
+            params![line.quantity, id, line.product_id],
+        ).map_err(|e| e.to_string())?;
+    }
+    if items.is_empty() {
+        return Err("Enter the quantities received".to_string());
+    }
+
+    let (supplier_id, supplier_name, supplier_phone): (i64, String, Option<String>) = tx.query_row(
+        "SELECT s.id, s.supplier_name, s.phone_number FROM purchase_orders po JOIN suppliers s ON s.id = po.supplier_id WHERE po.id = ?1",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).map_err(|e| e.to_string())?;
+    let purchase = Purchase {
+        purchase_id: None,
+        supplier_name: Some(supplier_name),
+        supplier_phone,
+        invoice_number: receipt.invoice_number.clone(),
+        purchase_date: receipt.receipt_date.clone(),
+        total_amount: items.iter().map(|item| item.subtotal).sum(),
+        notes: Some(match &receipt.notes {
+            Some(notes) if !notes.trim().is_empty() => format!("PO #{}: {}", id, notes.trim()),
+            _ => format!("PO #{}", id),
+        }),
+        created_at: None,
+        supplier_id: Some(supplier_id),
+        paid_amount: receipt.paid_amount,
+        due_amount: None,
+        purchase_order_id: Some(id),
+        created_by: None,
+        created_by_name: None,
+    };
+    let purchase_id = commands::post_purchase(&tx, &session, &purchase, &items, Some(id))?;
+    refresh_status(&tx, id)?;
+
+    let after = snapshot(&tx, id)?;
+    audit::record_change(&tx, &session, "UPDATE", "PurchaseOrder", Some(id), before, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(purchase_id)
+}
+
+/// Quantities ordered but not yet received on open orders, grouped by supplier.
+#[tauri::command]
+pub fn get_outstanding_purchase_report(token: String, supplier_id: Option<i64>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<OutstandingPurchaseItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.supplier_name, po.id, po.status, po.order_date, po.expected_date, p.id, p.product_name,
+                i.quantity_ordered, i.quantity_received, i.unit_cost
+         FROM purchase_order_items i
+         JOIN purchase_orders po ON po.id = i.purchase_order_id
+         JOIN suppliers s ON s.id = po.supplier_id
+         JOIN products p ON p.id = i.product_id
+         WHERE po.status IN ('sent', 'partially_received')
+           AND i.quantity_ordered - i.quantity_received > ?1
+           AND (?2 IS NULL OR s.id = ?2)
+         ORDER BY s.supplier_name COLLATE NOCASE, po.expected_date IS NULL, po.expected_date, po.id, i.id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![QUANTITY_EPSILON, supplier_id], |row| {
+        let ordered: f64 = row.get(8)?;
+        let received: f64 = row.get(9)?;
+        let unit_cost: f64 = row.get(10)?;
+        Ok(OutstandingPurchaseItem {
+            supplier_id: row.get(0)?,
+            supplier_name: row.get(1)?,
+            purchase_order_id: row.get(2)?,
+            status: row.get(3)?,
+            order_date: row.get(4)?,
+            expected_date: row.get(5)?,
+            product_id: row.get(6)?,
+            product_name: row.get(7)?,
+            quantity_ordered: ordered,
+            quantity_received: received,
+            quantity_outstanding: ordered - received,
+            outstanding_value: (ordered - received) * unit_cost,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut report = Vec::new();
+    for row in rows {
+        report.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(report)
+}
@@ -0,0 +1,275 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::db::Database;
+use crate::models::{PurchaseReturn, PurchaseReturnItem};
+use crate::permissions;
+use crate::totals::EPSILON;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Purchase returns send part of a received purchase back to the supplier. Stock goes
+// down at the landed unit cost the items were bought at, which takes exactly that
+// value back out of the moving-average cost. The return's value is credited against
+// what we owe the supplier: the returned purchase first, then the oldest dues; any
+// credit left over stays on the return as `credit_remaining` and is used up by the
+// supplier's next purchases on credit. Every application is recorded in
+// `purchase_return_allocations`, which `suppliers::payable_amounts` counts like a payment.
+
+/// Takes units out of stock at the cost they came in at, so the moving average goes
+/// back to what it was before they arrived. Once nothing is left in stock there is
+/// no value to average, so the last cost is kept.
+pub fn remove_at_cost(conn: &Connection, product_id: i64, quantity: f64, unit_cost: f64) -> Result<(), String> {
+    let (stock, average_cost): (f64, f64) = conn.query_row(
+        "SELECT stock_quantity, buying_price FROM products WHERE id = ?1",
+        params![product_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).map_err(|e| e.to_string())?;
+    let remaining = stock - quantity;
+    let new_average_cost = if remaining > 1e-9 {
+        ((stock * average_cost - quantity * unit_cost) / remaining).max(0.0)
+    } else {
+        average_cost
+    };
+    conn.execute(
+        "UPDATE products SET stock_quantity = ?1, buying_price = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?3",
+        params![remaining, new_average_cost, product_id],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Applies return credit to a purchase's due and records where it went.
+fn allocate(conn: &Connection, return_id: i64, purchase_id: i64, due: f64, amount: f64) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO purchase_return_allocations (return_id, purchase_id, amount) VALUES (?1, ?2, ?3)",
+        params![return_id, purchase_id, amount],
+    ).map_err(|e| e.to_string())?;
+    let left = due - amount;
+    conn.execute(
+        "UPDATE purchases SET due_amount = ?1 WHERE purchase_id = ?2",
+        params![if left > EPSILON { left } else { 0.0 }, purchase_id],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Settles a purchase's due from the supplier's unused return credit, oldest return first.
+pub fn apply_open_credit(conn: &Connection, purchase_id: i64) -> Result<(), String> {
+    let (supplier_id, mut due): (Option<i64>, f64) = conn.query_row(
+        "SELECT supplier_id, due_amount FROM purchases WHERE purchase_id = ?1",
+        params![purchase_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).map_err(|e| e.to_string())?;
+    let Some(supplier_id) = supplier_id else {
+        return Ok(());
+    };
+    if due <= EPSILON {
+        return Ok(());
+    }
+
+    let credits: Vec<(i64, f64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, credit_remaining FROM purchase_returns WHERE supplier_id = ?1 AND credit_remaining > 0
+             ORDER BY datetime(return_date), id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![supplier_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    for (return_id, credit) in credits {
+        if due <= EPSILON {
+            break;
+        }
+        let applied = credit.min(due);
+        allocate(conn, return_id, purchase_id, due, applied)?;
+        let left = credit - applied;
+        conn.execute(
+            "UPDATE purchase_returns SET credit_applied = credit_applied + ?1, credit_remaining = ?2 WHERE id = ?3",
+            params![applied, if left > EPSILON { left } else { 0.0 }, return_id],
+        ).map_err(|e| e.to_string())?;
+        due -= applied;
+    }
+    Ok(())
+}
+
+/// Hands return credit applied to a purchase back to its returns, e.g. when the
+/// purchase is deleted. The credit becomes available for the supplier's other dues.
+pub fn release_credit(conn: &Connection, purchase_id: i64) -> Result<(), String> {
+    let allocations: Vec<(i64, f64)> = {
+        let mut stmt = conn.prepare("SELECT return_id, amount FROM purchase_return_allocations WHERE purchase_id = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![purchase_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    for (return_id, amount) in allocations {
+        conn.execute(
+            "UPDATE purchase_returns SET credit_applied = credit_applied - ?1, credit_remaining = credit_remaining + ?1 WHERE id = ?2",
+            params![amount, return_id],
+        ).map_err(|e| e.to_string())?;
+    }
+    conn.execute("DELETE FROM purchase_return_allocations WHERE purchase_id = ?1", params![purchase_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Whether a purchase has goods returned against it. Such purchases can no longer be
+/// edited or deleted since the returns were costed from them.
+pub fn has_returns(conn: &Connection, purchase_id: i64) -> Result<bool, String> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM purchase_returns WHERE purchase_id = ?1", params![purchase_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    Ok(count > 0)
+}
+
+fn items_of(conn: &Connection, return_id: i64) -> Result<Vec<PurchaseReturnItem>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT ri.id, ri.purchase_item_id, ri.product_id, p.product_name, ri.quantity, ri.unit_cost, ri.subtotal
+         FROM purchase_return_items ri
+         JOIN products p ON p.id = ri.product_id
+         WHERE ri.return_id = ?1
+         ORDER BY ri.id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![return_id], |row| {
+        Ok(PurchaseReturnItem {
+            id: Some(row.get(0)?),
+            purchase_item_id: row.get(1)?,
+            product_id: Some(row.get(2)?),
+            product_name: row.get(3)?,
+            quantity: row.get(4)?,
+            unit_cost: Some(row.get(5)?),
+            subtotal: Some(row.get(6)?),
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_purchase_returns(token: String, purchase_id: Option<i64>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<PurchaseReturn>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_PURCHASES)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.purchase_id, r.supplier_id, s.supplier_name, r.return_date, r.total_amount, r.credit_applied, r.credit_remaining,
+                r.reason, r.notes, r.created_by_name
+         FROM purchase_returns r
+         LEFT JOIN suppliers s ON s.id = r.supplier_id
+         WHERE ?1 IS NULL OR r.purchase_id = ?1
+         ORDER BY r.return_date DESC, r.id DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![purchase_id], |row| {
+        Ok(PurchaseReturn {
+            id: Some(row.get(0)?),
+            purchase_id: row.get(1)?,
+            supplier_id: row.get(2)?,
+            supplier_name: row.get(3)?,
+            return_date: row.get(4)?,
+            total_amount: Some(row.get(5)?),
+            credit_applied: Some(row.get(6)?),
+            credit_remaining: Some(row.get(7)?),
+            reason: row.get(8)?,
+            notes: row.get(9)?,
+            created_by_name: row.get(10)?,
+            items: Vec::new(),
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut returns = Vec::new();
+    for ret in rows {
+        let mut ret = ret.map_err(|e| e.to_string())?;
+        ret.items = items_of(&conn, ret.id.unwrap_or(0))?;
+        returns.push(ret);
+    }
+    Ok(returns)
+}
+
+/// Returns items of a purchase to its supplier. Quantities are limited to what was
+/// bought less earlier returns, and to what is still in stock.
+#[tauri::command]
+pub fn create_purchase_return(token: String, purchase_return: PurchaseReturn, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::EDIT_PURCHASE)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let purchase_id = purchase_return.purchase_id;
+
+    let supplier_id: Option<i64> = tx.query_row(
+        "SELECT supplier_id FROM purchases WHERE purchase_id = ?1",
+        params![purchase_id],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Purchase #{} not found", purchase_id))?;
+    let supplier_id = supplier_id.ok_or("Link the purchase to a supplier before returning goods")?;
+    if purchase_return.items.iter().all(|item| item.quantity == 0.0) {
+        return Err("Enter the quantities being returned".to_string());
+    }
+
+    tx.execute(
+        "INSERT INTO purchase_returns (purchase_id, supplier_id, return_date, total_amount, reason, notes, created_by, created_by_name)
+         VALUES (?1, ?2, COALESCE(?3, CURRENT_TIMESTAMP), 0, ?4, ?5, ?6, ?7)",
+        params![purchase_id, supplier_id, purchase_return.return_date, purchase_return.reason, purchase_return.notes, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let return_id = tx.last_insert_rowid();
+
+    let mut total = 0.0;
+    for item in purchase_return.items.iter().filter(|item| item.quantity != 0.0) {
+        if item.quantity < 0.0 {
+            return Err("Returned quantities cannot be negative".to_string());
+        }
+        let (product_id, bought, unit_cost, returned): (i64, f64, f64, f64) = tx.query_row(
+            "SELECT pi.product_id, pi.quantity,
+                    CASE WHEN pi.purchase_unit_cost > 0 THEN pi.purchase_unit_cost ELSE pi.subtotal / pi.quantity END,
+                    (SELECT COALESCE(SUM(ri.quantity), 0) FROM purchase_return_items ri WHERE ri.purchase_item_id = pi.id)
+             FROM purchase_items pi WHERE pi.id = ?1 AND pi.purchase_id = ?2",
+            params![item.purchase_item_id, purchase_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).optional().map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Item #{} is not on purchase #{}", item.purchase_item_id, purchase_id))?;
+        let (product_name, stock): (String, f64) = tx.query_row(
+            "SELECT product_name, stock_quantity FROM products WHERE id = ?1",
+            params![product_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).map_err(|e| e.to_string())?;
+        if item.quantity > bought - returned + 1e-9 {
+            return Err(format!("Only {} of {} can still be returned from this purchase", bought - returned, product_name));
+        }
+        if item.quantity > stock + 1e-9 {
+            return Err(format!("Only {} of {} is in stock to return", stock.max(0.0), product_name));
+        }
+
+        remove_at_cost(&tx, product_id, item.quantity, unit_cost)?;
+
+        let subtotal = item.quantity * unit_cost;
+        tx.execute(
+            "INSERT INTO purchase_return_items (return_id, purchase_item_id, product_id, quantity, unit_cost, subtotal) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![return_id, item.purchase_item_id, product_id, item.quantity, unit_cost, subtotal],
+        ).map_err(|e| e.to_string())?;
+        total += subtotal;
+    }
+
+    // Credit the supplier: this purchase's due first, then the oldest dues
+    let dues: Vec<(i64, f64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT purchase_id, due_amount FROM purchases WHERE supplier_id = ?1 AND due_amount > 0
+             ORDER BY (purchase_id = ?2) DESC, datetime(purchase_date), purchase_id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![supplier_id, purchase_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    let mut credit = total;
+    for (due_purchase_id, due) in dues {
+        if credit <= EPSILON {
+            break;
+        }
+        let applied = credit.min(due);
+        allocate(&tx, return_id, due_purchase_id, due, applied)?;
+        credit -= applied;
+    }
+    let credit_remaining = if credit > EPSILON { credit } else { 0.0 };
+    tx.execute(
+        "UPDATE purchase_returns SET total_amount = ?1, credit_applied = ?2, credit_remaining = ?3 WHERE id = ?4",
+        params![total, total - credit_remaining, credit_remaining, return_id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = audit::snapshot_with_items(&tx, "purchase_returns", "id", return_id, "purchase_return_items", "return_id")?;
+    audit::record_change(&tx, &session, "CREATE", "PurchaseReturn", Some(return_id), None, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(return_id)
+}
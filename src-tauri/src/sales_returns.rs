@@ -0,0 +1,221 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::db::Database;
+use crate::loyalty;
+use crate::models::{SalesReturn, SalesReturnItem};
+use crate::order_status;
+use crate::permissions;
+use crate::totals::EPSILON;
+use crate::shifts;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Sales returns take goods back from an order without rewriting it, so the original
+// sale and the profit already reported stay as they were. Each unit is valued at its
+// share of the line subtotal (so after any line promotion) less its share of the order
+// discount; extra and delivery charges are not refunded. Tax charged on top of the price
+// (exclusive mode) is refunded with it.
+// The share of the value that was paid with loyalty points goes back as points; of the
+// rest, whatever the customer still owes on the order is cancelled first and the
+// remainder is paid back by the chosen refund method. Loyalty points the order earned
+// are taken back in proportion to the value returned.
+//
+// Restocked lines go back on the shelf at the cost they were sold at, which is also
+// what comes back out of COGS. Written-off lines leave stock alone and their cost
+// stays in COGS as a loss.
+
+const REFUND_METHODS: &[&str] = &["cash", "card", "mobile"];
+
+/// Whether an order has goods returned against it. Such orders can no longer be
+/// edited or deleted since the returns were priced from their lines.
+pub fn has_returns(conn: &Connection, order_id: i64) -> Result<bool, String> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM sales_returns WHERE order_id = ?1", params![order_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    Ok(count > 0)
+}
+
+fn items_of(conn: &Connection, return_id: i64) -> Result<Vec<SalesReturnItem>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT ri.id, ri.order_item_id, ri.product_id, p.product_name, ri.quantity, ri.unit_price, ri.subtotal, ri.restock
+         FROM sales_return_items ri
+         JOIN products p ON p.id = ri.product_id
+         WHERE ri.return_id = ?1
+         ORDER BY ri.id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![return_id], |row| {
+        Ok(SalesReturnItem {
+            id: Some(row.get(0)?),
+            order_item_id: row.get(1)?,
+            product_id: Some(row.get(2)?),
+            product_name: row.get(3)?,
+            quantity: row.get(4)?,
+            unit_price: Some(row.get(5)?),
+            subtotal: Some(row.get(6)?),
+            restock: row.get(7)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_sales_returns(token: String, order_id: Option<i64>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<SalesReturn>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_ORDERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.order_id, o.customer_name, r.return_date, r.total_amount, r.credit_amount, r.refund_amount,
+                r.refund_method, r.reason, r.notes, r.created_by_name, r.points_restored, r.points_value
+         FROM sales_returns r
+         LEFT JOIN orders o ON o.order_id = r.order_id
+         WHERE ?1 IS NULL OR r.order_id = ?1
+         ORDER BY r.return_date DESC, r.id DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![order_id], |row| {
+        Ok(SalesReturn {
+            id: Some(row.get(0)?),
+            order_id: row.get(1)?,
+            customer_name: row.get(2)?,
+            return_date: row.get(3)?,
+            total_amount: Some(row.get(4)?),
+            credit_amount: Some(row.get(5)?),
+            refund_amount: Some(row.get(6)?),
+            refund_method: row.get(7)?,
+            reason: row.get(8)?,
+            notes: row.get(9)?,
+            created_by_name: row.get(10)?,
+            points_restored: Some(row.get(11)?),
+            points_value: Some(row.get(12)?),
+            items: Vec::new(),
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut returns = Vec::new();
+    for ret in rows {
+        let mut ret = ret.map_err(|e| e.to_string())?;
+        ret.items = items_of(&conn, ret.id.unwrap_or(0))?;
+        returns.push(ret);
+    }
+    Ok(returns)
+}
+
+/// Takes items back from an order. Quantities are limited to what was sold less
+/// earlier returns. Cash refunds come out of the open shift's drawer.
+#[tauri::command]
+pub fn create_sales_return(token: String, sales_return: SalesReturn, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::PROCESS_RETURNS)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let order_id = sales_return.order_id;
+
+    let (customer_id, order_subtotal, discount, due_amount, tax_mode): (Option<i64>, f64, f64, f64, Option<String>) = tx.query_row(
+        "SELECT customer_id, COALESCE(subtotal, 0), COALESCE(discount, 0), COALESCE(due_amount, 0), tax_mode FROM orders WHERE order_id = ?1",
+        params![order_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    ).optional().map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Sale #{} not found", order_id))?;
+    if order_status::is_void(&tx, order_id)? {
+        return Err("Cancelled or returned orders have nothing left to return".to_string());
+    }
+    if sales_return.items.iter().all(|item| item.quantity == 0.0) {
+        return Err("Enter the quantities being returned".to_string());
+    }
+    // Share of each sold unit's price that the order discount took off
+    let discount_rate = if order_subtotal > 0.0 { (discount / order_subtotal).clamp(0.0, 1.0) } else { 0.0 };
+    let tax_added = tax_mode.as_deref() == Some("exclusive");
+
+    tx.execute(
+        "INSERT INTO sales_returns (order_id, customer_id, return_date, total_amount, reason, notes, shift_id, created_by, created_by_name)
+         VALUES (?1, ?2, COALESCE(?3, CURRENT_TIMESTAMP), 0, ?4, ?5, ?6, ?7, ?8)",
+        params![order_id, customer_id, sales_return.return_date, sales_return.reason, sales_return.notes,
+            shifts::open_shift_id(&tx)?, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let return_id = tx.last_insert_rowid();
+
+    let mut total = 0.0;
+    for item in sales_return.items.iter().filter(|item| item.quantity != 0.0) {
+        if item.quantity < 0.0 {
+            return Err("Returned quantities cannot be negative".to_string());
+        }
+        let (product_id, sold, line_subtotal, cost, returned, line_tax): (i64, f64, f64, f64, f64, f64) = tx.query_row(
+            "SELECT oi.product_id, oi.quantity, oi.subtotal,
+                    COALESCE(oi.buying_price_snapshot, (SELECT buying_price FROM products WHERE id = oi.product_id), 0),
+                    (SELECT COALESCE(SUM(ri.quantity), 0) FROM sales_return_items ri WHERE ri.order_item_id = oi.id),
+                    COALESCE(oi.tax_amount, 0)
+             FROM order_items oi WHERE oi.id = ?1 AND oi.order_id = ?2",
+            params![item.order_item_id, order_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        ).optional().map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Item #{} is not on sale #{}", item.order_item_id, order_id))?;
+        let (product_name, stock, average_cost): (String, f64, f64) = tx.query_row(
+            "SELECT product_name, stock_quantity, buying_price FROM products WHERE id = ?1",
+            params![product_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).map_err(|e| e.to_string())?;
+        if item.quantity > sold - returned + 1e-9 {
+            return Err(format!("Only {} of {} can still be returned from this sale", sold - returned, product_name));
+        }
+
+        if item.restock {
+            // Put the units back at the cost they left at. With nothing (or less than
+            // nothing) on the shelf there is no value to average against.
+            let new_stock = stock + item.quantity;
+            let new_average_cost = if stock > 1e-9 {
+                (stock * average_cost + item.quantity * cost) / new_stock
+            } else {
+                cost
+            };
+            tx.execute(
+                "UPDATE products SET stock_quantity = ?1, buying_price = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?3",
+                params![new_stock, new_average_cost, product_id],
+            ).map_err(|e| e.to_string())?;
+        }
+
+        let unit_tax = if tax_added && sold > 0.0 { line_tax / sold } else { 0.0 };
+        let line_price = if sold > 0.0 { line_subtotal / sold } else { 0.0 };
+        let unit_price = line_price * (1.0 - discount_rate) + unit_tax;
+        let subtotal = item.quantity * unit_price;
+        tx.execute(
+            "INSERT INTO sales_return_items (return_id, order_item_id, product_id, quantity, unit_price, subtotal, restock, cost_snapshot)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![return_id, item.order_item_id, product_id, item.quantity, unit_price, subtotal, item.restock, cost],
+        ).map_err(|e| e.to_string())?;
+        total += subtotal;
+    }
+
+    // Goods paid for with points are paid back in points, then whatever the customer
+    // still owes on this sale is cancelled
+    tx.execute("UPDATE sales_returns SET total_amount = ?1 WHERE id = ?2", params![total, return_id])
+        .map_err(|e| e.to_string())?;
+    let (points_restored, points_value) = loyalty::reverse_return(&tx, &session, order_id, return_id)?;
+    let money = (total - points_value).max(0.0);
+    let credit = money.min(due_amount.max(0.0));
+    if credit > 0.0 {
+        let left = due_amount - credit;
+        tx.execute(
+            "UPDATE orders SET due_amount = ?1 WHERE order_id = ?2",
+            params![if left > EPSILON { left } else { 0.0 }, order_id],
+        ).map_err(|e| e.to_string())?;
+    }
+    let refund = if money - credit > EPSILON { money - credit } else { 0.0 };
+    let refund_method = if refund > 0.0 {
+        let method = sales_return.refund_method.as_deref().map(|m| m.trim().to_lowercase()).unwrap_or_default();
+        if !REFUND_METHODS.contains(&method.as_str()) {
+            return Err("Choose how the refund is paid: cash, card or mobile".to_string());
+        }
+        if method == "cash" {
+            shifts::require_open_shift(&tx)?;
+        }
+        Some(method)
+    } else {
+        None
+    };
+    tx.execute(
+        "UPDATE sales_returns SET credit_amount = ?1, refund_amount = ?2, refund_method = ?3, points_restored = ?4, points_value = ?5 WHERE id = ?6",
+        params![credit, refund, refund_method, points_restored, points_value, return_id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = audit::snapshot_with_items(&tx, "sales_returns", "id", return_id, "sales_return_items", "return_id")?;
+    audit::record_change(&tx, &session, "CREATE", "SalesReturn", Some(return_id), None, after)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(return_id)
+}
@@ -0,0 +1,257 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::db::Database;
+use crate::models::{CashMovement, Shift};
+use crate::permissions;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Cash drawer shifts. One drawer, so at most one shift is open at a time; every
+// order is stamped with the open shift's id. Expected cash is
+//   opening float + cash taken in the shift + cash in - cash out
+// and is frozen together with the counted cash and variance when the shift closes.
+
+const SHIFT_COLUMNS: &str = "id, status, opened_by_name, opened_at, opening_float, closed_by_name, closed_at, cash_sales, expected_cash, counted_cash, variance, notes";
+
+/// Id of the currently open shift, if any.
+pub fn open_shift_id(conn: &Connection) -> Result<Option<i64>, String> {
+    conn.query_row("SELECT id FROM shifts WHERE status = 'open'", [], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())
+}
+
+/// The open shift's id, or an error telling the cashier to open one.
+pub fn require_open_shift(conn: &Connection) -> Result<i64, String> {
+    open_shift_id(conn)?.ok_or_else(|| "No open shift. Open a shift before taking orders".to_string())
+}
+
+/// Cash kept at checkout (tendered less change) plus cash collected on customer accounts during the shift,
+/// less cash refunded for returns and for orders voided during the shift.
+fn cash_sales(conn: &Connection, shift_id: i64) -> Result<f64, String> {
+    conn.query_row(
+        "SELECT (SELECT COALESCE(SUM(p.amount), 0) FROM order_payments p JOIN orders o ON o.order_id = p.order_id
+                 WHERE o.shift_id = ?1 AND p.payment_method = 'cash')
+              + (SELECT COALESCE(SUM(amount), 0) FROM customer_payments WHERE shift_id = ?1 AND LOWER(COALESCE(payment_method, 'cash')) = 'cash')
+              - (SELECT COALESCE(SUM(refund_amount), 0) FROM sales_returns WHERE shift_id = ?1 AND refund_method = 'cash')
+              - (SELECT COALESCE(SUM(amount), 0) FROM order_refunds WHERE shift_id = ?1 AND refund_method = 'cash')",
+        params![shift_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())
+}
+
+fn movement_total(conn: &Connection, shift_id: i64, movement_type: &str) -> Result<f64, String> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(amount), 0) FROM cash_movements WHERE shift_id = ?1 AND movement_type = ?2",
+        params![shift_id, movement_type],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())
+}
+
+fn load_shift(conn: &Connection, shift_id: i64) -> Result<Shift, String> {
+    let mut shift = conn.query_row(
+        &format!("SELECT {} FROM shifts WHERE id = ?1", SHIFT_COLUMNS),
+        params![shift_id],
+        shift_from_row,
+    ).map_err(|e| e.to_string())?;
+    fill_totals(conn, &mut shift)?;
+    Ok(shift)
+}
+
+fn shift_from_row(row: &rusqlite::Row) -> rusqlite::Result<Shift> {
+    Ok(Shift {
+        id: row.get(0)?,
+        status: row.get(1)?,
+        opened_by_name: row.get(2)?,
+        opened_at: row.get(3)?,
+        opening_float: row.get(4)?,
+        closed_by_name: row.get(5)?,
+        closed_at: row.get(6)?,
+        cash_sales: row.get::<_, Option<f64>>(7)?.unwrap_or(0.0),
+        cash_in: 0.0,
+        cash_out: 0.0,
+        expected_cash: row.get::<_, Option<f64>>(8)?.unwrap_or(0.0),
+        counted_cash: row.get(9)?,
+        variance: row.get(10)?,
+        order_count: 0,
+        notes: row.get(11)?,
+    })
+}
+
+/// Closed shifts keep their frozen figures; open shifts are computed live.
+fn fill_totals(conn: &Connection, shift: &mut Shift) -> Result<(), String> {
+    shift.cash_in = movement_total(conn, shift.id, "IN")?;
+    shift.cash_out = movement_total(conn, shift.id, "OUT")?;
+    shift.order_count = conn.query_row(
+        "SELECT COUNT(*) FROM orders WHERE shift_id = ?1 AND status NOT IN ('cancelled', 'returned')",
+        params![shift.id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    if shift.status == "open" {
+        shift.cash_sales = cash_sales(conn, shift.id)?;
+        shift.expected_cash = shift.opening_float + shift.cash_sales + shift.cash_in - shift.cash_out;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_current_shift(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Option<Shift>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_SHIFTS)?;
+    match open_shift_id(&conn)? {
+        Some(id) => Ok(Some(load_shift(&conn, id)?)),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn open_shift(token: String, opening_float: f64, notes: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Shift, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SHIFTS)?;
+    if opening_float < 0.0 {
+        return Err("Opening float cannot be negative".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if open_shift_id(&tx)?.is_some() {
+        return Err("A shift is already open. Close it before opening a new one".to_string());
+    }
+
+    tx.execute(
+        "INSERT INTO shifts (status, opened_by, opened_by_name, opening_float, notes) VALUES ('open', ?1, ?2, ?3, ?4)",
+        params![session.user_id, session.username, opening_float, notes],
+    ).map_err(|e| e.to_string())?;
+    let shift_id = tx.last_insert_rowid();
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        "SHIFT_OPEN",
+        "Shift",
+        Some(shift_id),
+        &format!("Opened shift #{} with float {:.2}", shift_id, opening_float),
+    )?;
+
+    let shift = load_shift(&tx, shift_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(shift)
+}
+
+/// Records cash put into ("IN") or taken out of ("OUT") the drawer during the open shift.
+#[tauri::command]
+pub fn add_cash_movement(token: String, movement_type: String, amount: f64, reason: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SHIFTS)?;
+    let movement_type = movement_type.to_uppercase();
+    if movement_type != "IN" && movement_type != "OUT" {
+        return Err("Movement type must be IN or OUT".to_string());
+    }
+    if amount <= 0.0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let shift_id = open_shift_id(&tx)?.ok_or_else(|| "No open shift".to_string())?;
+
+    tx.execute(
+        "INSERT INTO cash_movements (shift_id, movement_type, amount, reason, user_id, username) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![shift_id, movement_type, amount, reason, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let movement_id = tx.last_insert_rowid();
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        &format!("CASH_{}", movement_type),
+        "Shift",
+        Some(shift_id),
+        &format!(
+            "Cash {} {:.2} on shift #{}{}",
+            if movement_type == "IN" { "in" } else { "out" },
+            amount,
+            shift_id,
+            reason.as_deref().map(|r| format!(": {}", r)).unwrap_or_default()
+        ),
+    )?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(movement_id)
+}
+
+/// Closes the open shift with the counted drawer cash; variance is counted - expected
+/// (positive = over, negative = short).
+#[tauri::command]
+pub fn close_shift(token: String, counted_cash: f64, notes: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Shift, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SHIFTS)?;
+    if counted_cash < 0.0 {
+        return Err("Counted cash cannot be negative".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let shift_id = open_shift_id(&tx)?.ok_or_else(|| "No open shift".to_string())?;
+
+    let live = load_shift(&tx, shift_id)?;
+    let variance = counted_cash - live.expected_cash;
+    tx.execute(
+        "UPDATE shifts SET status = 'closed', closed_by = ?1, closed_by_name = ?2, closed_at = CURRENT_TIMESTAMP,
+         cash_sales = ?3, expected_cash = ?4, counted_cash = ?5, variance = ?6, notes = COALESCE(?7, notes)
+         WHERE id = ?8",
+        params![session.user_id, session.username, live.cash_sales, live.expected_cash, counted_cash, variance, notes, shift_id],
+    ).map_err(|e| e.to_string())?;
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        "SHIFT_CLOSE",
+        "Shift",
+        Some(shift_id),
+        &format!(
+            "Closed shift #{}: expected {:.2}, counted {:.2}, variance {:+.2}",
+            shift_id, live.expected_cash, counted_cash, variance
+        ),
+    )?;
+
+    let shift = load_shift(&tx, shift_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(shift)
+}
+
+#[tauri::command]
+pub fn get_shifts(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Shift>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM shifts ORDER BY id DESC", SHIFT_COLUMNS)).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], shift_from_row).map_err(|e| e.to_string())?;
+    let mut shifts = Vec::new();
+    for row in rows {
+        let mut shift = row.map_err(|e| e.to_string())?;
+        fill_totals(&conn, &mut shift)?;
+        shifts.push(shift);
+    }
+    Ok(shifts)
+}
+
+#[tauri::command]
+pub fn get_shift_cash_movements(token: String, shift_id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<CashMovement>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::MANAGE_SHIFTS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, shift_id, movement_type, amount, reason, username, created_at FROM cash_movements WHERE shift_id = ?1 ORDER BY id"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![shift_id], |row| {
+        Ok(CashMovement {
+            id: row.get(0)?,
+            shift_id: row.get(1)?,
+            movement_type: row.get(2)?,
+            amount: row.get(3)?,
+            reason: row.get(4)?,
+            username: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut movements = Vec::new();
+    for movement in rows {
+        movements.push(movement.map_err(|e| e.to_string())?);
+    }
+    Ok(movements)
+}
@@ -0,0 +1,117 @@
+use crate::audit;
+use crate::auth::Session;
+use crate::db;
+use crate::models::{Order, OrderItem, StockShortage, StockShortageError, User};
+use crate::permissions;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+
+// Negative-stock policy, from the `negative_stock_policy` setting:
+//   block - a sale that would take a product below zero is refused unless someone
+//           holding override_stock approves it: the operator themselves, or a manager
+//           entering their PIN at the till
+//   warn  - the operator is told what is short and may go ahead
+//   allow - stock may go negative without asking
+// Refusals are returned as a JSON StockShortageError so the till can list what is
+// short. Every sale that goes ahead short is written to the audit log.
+
+const DEFAULT_POLICY: &str = "block";
+
+#[derive(PartialEq)]
+enum Policy {
+    Block,
+    Warn,
+    Allow,
+}
+
+fn policy(conn: &Connection) -> Result<Policy, String> {
+    let value = db::get_setting(conn, "negative_stock_policy").map_err(|e| e.to_string())?;
+    Ok(match value.as_deref().unwrap_or(DEFAULT_POLICY).trim() {
+        "warn" => Policy::Warn,
+        "allow" => Policy::Allow,
+        _ => Policy::Block,
+    })
+}
+
+/// A shortage someone agreed to sell through.
+pub struct Approval {
+    items: Vec<StockShortage>,
+    user_id: i64,
+    username: String,
+}
+
+/// Products the items would take below zero, with what is on the shelf now. For an
+/// edited order, call this after its old quantities were put back.
+fn shortages(conn: &Connection, items: &[OrderItem]) -> Result<Vec<StockShortage>, String> {
+    let mut requested: BTreeMap<i64, f64> = BTreeMap::new();
+    for item in items {
+        *requested.entry(item.product_id).or_insert(0.0) += item.quantity;
+    }
+    let mut short = Vec::new();
+    for (product_id, quantity) in requested {
+        let (product_name, stock): (String, f64) = conn.query_row(
+            "SELECT product_name, stock_quantity FROM products WHERE id = ?1",
+            params![product_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).map_err(|e| e.to_string())?;
+        if quantity > stock + 1e-9 {
+            short.push(StockShortage { product_id, product_name, requested: quantity, available: stock.max(0.0) });
+        }
+    }
+    Ok(short)
+}
+
+/// Applies the policy to an order about to take its items out of stock. `approver`
+/// is the manager identified by `Order::override_pin`, resolved before the
+/// transaction so failed PINs are still counted.
+pub fn check(conn: &Connection, session: &Session, approver: Option<&User>, order: &Order, items: &[OrderItem]) -> Result<Option<Approval>, String> {
+    let policy = policy(conn)?;
+    if policy == Policy::Allow {
+        return Ok(None);
+    }
+    let short = shortages(conn, items)?;
+    if short.is_empty() {
+        return Ok(None);
+    }
+
+    let confirmed = order.allow_short_stock.unwrap_or(false);
+    let can_override = permissions::has_permission(conn, &session.role, permissions::OVERRIDE_STOCK)?;
+    let approved_by = match (policy == Policy::Warn, approver) {
+        (_, Some(user)) => Some((user.id.unwrap_or_default(), user.username.clone())),
+        (true, None) if confirmed => Some((session.user_id, session.username.clone())),
+        (false, None) if confirmed && can_override => Some((session.user_id, session.username.clone())),
+        _ => None,
+    };
+    match approved_by {
+        Some((user_id, username)) => Ok(Some(Approval { items: short, user_id, username })),
+        None => {
+            let list = short.iter()
+                .map(|s| format!("{} ({} available, {} needed)", s.product_name, s.available, s.requested))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let error = StockShortageError {
+                code: "insufficient_stock".to_string(),
+                policy: if policy == Policy::Warn { "warn" } else { "block" }.to_string(),
+                can_override: policy == Policy::Warn || can_override,
+                message: format!("Not enough stock: {}", list),
+                items: short,
+            };
+            Err(serde_json::to_string(&error).map_err(|e| e.to_string())?)
+        }
+    }
+}
+
+/// Records who let the order go ahead short, and what it was short of.
+pub fn log_approval(conn: &Connection, session: &Session, approval: &Approval, order_id: i64) -> Result<(), String> {
+    let list = approval.items.iter()
+        .map(|s| format!("{} x{} with {} in stock", s.product_name, s.requested, s.available))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let by = if approval.user_id == session.user_id {
+        String::new()
+    } else {
+        format!(" for {}", session.username)
+    };
+    audit::write_activity_log(conn, Some(approval.user_id), &approval.username, "STOCK_OVERRIDE", "Order", Some(order_id),
+        &format!("Sale #{} allowed below stock by {}{}: {}", order_id, approval.username, by, list))
+}
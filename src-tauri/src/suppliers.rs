@@ -0,0 +1,480 @@
+use crate::audit;
+use crate::auth::SessionStore;
+use crate::customers::{non_empty, normalize_phone};
+use crate::db::Database;
+use crate::models::{AgingRow, Purchase, StatementEntry, Supplier, SupplierPayment};
+use crate::permissions;
+use crate::totals::EPSILON;
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::State;
+
+// Suppliers mirror customers: purchases keep the supplier name/phone as typed and link
+// to a supplier record through `purchases.supplier_id`, matched on the normalized phone
+// or, for suppliers without one, on the name.
+//
+// Payables: `purchases.paid_amount` is what was paid on receipt and `purchases.due_amount`
+// what we still owe. Later payments go into `supplier_payments` and are applied to
+// purchases (given purchase first, then oldest first) through `supplier_payment_allocations`.
+// Purchase return credit is applied the same way through `purchase_return_allocations`.
+
+fn find_by_phone_or_name(conn: &Connection, normalized: Option<&str>, name: Option<&str>) -> Result<Option<i64>, String> {
+    if let Some(normalized) = normalized {
+        let id = conn.query_row("SELECT id FROM suppliers WHERE phone_normalized = ?1", params![normalized], |row| row.get(0))
+            .optional().map_err(|e| e.to_string())?;
+        if id.is_some() {
+            return Ok(id);
+        }
+    }
+    match name {
+        Some(name) => conn.query_row(
+            "SELECT id FROM suppliers WHERE LOWER(supplier_name) = LOWER(?1) AND (?2 IS NULL OR phone_normalized IS NULL) ORDER BY id LIMIT 1",
+            params![name, normalized],
+            |row| row.get(0),
+        ).optional().map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Resolves the supplier for a purchase: an explicit `supplier_id` wins, otherwise the
+/// phone or name is matched and a supplier is created on first sight.
+pub fn match_or_create(conn: &Connection, purchase: &Purchase) -> Result<Option<i64>, String> {
+    if let Some(id) = purchase.supplier_id {
+        let exists: bool = conn.query_row("SELECT COUNT(*) FROM suppliers WHERE id = ?1", params![id], |row| row.get::<_, i64>(0))
+            .map_err(|e| e.to_string())? > 0;
+        if !exists {
+            return Err(format!("Supplier #{} not found", id));
+        }
+        return Ok(Some(id));
+    }
+
+    let name = non_empty(&purchase.supplier_name);
+    let phone = non_empty(&purchase.supplier_phone);
+    let normalized = phone.and_then(normalize_phone);
+    if name.is_none() && normalized.is_none() {
+        return Ok(None);
+    }
+    if let Some(id) = find_by_phone_or_name(conn, normalized.as_deref(), name)? {
+        // Fill in a phone number the supplier record is still missing
+        if let (Some(phone), Some(normalized)) = (phone, &normalized) {
+            conn.execute(
+                "UPDATE suppliers SET phone_number = ?1, phone_normalized = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?3 AND phone_normalized IS NULL",
+                params![phone, normalized, id],
+            ).map_err(|e| e.to_string())?;
+        }
+        return Ok(Some(id));
+    }
+
+    conn.execute(
+        "INSERT INTO suppliers (supplier_name, phone_number, phone_normalized, created_at, updated_at) VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        params![name.or(phone).unwrap_or_default(), phone, normalized],
+    ).map_err(|e| e.to_string())?;
+    Ok(Some(conn.last_insert_rowid()))
+}
+
+/// Works out `(paid_amount, due_amount)` for a purchase being created or edited.
+/// Payments and return credit already applied to the purchase count towards it;
+/// anything left unpaid must be owed to a known supplier.
+pub fn payable_amounts(conn: &Connection, purchase: &Purchase, purchase_id: Option<i64>, supplier_id: Option<i64>) -> Result<(f64, f64), String> {
+    let allocated: f64 = match purchase_id {
+        Some(id) => conn.query_row(
+            "SELECT COALESCE(SUM(amount), 0) FROM (
+                SELECT amount FROM supplier_payment_allocations WHERE purchase_id = ?1
+                UNION ALL
+                SELECT amount FROM purchase_return_allocations WHERE purchase_id = ?1
+             )",
+            params![id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?,
+        None => 0.0,
+    };
+    if allocated > EPSILON {
+        let previous_supplier: Option<i64> = conn.query_row(
+            "SELECT supplier_id FROM purchases WHERE purchase_id = ?1",
+            params![purchase_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        if previous_supplier != supplier_id {
+            return Err("Purchase has supplier payments or return credit recorded; its supplier cannot be changed".to_string());
+        }
+    }
+
+    let outstanding = purchase.total_amount - allocated;
+    if outstanding < -EPSILON {
+        return Err(format!("Purchase total is below the {:.2} already paid or credited by the supplier", allocated));
+    }
+    let paid = purchase.paid_amount.unwrap_or(outstanding);
+    if paid < 0.0 {
+        return Err("Paid amount cannot be negative".to_string());
+    }
+    if paid > outstanding + EPSILON {
+        return Err(format!("Paid amount exceeds the {:.2} owing on this purchase", outstanding.max(0.0)));
+    }
+    let due = (outstanding - paid).max(0.0);
+    if due > EPSILON && supplier_id.is_none() {
+        return Err("Buying on credit needs a supplier. Enter the supplier's name or phone".to_string());
+    }
+    Ok((paid.min(outstanding.max(0.0)), if due > EPSILON { due } else { 0.0 }))
+}
+
+/// One-time migration: folds the free-text suppliers on historic purchases into
+/// supplier records, matching on phone first and then on name (the most recent
+/// purchase's spelling wins).
+pub fn backfill_purchase_suppliers(conn: &Connection) -> rusqlite::Result<()> {
+    let purchases: Vec<(i64, Option<String>, Option<String>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT purchase_id, supplier_name, supplier_phone FROM purchases
+             WHERE supplier_id IS NULL AND (TRIM(COALESCE(supplier_name, '')) != '' OR TRIM(COALESCE(supplier_phone, '')) != '')
+             ORDER BY purchase_date DESC, purchase_id DESC"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    for (purchase_id, name, phone) in purchases {
+        let name = non_empty(&name);
+        let phone = non_empty(&phone);
+        let normalized = phone.and_then(normalize_phone);
+        let mut supplier_id: Option<i64> = match &normalized {
+            Some(normalized) => conn.query_row("SELECT id FROM suppliers WHERE phone_normalized = ?1", params![normalized], |row| row.get(0)).optional()?,
+            None => None,
+        };
+        if supplier_id.is_none() {
+            if let Some(name) = name {
+                supplier_id = conn.query_row(
+                    "SELECT id FROM suppliers WHERE LOWER(supplier_name) = LOWER(?1) AND (?2 IS NULL OR phone_normalized IS NULL) ORDER BY id LIMIT 1",
+                    params![name, normalized],
+                    |row| row.get(0),
+                ).optional()?;
+            }
+        }
+        let supplier_id = match supplier_id {
+            Some(id) => id,
+            None => {
+                conn.execute(
+                    "INSERT INTO suppliers (supplier_name, phone_number, phone_normalized, created_at, updated_at) VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                    params![name.or(phone).unwrap_or_default(), phone, normalized],
+                )?;
+                conn.last_insert_rowid()
+            }
+        };
+        conn.execute("UPDATE purchases SET supplier_id = ?1 WHERE purchase_id = ?2", params![supplier_id, purchase_id])?;
+    }
+    Ok(())
+}
+
+const SUPPLIER_SELECT: &str = "SELECT s.id, s.supplier_name, s.phone_number, s.address, s.notes, s.created_at,
+        (SELECT COUNT(*) FROM purchases p WHERE p.supplier_id = s.id),
+        (SELECT COALESCE(SUM(p.total_amount), 0) FROM purchases p WHERE p.supplier_id = s.id),
+        (SELECT COALESCE(SUM(p.due_amount), 0) FROM purchases p WHERE p.supplier_id = s.id)
+          - (SELECT COALESCE(SUM(r.credit_remaining), 0) FROM purchase_returns r WHERE r.supplier_id = s.id)
+     FROM suppliers s";
+
+fn supplier_from_row(row: &rusqlite::Row) -> rusqlite::Result<Supplier> {
+    Ok(Supplier {
+        id: Some(row.get(0)?),
+        supplier_name: row.get(1)?,
+        phone_number: row.get(2)?,
+        address: row.get(3)?,
+        notes: row.get(4)?,
+        created_at: row.get(5)?,
+        purchase_count: Some(row.get(6)?),
+        total_purchased: Some(row.get(7)?),
+        balance: Some(row.get(8)?),
+    })
+}
+
+fn validate(conn: &Connection, supplier: &Supplier, id: Option<i64>) -> Result<Option<String>, String> {
+    if supplier.supplier_name.trim().is_empty() {
+        return Err("Supplier name is required".to_string());
+    }
+    let normalized = non_empty(&supplier.phone_number).and_then(normalize_phone);
+    if let Some(normalized) = &normalized {
+        let taken: Option<String> = conn.query_row(
+            "SELECT supplier_name FROM suppliers WHERE phone_normalized = ?1 AND id != ?2",
+            params![normalized, id.unwrap_or(0)],
+            |row| row.get(0),
+        ).optional().map_err(|e| e.to_string())?;
+        if let Some(name) = taken {
+            return Err(format!("Phone number already belongs to supplier \"{}\"", name));
+        }
+    }
+    Ok(normalized)
+}
+
+#[tauri::command]
+pub fn get_suppliers(token: String, search: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<Supplier>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_SUPPLIERS)?;
+
+    let query = search.as_deref().map(str::trim).unwrap_or("").to_lowercase();
+    let phone = normalize_phone(&query).unwrap_or_default();
+    let mut stmt = conn.prepare(&format!(
+        "{} WHERE ?1 = '' OR LOWER(s.supplier_name) LIKE '%' || ?1 || '%' OR (?2 != '' AND s.phone_normalized LIKE '%' || ?2 || '%')
+         ORDER BY s.supplier_name COLLATE NOCASE",
+        SUPPLIER_SELECT
+    )).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![query, phone], supplier_from_row).map_err(|e| e.to_string())?;
+
+    let mut suppliers = Vec::new();
+    for supplier in rows {
+        suppliers.push(supplier.map_err(|e| e.to_string())?);
+    }
+    Ok(suppliers)
+}
+
+#[tauri::command]
+pub fn create_supplier(token: String, supplier: Supplier, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SUPPLIERS)?;
+    let normalized = validate(&conn, &supplier, None)?;
+
+    conn.execute(
+        "INSERT INTO suppliers (supplier_name, phone_number, phone_normalized, address, notes, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        params![supplier.supplier_name.trim(), non_empty(&supplier.phone_number), normalized, non_empty(&supplier.address), non_empty(&supplier.notes)],
+    ).map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+
+    let after = audit::snapshot(&conn, "suppliers", "id", id)?;
+    audit::record_change(&conn, &session, "CREATE", "Supplier", Some(id), None, after)?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn update_supplier(token: String, id: i64, supplier: Supplier, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SUPPLIERS)?;
+    let before = audit::snapshot(&conn, "suppliers", "id", id)?;
+    if before.is_none() {
+        return Err(format!("Supplier #{} not found", id));
+    }
+    let normalized = validate(&conn, &supplier, Some(id))?;
+
+    conn.execute(
+        "UPDATE suppliers SET supplier_name = ?1, phone_number = ?2, phone_normalized = ?3, address = ?4, notes = ?5, updated_at = CURRENT_TIMESTAMP WHERE id = ?6",
+        params![supplier.supplier_name.trim(), non_empty(&supplier.phone_number), normalized, non_empty(&supplier.address), non_empty(&supplier.notes), id],
+    ).map_err(|e| e.to_string())?;
+
+    let after = audit::snapshot(&conn, "suppliers", "id", id)?;
+    audit::record_change(&conn, &session, "UPDATE", "Supplier", Some(id), before, after)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_supplier(token: String, id: i64, db: State<Database>, sessions: State<SessionStore>) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::MANAGE_SUPPLIERS)?;
+
+    let purchase_count: i64 = conn.query_row("SELECT COUNT(*) FROM purchases WHERE supplier_id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if purchase_count > 0 {
+        return Err(format!("Supplier has {} purchases and cannot be deleted", purchase_count));
+    }
+    let order_count: i64 = conn.query_row("SELECT COUNT(*) FROM purchase_orders WHERE supplier_id = ?1", params![id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if order_count > 0 {
+        return Err(format!("Supplier has {} purchase orders and cannot be deleted", order_count));
+    }
+
+    let before = audit::snapshot(&conn, "suppliers", "id", id)?;
+    conn.execute("DELETE FROM suppliers WHERE id = ?1", params![id]).map_err(|e| e.to_string())?;
+    audit::record_change(&conn, &session, "DELETE", "Supplier", Some(id), before, None)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn record_supplier_payment(token: String, payment: SupplierPayment, db: State<Database>, sessions: State<SessionStore>) -> Result<i64, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let session = sessions.require_permission(&conn, &token, permissions::PAY_SUPPLIERS)?;
+    if payment.amount <= 0.0 {
+        return Err("Payment amount must be greater than zero".to_string());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let supplier_name: String = tx.query_row(
+        "SELECT supplier_name FROM suppliers WHERE id = ?1",
+        params![payment.supplier_id],
+        |row| row.get(0),
+    ).optional().map_err(|e| e.to_string())?.ok_or_else(|| format!("Supplier #{} not found", payment.supplier_id))?;
+    let outstanding: f64 = tx.query_row(
+        "SELECT COALESCE(SUM(due_amount), 0) FROM purchases WHERE supplier_id = ?1",
+        params![payment.supplier_id],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+    if payment.amount > outstanding + EPSILON {
+        return Err(format!("Payment exceeds the {:.2} owed to this supplier", outstanding));
+    }
+
+    tx.execute(
+        "INSERT INTO supplier_payments (supplier_id, amount, payment_method, payment_date, notes, created_by, created_by_name)
+         VALUES (?1, ?2, ?3, COALESCE(?4, CURRENT_TIMESTAMP), ?5, ?6, ?7)",
+        params![payment.supplier_id, payment.amount, payment.payment_method, payment.payment_date, payment.notes, session.user_id, session.username],
+    ).map_err(|e| e.to_string())?;
+    let payment_id = tx.last_insert_rowid();
+
+    // The chosen purchase first, then the oldest dues
+    let dues: Vec<(i64, f64)> = {
+        let mut stmt = tx.prepare(
+            "SELECT purchase_id, due_amount FROM purchases WHERE supplier_id = ?1 AND due_amount > 0
+             ORDER BY (purchase_id = ?2) DESC, datetime(purchase_date), purchase_id"
+        ).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params![payment.supplier_id, payment.purchase_id.unwrap_or(0)], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())?
+    };
+    if let Some(purchase_id) = payment.purchase_id {
+        if dues.first().map(|(id, _)| *id) != Some(purchase_id) {
+            return Err(format!("Purchase #{} has nothing owing to this supplier", purchase_id));
+        }
+    }
+    let mut remaining = payment.amount;
+    for (purchase_id, due) in dues {
+        if remaining <= EPSILON {
+            break;
+        }
+        let applied = remaining.min(due);
+        tx.execute(
+            "INSERT INTO supplier_payment_allocations (payment_id, purchase_id, amount) VALUES (?1, ?2, ?3)",
+            params![payment_id, purchase_id, applied],
+        ).map_err(|e| e.to_string())?;
+        let left = due - applied;
+        tx.execute(
+            "UPDATE purchases SET due_amount = ?1 WHERE purchase_id = ?2",
+            params![if left > EPSILON { left } else { 0.0 }, purchase_id],
+        ).map_err(|e| e.to_string())?;
+        remaining -= applied;
+    }
+
+    audit::write_activity_log(
+        &tx,
+        Some(session.user_id),
+        &session.username,
+        "PAYMENT",
+        "Supplier",
+        Some(payment.supplier_id),
+        &format!(
+            "Paid {:.2} to {} ({})",
+            payment.amount,
+            supplier_name,
+            payment.payment_method.as_deref().unwrap_or("cash")
+        ),
+    )?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(payment_id)
+}
+
+/// Statement of account with a supplier: purchases as credits (we owe), payments on
+/// receipt and later and goods returned as debits, with a running balance owed. Entries before
+/// `start_date` roll into an opening balance.
+#[tauri::command]
+pub fn get_supplier_statement(
+    token: String,
+    supplier_id: i64,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    db: State<Database>,
+    sessions: State<SessionStore>,
+) -> Result<Vec<StatementEntry>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_SUPPLIERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date, entry_type, reference, description, debit, credit FROM (
+            SELECT purchase_date AS date, 'PURCHASE' AS entry_type, 'Purchase #' || purchase_id AS reference,
+                   invoice_number AS description, 0 AS debit, total_amount AS credit, purchase_id AS seq, 0 AS sub
+            FROM purchases WHERE supplier_id = ?1
+            UNION ALL
+            SELECT purchase_date, 'PAYMENT', 'Purchase #' || purchase_id, 'Paid on receipt', paid_amount, 0, purchase_id, 1
+            FROM purchases WHERE supplier_id = ?1 AND paid_amount > 0
+            UNION ALL
+            SELECT payment_date, 'PAYMENT', 'Payment #' || id, COALESCE(notes, COALESCE(payment_method, 'cash')),
+                   amount, 0, id, 2
+            FROM supplier_payments WHERE supplier_id = ?1
+            UNION ALL
+            SELECT return_date, 'RETURN', 'Return #' || id, COALESCE(reason, 'Goods returned'), total_amount, 0, id, 3
+            FROM purchase_returns WHERE supplier_id = ?1
+         ) ORDER BY datetime(date), sub, seq"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![supplier_id], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, f64>(4)?,
+            row.get::<_, f64>(5)?,
+        ))
+    }).map_err(|e| e.to_string())?;
+
+    let mut rows = rows.collect::<rusqlite::Result<Vec<_>>>().map_err(|e| e.to_string())?;
+
+    // Dates are compared on their YYYY-MM-DD prefix, which every stored format shares
+    let day = |date: &Option<String>| date.as_deref().map(|d| d.chars().take(10).collect::<String>()).unwrap_or_default();
+    let mut entries = Vec::new();
+    let mut balance = 0.0;
+    if let Some(start) = &start_date {
+        let (before, rest): (Vec<_>, Vec<_>) = rows.into_iter().partition(|r| day(&r.0).as_str() < start.as_str());
+        balance = before.iter().map(|r| r.5 - r.4).sum();
+        entries.push(StatementEntry {
+            date: Some(start.clone()),
+            entry_type: "OPENING".to_string(),
+            reference: None,
+            description: Some("Opening balance".to_string()),
+            debit: (-balance).max(0.0),
+            credit: balance.max(0.0),
+            balance,
+        });
+        rows = rest;
+    }
+    for (date, entry_type, reference, description, debit, credit) in rows {
+        if let Some(end) = &end_date {
+            if day(&date).as_str() > end.as_str() {
+                break;
+            }
+        }
+        balance += credit - debit;
+        entries.push(StatementEntry { date, entry_type, reference, description, debit, credit, balance });
+    }
+    Ok(entries)
+}
+
+/// What we owe per supplier bucketed by the age of the purchase.
+#[tauri::command]
+pub fn get_payables_aging(token: String, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<AgingRow>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_SUPPLIERS)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.supplier_name, s.phone_number,
+            SUM(CASE WHEN age <= 30 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 30 AND age <= 60 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 60 AND age <= 90 THEN due ELSE 0 END),
+            SUM(CASE WHEN age > 90 THEN due ELSE 0 END),
+            SUM(due)
+         FROM (
+            SELECT supplier_id, due_amount AS due,
+                   CAST(julianday('now', 'localtime') - julianday(date(purchase_date)) AS INTEGER) AS age
+            FROM purchases WHERE due_amount > 0 AND supplier_id IS NOT NULL
+         ) p
+         JOIN suppliers s ON s.id = p.supplier_id
+         GROUP BY s.id
+         ORDER BY SUM(due) DESC"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok(AgingRow {
+            party_id: row.get(0)?,
+            party_name: row.get(1)?,
+            phone_number: row.get(2)?,
+            days_0_30: row.get(3)?,
+            days_31_60: row.get(4)?,
+            days_61_90: row.get(5)?,
+            days_90_plus: row.get(6)?,
+            total: row.get(7)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut aging = Vec::new();
+    for row in rows {
+        aging.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(aging)
+}
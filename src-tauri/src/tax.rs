@@ -0,0 +1,131 @@
+use crate::auth::SessionStore;
+use crate::db::{self, Database};
+use crate::models::{OrderItem, TaxSummaryItem};
+use crate::permissions;
+use rusqlite::{params, Connection};
+use tauri::State;
+
+// Sales tax from products.tax_percentage, per the `tax_mode` setting:
+//   inclusive - selling prices already contain the tax, which is worked out of them
+//   exclusive - tax is added on top of the line and so to the grand total
+// Tax is taken on line subtotals, which already carry any price reductions and line
+// promotions, less each line's share of the order discount; extra and delivery charges
+// are not taxed. Each line keeps its rate, taxable amount and tax,
+// and the order its mode, so changing a rate or the mode leaves past sales alone.
+
+const DEFAULT_MODE: &str = "inclusive";
+
+fn mode(conn: &Connection) -> Result<String, String> {
+    let value = db::get_setting(conn, "tax_mode").map_err(|e| e.to_string())?;
+    Ok(match value.as_deref().map(str::trim) {
+        Some("exclusive") => "exclusive",
+        _ => DEFAULT_MODE,
+    }.to_string())
+}
+
+fn round2(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Tax worked out for one order line.
+pub struct LineTax {
+    pub rate: f64,
+    pub taxable: f64, // Line subtotal net of discount and tax
+    pub tax: f64,
+}
+
+/// Tax worked out for an order, one entry per item in the order given.
+pub struct OrderTax {
+    pub mode: String,
+    pub lines: Vec<LineTax>,
+    pub total: f64,
+}
+
+impl OrderTax {
+    /// Tax to add to the grand total: none when prices include it.
+    pub fn added(&self) -> f64 {
+        if self.mode == "exclusive" { self.total } else { 0.0 }
+    }
+}
+
+/// Works out the tax on an order's items at their products' current rates, after
+/// spreading `discount` over the lines in proportion to their subtotals.
+pub fn compute(conn: &Connection, items: &[OrderItem], discount: f64) -> Result<OrderTax, String> {
+    let mode = mode(conn)?;
+    let subtotal: f64 = items.iter().map(|item| item.subtotal).sum();
+    let kept = if subtotal > 0.0 { (1.0 - discount / subtotal).clamp(0.0, 1.0) } else { 1.0 };
+
+    let mut lines = Vec::new();
+    for item in items {
+        let rate: f64 = conn.query_row(
+            "SELECT COALESCE(tax_percentage, 0) FROM products WHERE id = ?1",
+            params![item.product_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        let rate = rate.max(0.0);
+        let amount = item.subtotal * kept;
+        let (taxable, tax) = if mode == "exclusive" {
+            (round2(amount), round2(amount * rate / 100.0))
+        } else {
+            let tax = round2(amount * rate / (100.0 + rate));
+            (round2(amount - tax), tax)
+        };
+        lines.push(LineTax { rate, taxable, tax });
+    }
+    let total = round2(lines.iter().map(|l| l.tax).sum());
+    Ok(OrderTax { mode, lines, total })
+}
+
+/// Tax charged and refunded per rate and period (day, month or year), for VAT returns.
+/// Sales count on their order date and returns on their return date; cancelled and
+/// returned orders are left out.
+#[tauri::command]
+pub fn get_tax_summary_report(token: String, start_date: String, end_date: String, period: Option<String>, db: State<Database>, sessions: State<SessionStore>) -> Result<Vec<TaxSummaryItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    sessions.require_permission(&conn, &token, permissions::VIEW_REPORTS)?;
+
+    let format = match period.as_deref().unwrap_or("month") {
+        "day" => "%Y-%m-%d",
+        "month" => "%Y-%m",
+        "year" => "%Y",
+        other => return Err(format!("Unknown period '{}'. Use day, month or year", other)),
+    };
+    let mut stmt = conn.prepare(
+        "SELECT period, rate, SUM(taxable), SUM(tax), SUM(returned_taxable), SUM(returned_tax)
+         FROM (
+            SELECT strftime(?3, o.order_date) AS period, COALESCE(oi.tax_rate, 0) AS rate,
+                   COALESCE(oi.taxable_amount, 0) AS taxable, COALESCE(oi.tax_amount, 0) AS tax,
+                   0 AS returned_taxable, 0 AS returned_tax
+            FROM order_items oi
+            JOIN orders o ON o.order_id = oi.order_id
+            WHERE date(o.order_date) BETWEEN date(?1) AND date(?2) AND o.status NOT IN ('cancelled', 'returned')
+            UNION ALL
+            SELECT strftime(?3, r.return_date), COALESCE(oi.tax_rate, 0), 0, 0,
+                   ri.quantity / oi.quantity * COALESCE(oi.taxable_amount, 0),
+                   ri.quantity / oi.quantity * COALESCE(oi.tax_amount, 0)
+            FROM sales_return_items ri
+            JOIN sales_returns r ON r.id = ri.return_id
+            JOIN order_items oi ON oi.id = ri.order_item_id
+            WHERE oi.quantity > 0 AND date(r.return_date) BETWEEN date(?1) AND date(?2)
+         )
+         GROUP BY period, rate
+         ORDER BY period DESC, rate"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map(params![start_date, end_date, format], |row| {
+        let taxable_sales: f64 = row.get(2)?;
+        let tax_collected: f64 = row.get(3)?;
+        let taxable_returns: f64 = row.get(4)?;
+        let tax_refunded: f64 = row.get(5)?;
+        Ok(TaxSummaryItem {
+            period: row.get(0)?,
+            tax_rate: row.get(1)?,
+            taxable_sales,
+            tax_collected,
+            taxable_returns,
+            tax_refunded,
+            net_taxable: taxable_sales - taxable_returns,
+            net_tax: tax_collected - tax_refunded,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>().map_err(|e| e.to_string())
+}
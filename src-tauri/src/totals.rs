@@ -0,0 +1,117 @@
+use crate::models::{Order, OrderItem, Purchase, PurchaseItem, ValidationError};
+use crate::tax::{self, OrderTax};
+use rusqlite::Connection;
+
+// Derived amounts sent with orders and purchases are worked out again from their lines
+// using the formulas in CALCULATIONS.md. A value off by no more than TOLERANCE is
+// rounding and is replaced by the backend's figure; anything further off is refused
+// with a JSON ValidationError naming the field, so a frontend bug cannot end up in
+// the books.
+
+pub const TOLERANCE: f64 = 0.01;
+
+// Balances (dues, credits, change) within EPSILON of zero count as settled, and
+// received quantities within QUANTITY_EPSILON of the ordered ones as complete.
+pub const EPSILON: f64 = 0.005;
+pub const QUANTITY_EPSILON: f64 = 0.0001;
+
+fn round2(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+fn invalid(field: &str, expected: Option<f64>, received: Option<f64>, message: String) -> String {
+    let error = ValidationError {
+        code: "validation_failed".to_string(),
+        field: field.to_string(),
+        expected,
+        received,
+        message,
+    };
+    serde_json::to_string(&error).unwrap_or(error.message)
+}
+
+/// Returns `expected` when `received` is within rounding of it.
+fn reconcile(field: &str, label: &str, expected: f64, received: f64) -> Result<f64, String> {
+    if (expected - received).abs() > TOLERANCE + 1e-9 {
+        return Err(invalid(field, Some(expected), Some(received),
+            format!("{} should be {:.2} but {:.2} was sent", label, expected, received)));
+    }
+    Ok(expected)
+}
+
+fn not_negative(field: &str, label: &str, value: f64) -> Result<(), String> {
+    if value < 0.0 || !value.is_finite() {
+        return Err(invalid(field, None, Some(value), format!("{} cannot be negative", label)));
+    }
+    Ok(())
+}
+
+fn positive(field: &str, label: &str, value: f64) -> Result<(), String> {
+    if value <= 0.0 || !value.is_finite() {
+        return Err(invalid(field, None, Some(value), format!("{} must be above zero", label)));
+    }
+    Ok(())
+}
+
+/// Checks an order against its items (CALCULATIONS.md 3.1 - 3.4, 3.7), correcting rounding,
+/// and returns the tax worked out on the way. Promotions must already be applied; the
+/// order-level one is added to `order.discount`.
+pub fn order(conn: &Connection, order: &mut Order, items: &mut [OrderItem]) -> Result<OrderTax, String> {
+    work_out(conn, order, items, true)
+}
+
+/// Fills in an order's derived amounts from its items without checking what was sent,
+/// for pricing a cart.
+pub fn price(conn: &Connection, order: &mut Order, items: &mut [OrderItem]) -> Result<OrderTax, String> {
+    work_out(conn, order, items, false)
+}
+
+fn work_out(conn: &Connection, order: &mut Order, items: &mut [OrderItem], check: bool) -> Result<OrderTax, String> {
+    let settle = |field: &str, label: &str, expected: f64, received: f64| {
+        if check { reconcile(field, label, expected, received) } else { Ok(expected) }
+    };
+    if items.is_empty() {
+        return Err(invalid("items", None, None, "The order has no items".to_string()));
+    }
+    for (i, item) in items.iter_mut().enumerate() {
+        positive(&format!("items[{}].quantity", i), "Quantity", item.quantity)?;
+        not_negative(&format!("items[{}].selling_price", i), "Selling price", item.selling_price)?;
+        let promotion = item.promotion_discount.unwrap_or(0.0);
+        item.subtotal = settle(&format!("items[{}].subtotal", i), "Item subtotal",
+            round2(item.quantity * item.selling_price - promotion), item.subtotal)?;
+    }
+    order.subtotal = settle("subtotal", "Subtotal", round2(items.iter().map(|item| item.subtotal).sum()), order.subtotal)?;
+    not_negative("extra_charge", "Extra charge", order.extra_charge)?;
+    not_negative("delivery_charge", "Delivery charge", order.delivery_charge)?;
+    not_negative("discount", "Discount", order.discount)?;
+    let promotion = order.promotion_discount.unwrap_or(0.0);
+    if order.discount > order.subtotal - promotion + TOLERANCE {
+        return Err(invalid("discount", Some(round2(order.subtotal - promotion)), Some(order.discount),
+            "Discount cannot be more than the subtotal left after promotions".to_string()));
+    }
+    order.discount = round2(order.discount + promotion);
+
+    let tax = tax::compute(conn, items, order.discount)?;
+    let grand_total = round2(order.subtotal + order.extra_charge + order.delivery_charge - order.discount + tax.added());
+    order.grand_total = settle("grand_total", "Grand total", grand_total, order.grand_total)?;
+    Ok(tax)
+}
+
+/// Checks a purchase against its items (CALCULATIONS.md 2.1 - 2.2), correcting rounding.
+pub fn purchase(purchase: &mut Purchase, items: &mut [PurchaseItem]) -> Result<(), String> {
+    if items.is_empty() {
+        return Err(invalid("items", None, None, "The purchase has no items".to_string()));
+    }
+    for (i, item) in items.iter_mut().enumerate() {
+        positive(&format!("items[{}].quantity", i), "Quantity", item.quantity)?;
+        not_negative(&format!("items[{}].buying_price", i), "Unit price", item.buying_price)?;
+        not_negative(&format!("items[{}].extra_charge", i), "Extra charge", item.extra_charge)?;
+        item.subtotal = reconcile(&format!("items[{}].subtotal", i), "Item subtotal",
+            round2(item.quantity * item.buying_price + item.extra_charge), item.subtotal)?;
+        item.purchase_unit_cost = reconcile(&format!("items[{}].purchase_unit_cost", i), "Landed cost",
+            item.subtotal / item.quantity, item.purchase_unit_cost)?;
+    }
+    purchase.total_amount = reconcile("total_amount", "Total amount",
+        round2(items.iter().map(|item| item.subtotal).sum()), purchase.total_amount)?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 import { ref, reactive } from "vue";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "../utils/api";
 
 export interface Message {
   id: number;
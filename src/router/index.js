@@ -3,6 +3,7 @@ import { useAuthStore } from '../stores/auth'
 import Dashboard from '../views/Dashboard.vue'
 import Products from '../views/Products.vue'
 import Buying from '../views/Buying.vue'
+import PurchaseOrders from '../views/PurchaseOrders.vue'
 import Selling from '../views/Selling.vue'
 import Stocks from '../views/Stocks.vue'
 import Reports from '../views/Reports.vue'
@@ -14,6 +15,10 @@ import ActivityLog from '../views/ActivityLog.vue'
 import Chat from '../views/Chat.vue'
 import Expenses from '../views/Expenses.vue'
 import License from '../views/License.vue'
+import Shifts from '../views/Shifts.vue'
+import Customers from '../views/Customers.vue'
+import Suppliers from '../views/Suppliers.vue'
+import Promotions from '../views/Promotions.vue'
 
 const routes = [
   { path: '/license', component: License, name: 'License', meta: { public: true } },
@@ -21,7 +26,12 @@ const routes = [
   { path: '/', component: Dashboard, name: 'Dashboard' },
   { path: '/products', component: Products, name: 'Products', meta: { permission: 'canManageProducts' } },
   { path: '/buying', component: Buying, name: 'Buying', meta: { permission: 'canBuy' } },
+  { path: '/purchase-orders', component: PurchaseOrders, name: 'PurchaseOrders', meta: { permission: 'canBuy' } },
   { path: '/selling', component: Selling, name: 'Selling', meta: { permission: 'canSell' } },
+  { path: '/customers', component: Customers, name: 'Customers', meta: { permission: 'canViewCustomers' } },
+  { path: '/suppliers', component: Suppliers, name: 'Suppliers', meta: { permission: 'canViewSuppliers' } },
+  { path: '/promotions', component: Promotions, name: 'Promotions', meta: { permission: 'canManagePromotions' } },
+  { path: '/shifts', component: Shifts, name: 'Shifts', meta: { permission: 'canManageShifts' } },
   { path: '/stocks', component: Stocks, name: 'Stocks', meta: { permission: 'canViewStock' } },
   { path: '/reports', component: Reports, name: 'Reports', meta: { permission: 'canViewReports' } },
   { path: '/backup', component: Backup, name: 'Backup', meta: { permission: 'canManageBackup' } },
@@ -1,27 +1,42 @@
 import { defineStore } from 'pinia';
 import { ref, computed } from 'vue';
+import { invoke } from '@tauri-apps/api/core';
 
 export const useAuthStore = defineStore('auth', () => {
   const user = ref(JSON.parse(localStorage.getItem('user')) || null);
+  const token = ref(localStorage.getItem('session_token') || null);
 
-  const isAuthenticated = computed(() => !!user.value);
+  // Set when the backend reports the session as locked (idle timeout or manual lock)
+  const locked = ref(false);
+
+  const isAuthenticated = computed(() => !!user.value && !!token.value);
   const role = computed(() => user.value?.role || '');
   
+  const permissions = ref(JSON.parse(localStorage.getItem('permissions')) || []);
+
   const isSuperAdmin = computed(() => role.value === 'super_admin');
   const isAdmin = computed(() => ['super_admin', 'admin'].includes(role.value));
   const isManager = computed(() => ['super_admin', 'admin', 'manager'].includes(role.value));
+
+  // Permissions come from the backend role matrix (see get_roles / set_role_permissions)
+  const can = (permission) => permissions.value.includes(permission);
+
+  const canManageUsers = computed(() => can('manage_users'));
+  const canManageRoles = computed(() => can('manage_roles'));
+  const canManageSettings = computed(() => can('view_settings'));
+  const canManageBackup = computed(() => can('view_backups'));
   
-  // Specific permissions
-  const canManageUsers = computed(() => ['super_admin', 'admin'].includes(role.value));
-  const canManageSettings = computed(() => ['super_admin', 'admin', 'demo'].includes(role.value));
-  const canManageBackup = computed(() => ['super_admin', 'admin', 'demo'].includes(role.value));
-  
-  const canBuy = computed(() => ['super_admin', 'admin', 'manager', 'buy_manager'].includes(role.value));
-  const canSell = computed(() => ['super_admin', 'admin', 'manager', 'sell_manager'].includes(role.value));
-  const canViewReports = computed(() => ['super_admin', 'admin', 'manager', 'report_checker', 'inspector', 'demo'].includes(role.value));
-  const canManageProducts = computed(() => ['super_admin', 'admin', 'manager', 'buy_manager', 'demo'].includes(role.value));
+  const canBuy = computed(() => can('view_purchases'));
+  const canSell = computed(() => can('view_orders'));
+  const canManageShifts = computed(() => can('manage_shifts'));
+  const canViewCustomers = computed(() => can('view_customers'));
+  const canViewSuppliers = computed(() => can('view_suppliers'));
+  const canManagePromotions = computed(() => can('manage_promotions'));
+  const canViewReports = computed(() => can('view_reports'));
+  const canManageProducts = computed(() => can('manage_products') || role.value === 'demo');
   const canViewStock = computed(() => !!role.value); // Everyone logged in
-  const canViewActivityLog = computed(() => ['super_admin', 'admin', 'manager', 'inspector', 'demo'].includes(role.value));
+  const canViewActivityLog = computed(() => can('view_activity_log'));
+  const canViewProfit = computed(() => can('view_profit'));
 
   const isDemo = computed(() => role.value === 'demo');
 
@@ -34,28 +49,66 @@ export const useAuthStore = defineStore('auth', () => {
     }
   }
 
+  function setSession(session) {
+    token.value = session?.token || null;
+    if (token.value) {
+      localStorage.setItem('session_token', token.value);
+    } else {
+      localStorage.removeItem('session_token');
+    }
+    locked.value = false;
+    permissions.value = session?.permissions || [];
+    if (session) {
+      localStorage.setItem('permissions', JSON.stringify(permissions.value));
+    } else {
+      localStorage.removeItem('permissions');
+    }
+    setUser(session?.user || null);
+  }
+
+  function lock() {
+    if (!token.value) return;
+    invoke('lock_session', { token: token.value }).catch(() => { });
+    locked.value = true;
+  }
+
   function logout() {
-    setUser(null);
+    if (token.value) {
+      invoke('logout', { token: token.value }).catch(() => { });
+    }
+    setSession(null);
   }
 
   return { 
     user, 
+    token,
+    locked,
     isAuthenticated, 
     role,
+    permissions,
+    can,
     isSuperAdmin,
     isAdmin,
     isManager,
     canManageUsers,
+    canManageRoles,
     canManageSettings,
     canManageBackup,
     canBuy,
     canSell,
+    canManageShifts,
+    canViewCustomers,
+    canViewSuppliers,
+    canManagePromotions,
     canViewReports,
     canManageProducts,
     canViewStock,
     canViewActivityLog,
+    canViewProfit,
     isDemo,
     setUser, 
+    setSession,
+    lock,
     logout 
   };
 });
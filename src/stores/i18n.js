@@ -48,6 +48,11 @@ export const useI18nStore = defineStore('i18n', {
         transaction: 'Transaction',
         buying: 'Buying',
         selling: 'Selling',
+        cash_drawer: 'Cash Drawer',
+        customers: 'Customers',
+        suppliers: 'Suppliers',
+        promotions: 'Promotions',
+        purchase_orders: 'Purchase Orders',
         expenses: 'Expenses',
         utilities: 'Utilities',
         reports: 'Reports',
@@ -90,6 +95,30 @@ export const useI18nStore = defineStore('i18n', {
         bank: 'Bank',
         mobile_banking: 'Mobile Banking',
         delivery_charge: 'Delivery Charge',
+        amount_paid: 'Amount Paid',
+        payments: 'Payments',
+        add_payment: 'Add payment',
+        change_due: 'Change Due',
+        tax: 'Tax',
+        tax_included: 'Tax (included)',
+        coupon_code: 'Coupon code',
+        apply: 'Apply',
+        remove: 'Remove',
+        only_in_stock: 'Only {n} in stock',
+        sell_anyway: 'Sell anyway',
+        approve: 'Approve',
+        due_on_account: 'Due on Account',
+        receivables: 'Receivables',
+        payables: 'Payables',
+        returns: 'Returns',
+        net_sales: 'Net Sales',
+        order_type: 'Order Type',
+        order_local: 'Walk-in',
+        order_online: 'Online (delivery)',
+        all_statuses: 'All statuses',
+        hold: 'Hold',
+        held_orders: 'Held',
+        redeem_points: 'Redeem Points',
         process_sale: 'Process Sale',
         update_sale: 'Update Sale',
 
@@ -308,6 +337,11 @@ export const useI18nStore = defineStore('i18n', {
         transaction: 'লেনদেন',
         buying: 'ক্রয়/কেনাকাটা',
         selling: 'বিক্রি',
+        cash_drawer: 'ক্যাশ ড্রয়ার',
+        customers: 'কাস্টমার তালিকা',
+        suppliers: 'সাপ্লায়ার তালিকা',
+        promotions: 'প্রমোশন',
+        purchase_orders: 'পারচেজ অর্ডার',
         expenses: 'খরচ',
         utilities: 'অন্যান্য সুবিধা',
         reports: 'রিপোর্ট',
@@ -350,6 +384,30 @@ export const useI18nStore = defineStore('i18n', {
         bank: 'ব্যাংক',
         mobile_banking: 'মোবাইল ব্যাংকিং',
         delivery_charge: 'ডেলিভারি চার্জ',
+        amount_paid: 'পরিশোধিত টাকা',
+        payments: 'পেমেন্ট',
+        add_payment: 'পেমেন্ট যোগ করুন',
+        change_due: 'ফেরত টাকা',
+        tax: 'কর',
+        tax_included: 'কর (অন্তর্ভুক্ত)',
+        coupon_code: 'কুপন কোড',
+        apply: 'প্রয়োগ',
+        remove: 'বাদ দিন',
+        only_in_stock: 'স্টকে আছে মাত্র {n}',
+        sell_anyway: 'তবুও বিক্রি করুন',
+        approve: 'অনুমোদন',
+        due_on_account: 'বাকি',
+        receivables: 'পাওনা',
+        payables: 'দেনা',
+        returns: 'ফেরত',
+        net_sales: 'নিট বিক্রয়',
+        order_type: 'অর্ডারের ধরন',
+        order_local: 'দোকানে',
+        order_online: 'অনলাইন (ডেলিভারি)',
+        all_statuses: 'সব স্ট্যাটাস',
+        hold: 'হোল্ড',
+        held_orders: 'হোল্ড করা',
+        redeem_points: 'পয়েন্ট ব্যবহার',
         process_sale: 'বিক্রি সম্পন্ন করুন',
         update_sale: 'আপডেট করুন',
 
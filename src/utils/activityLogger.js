@@ -1,16 +1,13 @@
-import { invoke } from '@tauri-apps/api/core';
-import { useAuthStore } from '../stores/auth';
+import { invoke } from './api';
 
 /**
  * Log an activity from the frontend.
  * Call this after any successful mutation (create, update, delete, etc.)
+ * The acting user is taken from the session on the backend.
  */
 export async function logActivity(action, entityType, entityId, description) {
   try {
-    const auth = useAuthStore();
     await invoke('log_activity', {
-      userId: auth.user?.id || null,
-      username: auth.user?.username || 'system',
       action,
       entityType,
       entityId: entityId ?? null,
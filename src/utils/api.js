@@ -0,0 +1,32 @@
+import { invoke as tauriInvoke } from '@tauri-apps/api/core';
+import { useAuthStore } from '../stores/auth';
+
+/**
+ * Wrapper around Tauri's invoke that attaches the current session token.
+ * Every backend command validates the token and the user's role.
+ */
+export async function invoke(cmd, args = {}) {
+  const auth = useAuthStore();
+  try {
+    return await tauriInvoke(cmd, { token: auth.token || '', ...args });
+  } catch (err) {
+    // Idle sessions are locked by the backend; show the PIN screen
+    if (String(err).startsWith('Session locked')) {
+      auth.locked = true;
+    }
+    throw err;
+  }
+}
+
+/**
+ * Some commands fail with a JSON error carrying a `code` (e.g. insufficient_stock)
+ * so the UI can react to it. Returns the parsed error, or null for plain messages.
+ */
+export function parseError(err) {
+  try {
+    const parsed = JSON.parse(String(err));
+    return parsed && typeof parsed === 'object' && parsed.code ? parsed : null;
+  } catch {
+    return null;
+  }
+}